@@ -0,0 +1,31 @@
+#ifndef CAMERA_GLSL
+#define CAMERA_GLSL
+
+#include "sampling.glsl"
+
+// Primary ray through the thin lens described by `lens` (x: aperture radius, y: focus distance).
+// `d` is the pixel position in normalized device coordinates, `Xi` picks the point on the lens.
+// An aperture radius of zero gives the pinhole ray. Orthographic projections give parallel rays from the near plane.
+void thinLensRay(mat4 viewInverse, mat4 projectionInverse, vec4 lens, vec2 d, vec2 Xi, out vec3 origin, out vec3 direction)
+{
+	if (projectionInverse[2][3] == 0.0f) {
+		// orthographic: parallel rays leaving the near plane
+		vec4 near = projectionInverse * vec4(d.x, d.y, 0, 1);
+		origin = (viewInverse * vec4(near.xyz / near.w, 1)).xyz;
+		direction = (viewInverse * vec4(0, 0, -1, 0)).xyz;
+		return;
+	}
+	vec4 target = projectionInverse * vec4(d.x, d.y, 1, 1);
+	vec3 dir = normalize(target.xyz);
+	vec3 lensPoint = vec3(0);
+	if (lens.x > 0.0f) {
+		// every ray through the pixel meets at the focal plane, z = -focus distance in view space
+		vec3 focusPoint = dir * (lens.y / -dir.z);
+		lensPoint = vec3(sampleConcentricDisk(Xi) * lens.x, 0);
+		dir = normalize(focusPoint - lensPoint);
+	}
+	origin = (viewInverse * vec4(lensPoint, 1)).xyz;
+	direction = (viewInverse * vec4(dir, 0)).xyz;
+}
+
+#endif
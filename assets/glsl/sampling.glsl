@@ -126,4 +126,22 @@ vec3 sampleCosineWeightedHemisphere(vec3 n, vec2 Xi)
     return alignToDirection(n, cosTheta, Xi.y * TWO_PI);
 }
 
+// Shirley's concentric mapping of the unit square onto the unit disk.
+vec2 sampleConcentricDisk(vec2 Xi)
+{
+    vec2 p = Xi * 2.0f - 1.0f;
+    if (p.x == 0.0f && p.y == 0.0f) {
+        return vec2(0.0f);
+    }
+    float r, theta;
+    if (abs(p.x) > abs(p.y)) {
+        r = p.x;
+        theta = (M_PI / 4.0f) * (p.y / p.x);
+    } else {
+        r = p.y;
+        theta = (M_PI / 2.0f) - (M_PI / 4.0f) * (p.x / p.y);
+    }
+    return r * vec2(cos(theta), sin(theta));
+}
+
 #endif
\ No newline at end of file
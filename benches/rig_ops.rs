@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use bolt::AppSettings;
 use bolt::SharedContext;
-use bolt::resource::skin::RigParser;
+use bolt::resource::skin::{GlobalTransforms, RigParser};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use ash::vk;
 use bolt::scene::*;
@@ -54,6 +54,16 @@ fn local_transforms_seq_multiple_rigs(rigs: &Vec<RigV1<Mat4, BoneV1<Mat4>>>) {
     }).collect();
 }
 
+// one linear pass per rig, parents before children
+fn global_transforms_seq_multiple_rigs(rigs: &mut Vec<GlobalTransforms>) {
+    rigs.iter_mut().for_each(|rig| rig.invalidate());
+    rigs.iter_mut().for_each(|rig| { rig.update(); });
+}
+
+fn global_transforms_par_multiple_rigs(rigs: &mut Vec<GlobalTransforms>) {
+    rigs.iter_mut().for_each(|rig| rig.invalidate());
+    GlobalTransforms::update_all(rigs);
+}
 
 // Learnings:
 // - parallelizing the computation of local transforms of a single rig is not worth it
@@ -77,6 +87,16 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("local_transforms_par_multiple_rigs", |b| b.iter(|| {
         local_transforms_par_multiple_rigs(&rigs)
     }));
+
+    let mut cached = rigs.iter().map(GlobalTransforms::new).collect::<Vec<_>>();
+
+    c.bench_function("global_transforms_seq_multiple_rigs", |b| b.iter(|| {
+        global_transforms_seq_multiple_rigs(&mut cached)
+    }));
+
+    c.bench_function("global_transforms_par_multiple_rigs", |b| b.iter(|| {
+        global_transforms_par_multiple_rigs(&mut cached)
+    }));
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -57,8 +57,10 @@ pub fn setup(app: &mut bolt::App) -> AppData {
             .vertex_type::<scene::ModelVertex>(),
     );
 
+    // the pipeline's winding assumes the flipped up vector, so keep it and only frame the scene
     let mut camera = scene::Camera::new(app.window.get_size());
     camera.look_at(Vec3::splat(3.0), vec3(0.0, 0.5, 0.0), -Vec3::Y);
+    camera.frame_bounds(&scene.bounds());
 
     let scene_data = SceneData {
         mvp: camera.perspective_matrix() * camera.view_matrix() * scene.meshes[0].transform,
@@ -128,7 +130,10 @@ pub fn render(app: &mut bolt::App, data: &mut AppData) -> Result<(), bolt::AppRe
             &[],
         );
     }
-    data.scene.vulkan_meshes.iter().for_each(|mesh| mesh.cmd_draw(cmd));
+    let frustum = camera.frustum();
+    data.scene.vulkan_meshes.iter().for_each(|mesh| {
+        mesh.cmd_draw_culled(cmd, &frustum);
+    });
     app.renderer.end_frame_default(image_aquired_semaphore, cmd)
 }
 
@@ -139,6 +144,7 @@ pub fn prepare() -> bolt::AppSettings {
         render: bolt::RendererSettings {
             samples: 8,
             clear_color: Vec4::splat(0.15),
+            pipeline_cache: Some(std::env::temp_dir().join("bolt_model_app_pipeline_cache.bin")),
             ..Default::default()
         },
         ..Default::default()
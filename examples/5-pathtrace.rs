@@ -21,6 +21,7 @@ struct SceneUniforms {
     model_view_projection: Mat4,
     frame: UVec3,
     padding: u32,
+    lens: Vec4,
 }
 
 impl SceneUniforms {
@@ -34,6 +35,7 @@ impl SceneUniforms {
             projection_inverse: camera.perspective_matrix().inverse(),
             model_view_projection: vp,
             frame,
+            lens: camera.lens().as_vec4(),
             ..Default::default()
         }
     }
@@ -55,6 +57,7 @@ pub struct AppData {
     pub scene_description: ray::SceneDescription,
     pub graphics_pipeline: ray::Pipeline,
     pub sbt: ray::ShaderBindingTable,
+    pub hot_reload: bolt::ShaderHotReload,
     pub accumulation_start_frame: u32,
     pub accum_target: bolt::Image2d,
     pub render_target: bolt::Image2d,
@@ -123,16 +126,20 @@ fn build_pipeline_sbt(
             .specialization(&[enable_sky as u32], 0)
             .name("AO_mat".to_string()),
     );
-    let sbt = ray::ShaderBindingTable::new(
+    let sbt = build_sbt(context, &pipeline);
+
+    (pipeline, sbt)
+}
+
+fn build_sbt(context: &Arc<bolt::Context>, pipeline: &ray::Pipeline) -> ray::ShaderBindingTable {
+    ray::ShaderBindingTable::new(
         context.clone(),
         pipeline.handle(),
         ray::ShaderBindingTableInfo::default()
             .raygen(0)
             .miss(1)
             .hitgroup(2),
-    );
-
-    (pipeline, sbt)
+    )
 }
 
 
@@ -186,14 +193,7 @@ pub fn setup(app: &mut bolt::App) -> AppData {
         let skymap = bolt::Texture2d::new(context.clone(), skymap_path.clone().to_path_buf());
         skydome = Some(skymap);
     }
-    let camera = match scene.camera {
-        Some(scene_camera) => {
-            let mut cam = scene_camera;
-            cam.set_window_size(app.window.get_size());
-            cam
-        }
-        None => scene::Camera::new(app.window.get_size())
-    };
+    let camera = scene.camera_or_framed(app.window.get_size());
 
     let scene_description = ray::SceneDescription::from_scene(context.clone(), &mut scene);
 
@@ -370,6 +370,7 @@ pub fn setup(app: &mut bolt::App) -> AppData {
         // pipeline_layout_compute,
         // collision_sbt,
         sbt,
+        hot_reload: bolt::ShaderHotReload::new(),
         accumulation_start_frame: 0,
         accum_target,
         render_target,
@@ -423,6 +424,12 @@ pub fn window_event(app: &mut bolt::App, data: &mut AppData, event: &WindowEvent
 }
 
 pub fn render(app: &mut bolt::App, data: &mut AppData) -> Result<(), bolt::AppRenderError> {
+    // rebuild the pipeline when a shader or one of its includes is saved
+    if !data.hot_reload.update(&app.renderer.context, &mut [&mut data.graphics_pipeline]).is_empty() {
+        data.sbt = build_sbt(&app.renderer.context, &data.graphics_pipeline);
+        data.accumulation_start_frame = app.elapsed_ticks as u32;
+    }
+
     let (semaphore, frame_index) = app.renderer.acquire_next_image()?;
 
     let ref mut frame_ubo = data.per_frame[frame_index].ubo;
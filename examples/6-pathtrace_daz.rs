@@ -16,6 +16,8 @@ struct SceneUniforms {
     projection_inverse: Mat4,
     model_view_projection: Mat4,
     frame: UVec3,
+    padding: u32,
+    lens: Vec4,
 }
 
 impl SceneUniforms {
@@ -29,6 +31,8 @@ impl SceneUniforms {
             projection_inverse: camera.perspective_matrix().inverse(),
             model_view_projection: vp,
             frame,
+            padding: 0,
+            lens: camera.lens().as_vec4(),
         }
     }
 }
@@ -120,21 +124,18 @@ fn build_pipeline_sbt(
 pub fn setup(app: &mut bolt::App) -> AppData {
     let context = &app.renderer.context;
     let index = std::env::args().position(|arg| arg == "--model").unwrap();
-    let scene = scene::load_scene(
-        context.clone(),
-        &bolt::util::find_asset(&std::env::args().nth(index + 1).expect("no file given"))
-            .unwrap(),
-    );
-    let scene_description = ray::SceneDescription::from_scene(context.clone(), &scene);
-
-    let camera = match scene.camera {
-        Some(scene_camera) => {
-            let mut cam = scene_camera;
-            cam.set_window_size(app.window.get_size());
-            cam
+    let model = bolt::util::find_asset(&std::env::args().nth(index + 1).expect("no file given")).unwrap();
+    // `--cache <path>` keeps the processed scene somewhere else than next to the model
+    let scene = match std::env::args().position(|arg| arg == "--cache") {
+        Some(i) => {
+            let cache_path = std::path::PathBuf::from(std::env::args().nth(i + 1).expect("no cache path given"));
+            scene::load_scene_cached(context.clone(), &model, &cache_path)
         }
-        None => scene::Camera::new(app.window.get_size()),
+        None => scene::load_scene(context.clone(), &model),
     };
+    let scene_description = ray::SceneDescription::from_scene(context.clone(), &scene);
+
+    let camera = scene.camera_or_framed(app.window.get_size());
 
     let mut per_frame = Vec::<PerFrameData>::new();
 
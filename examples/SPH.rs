@@ -107,16 +107,17 @@ impl<'b: 'a, 'a> AppDataBuilder<'a> {
         self
     }
 
-    fn pipeline_layout<F: FnOnce(bolt::PipelineLayoutInfo) -> bolt::PipelineLayoutInfo>(mut self, extensions: Option<F>) -> Self {
-
-        let layout = bolt::PipelineLayoutInfo::default().desc_set_layouts(&[
-            self.desc_set_layout.as_ref().expect("specify a descriptor set layout before building the pipeline layout").handle(),
-            self.pass_layout.as_ref().expect("specify a descriptor set layout for the render pass before building the pipeline layout").handle(),
-            ]);
-        let layout =  match extensions {
-            Some(f) => f(layout),
-            None => layout,
-        };
+    /// Descriptor set and pipeline layouts shared by the particle shaders, read from their spir-v.
+    fn reflected_layouts(mut self, shaders: &[(std::path::PathBuf, vk::ShaderStageFlags)]) -> Self {
+        let reflection = bolt::reflect_shaders(shaders, &bolt::ShaderOptions::default()).expect("failed to reflect the particle shaders");
+        let mut set_infos = reflection.descriptor_set_layout_infos(0).into_iter();
+        self = self
+            .descriptor_set_layout(set_infos.next().expect("the particle shaders bind no scene set"))
+            .pass_layout(set_infos.next().expect("the particle shaders bind no particle set"));
+        let layout = reflection.pipeline_layout_info(&[
+            self.desc_set_layout.as_ref().unwrap().handle(),
+            self.pass_layout.as_ref().unwrap().handle(),
+        ]);
         self.pipeline_layout = Some(bolt::PipelineLayout::new(
             self.app.renderer.context.clone(),
             layout,
@@ -149,34 +150,22 @@ impl<'b: 'a, 'a> AppDataBuilder<'a> {
 
     fn hash_pipeline(mut self) -> Self {
 
+        let hash_shader = bolt::util::find_asset("glsl/hash.comp").unwrap();
+        let reflection = bolt::reflect_shaders(&[(hash_shader.clone(), vk::ShaderStageFlags::COMPUTE)], &bolt::ShaderOptions::default())
+            .expect("failed to reflect the hash shader");
         let hash_layout = bolt::DescriptorSetLayout::new(
             self.app.renderer.context.clone(),
-            bolt::DescriptorSetLayoutInfo::default()
-            .binding(0, // spatial data to hash
-                vk::DescriptorType::STORAGE_BUFFER,
-                vk::ShaderStageFlags::COMPUTE
-            )
-            .binding(1, // hash output
-                vk::DescriptorType::STORAGE_BUFFER,
-                vk::ShaderStageFlags::COMPUTE
-            )
+            reflection.descriptor_set_layout_infos(0).remove(0),
         );
 
         let hash_pipeline_layout = bolt::PipelineLayout::new(
             self.app.renderer.context.clone(),
-            bolt::PipelineLayoutInfo::default()
-            .desc_set_layouts(&[hash_layout.handle()])
-            .push_constant_range(
-                vk::PushConstantRange::builder()
-                .stage_flags(vk::ShaderStageFlags::COMPUTE)
-                .size(size_of::<GridHashConstants>() as u32)
-                .build()
-            )
+            reflection.pipeline_layout_info(&[hash_layout.handle()]),
         );
 
         let pipeline_info = bolt::ComputePipelineInfo::default()
             .layout(hash_pipeline_layout.handle())
-            .comp(bolt::util::find_asset("glsl/hash.comp").unwrap());
+            .comp(hash_shader);
 
 
         self.hash_pipeline = Some(bolt::ComputePipeline::new(
@@ -378,46 +367,12 @@ pub fn setup(app: &mut bolt::App) -> AppData {
 
     AppDataBuilder::new(app)
     .scene(scene)
-    .descriptor_set_layout(
-        bolt::DescriptorSetLayoutInfo::default().binding(
-            0,
-            vk::DescriptorType::UNIFORM_BUFFER,
-            vk::ShaderStageFlags::ALL,
-        )
-    )
-    .pass_layout(
-        bolt::DescriptorSetLayoutInfo::default()
-        .binding(0,
-            vk::DescriptorType::STORAGE_BUFFER,
-            vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
-        )
-        .binding(1,
-            vk::DescriptorType::STORAGE_BUFFER,
-            vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
-        )
-        .binding(2,
-            vk::DescriptorType::STORAGE_BUFFER,
-            vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
-        )
-        .binding(3,
-            vk::DescriptorType::STORAGE_BUFFER,
-            vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
-        )
-        .binding(4,
-            vk::DescriptorType::STORAGE_BUFFER,
-            vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
-        )
-        .binding(5,
-            vk::DescriptorType::STORAGE_BUFFER,
-            vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
-        )
-    )    
-    .pipeline_layout(Some(|info: bolt::PipelineLayoutInfo| {
-        info.push_constant_range(vk::PushConstantRange::builder()
-        .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE)
-        .size(size_of::<Constants>() as u32)
-        .build())
-    }))
+    .reflected_layouts(&[
+        (bolt::util::find_asset("glsl/SPH/particle.vert").unwrap(), vk::ShaderStageFlags::VERTEX),
+        (bolt::util::find_asset("glsl/SPH/particle.frag").unwrap(), vk::ShaderStageFlags::FRAGMENT),
+        (bolt::util::find_asset("glsl/SPH/pressure.comp").unwrap(), vk::ShaderStageFlags::COMPUTE),
+        (bolt::util::find_asset("glsl/SPH/sph.comp").unwrap(), vk::ShaderStageFlags::COMPUTE),
+    ])
     .graphics_pipeline(|pipeline_info| {
         pipeline_info
             .vert(bolt::util::find_asset("glsl/SPH/particle.vert").unwrap())
@@ -4,6 +4,7 @@ use std::env;
 use bolt::prelude::*;
 use bolt::scene;
 use bolt::scene::CameraManip;
+use bolt::resource::skin::{GlobalTransforms, GpuSkinning, SkinningMode};
 use bolt::scene::Scene;
 use bolt::util::BasicVertex;
 use bolt::util::colored_cube_vertices;
@@ -30,13 +31,14 @@ pub struct PerFrameData {
 
 pub struct AppData {
     pub scene: scene::Scene,
+    pub rig_transforms: Vec<GlobalTransforms>,
     pub joint_geometry: bolt::Buffer,
     pub desc_set_layout: bolt::DescriptorSetLayout,
     pub pipeline_layout: bolt::PipelineLayout,
     pub pass_layout: bolt::DescriptorSetLayout,
     pub graphics_pipeline: bolt::Pipeline,
     pub debug_pipeline: bolt::Pipeline,
-    pub compute_pipeline: bolt::ComputePipeline,
+    pub skinning: GpuSkinning,
     pub per_frame: Vec<PerFrameData>,
     pub manip: scene::CameraManip,
 }
@@ -47,7 +49,7 @@ pub struct AppDataBuilder<'a> {
     // all fields of AppData but Optional
     pub scene: Option<Scene>,
     pub graphics_pipeline: Option<bolt::Pipeline>,
-    pub compute_pipeline: Option<bolt::ComputePipeline>,
+    pub skinning: Option<GpuSkinning>,
     pub debug_pipeline: Option<bolt::Pipeline>,
     pub desc_set_layout: Option<bolt::DescriptorSetLayout>,
     pub pass_layout: Option<bolt::DescriptorSetLayout>,
@@ -62,7 +64,7 @@ impl<'b: 'a, 'a> AppDataBuilder<'a> {
             app: app,
             scene: None,
             graphics_pipeline: None,
-            compute_pipeline: None,
+            skinning: None,
             debug_pipeline: None,
             desc_set_layout: None,
             pass_layout: None,
@@ -112,14 +114,8 @@ impl<'b: 'a, 'a> AppDataBuilder<'a> {
         self
     }
 
-    fn compute_pipeline<F: FnOnce(bolt::ComputePipelineInfo) -> bolt::ComputePipelineInfo>(mut self, pipeline_opts: F) -> Self {
-        self.compute_pipeline = Some(bolt::ComputePipeline::new(
-            self.app.renderer.context.clone(),
-            pipeline_opts(
-                bolt::ComputePipelineInfo::default()
-                .layout(self.pipeline_layout.as_ref().expect("specify a pipeline layout before building the pipeline").handle())
-            )
-        ));
+    fn skinning(mut self, mode: SkinningMode) -> Self {
+        self.skinning = Some(GpuSkinning::new(self.app.renderer.context.clone(), mode));
         self
     }
 
@@ -163,11 +159,15 @@ impl<'b: 'a, 'a> AppDataBuilder<'a> {
             &cube_vertices(0.8),
         );
 
+        let scene = self.scene.expect("specify a scene before building the app data");
+        let rig_transforms = scene.rigs.iter().map(GlobalTransforms::new).collect();
+
         AppData {
-            scene: self.scene.expect("specify a scene before building the app data"),
+            scene,
+            rig_transforms,
             joint_geometry,
             graphics_pipeline: self.graphics_pipeline.expect("specify a graphics pipeline before building the app data"),
-            compute_pipeline: self.compute_pipeline.expect("specify a compute pipeline before building the app data"),
+            skinning: self.skinning.expect("specify a skinning mode before building the app data"),
             debug_pipeline: self.debug_pipeline.expect("specify a debug pipeline before building the app data"),
             desc_set_layout: self.desc_set_layout.expect("specify a descriptor set layout before building the app data"),
             pass_layout: self.pass_layout.expect("specify a descriptor set layout for the render pass before building the app data"),
@@ -234,9 +234,6 @@ pub fn setup(app: &mut bolt::App) -> AppData {
                 vk::DescriptorType::STORAGE_BUFFER,
                 vk::ShaderStageFlags::ALL_GRAPHICS ^ vk::ShaderStageFlags::COMPUTE
             )
-            .binding(2, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE) // transforms
-            .binding(3, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE) // joints
-            .binding(4, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE) // inverse bind matrices
             .binding(5, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::ALL_GRAPHICS)  
         )
         .pipeline_layout(Some(|layout_info| { layout_info }))
@@ -249,9 +246,10 @@ pub fn setup(app: &mut bolt::App) -> AppData {
             .vertex_type::<scene::ModelVertex>()
             .polygon_mode(vk::PolygonMode::FILL)
         })
-        .compute_pipeline(|layout| {
-            layout
-                .comp(bolt::util::find_asset("glsl/skinning.comp").unwrap())
+        .skinning(if std::env::args().any(|arg| arg == "--dq") {
+            SkinningMode::DualQuaternion
+        } else {
+            SkinningMode::Linear
         })
         .debug_pipeline(|layout| {
             layout
@@ -294,13 +292,6 @@ pub fn window_event(_: &mut bolt::App, data: &mut AppData, event: &winit::event:
     data.manip.update(&event);
 }
 
-#[repr(C)]
-#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Constants {
-    pub weights_count: u32,
-    pub vertex_count: u32,
-}
-
 pub fn render(app: &mut bolt::App, data: &mut AppData) -> Result<(), bolt::AppRenderError> {
     let (image_aquired_semaphore, cmd) = app.renderer.begin_frame_default()?;
     let ref camera = data.manip.camera;
@@ -325,81 +316,24 @@ pub fn render(app: &mut bolt::App, data: &mut AppData) -> Result<(), bolt::AppRe
     data.scene.skins.iter_mut()
     .zip(data.scene.vulkan_skins.iter())
     .zip(data.scene.rigs.iter())
-    .for_each(|((skin, vk_skin), rig)| {
-        skin.transforms_from(rig);
-        vk_skin.update(skin);
+    .zip(data.rig_transforms.iter_mut())
+    .for_each(|(((skin, vk_skin), rig), globals)| {
+        if skin.transforms_from(rig, globals) {
+            vk_skin.update(skin);
+        }
     });
 
     let pass_layout = data.pass_layout.get_or_create(
         bolt::DescriptorSetInfo::default()
             .buffer(0, data.scene.vulkan_meshes[0].vertex_buffer.get_descriptor_info())
             // .buffer(1, data.scene.vulkan_meshes[0].index_buffer.unwrap().get_descriptor_info())
-            .buffer(2, data.scene.vulkan_skins[0].transforms.get_descriptor_info())
-            .buffer(3, data.scene.vulkan_skins[0].joints.get_descriptor_info())
-            .buffer(4, data.scene.vulkan_skins[0].inverse_bind_matrices.get_descriptor_info())
             .buffer(5, data.joint_geometry.get_descriptor_info())
     );
 
-    let vertex_count = data.scene.vulkan_meshes[0].vertex_buffer.get_element_count() as u32;
-    let weights_count = data.scene.vulkan_skins[0].joints.get_element_count() as u32;
-    let joint_count = data.scene.vulkan_skins[0].transforms.get_element_count() as u32;
-
-    let constants = Constants {
-        weights_count,
-        vertex_count,
-    };
-
     let descriptor_sets = [data.per_frame[app.renderer.active_frame_index].desc_set.handle(), pass_layout.handle()];
     let device = app.renderer.context.device();
 
-
-    unsafe {
-        device.cmd_bind_descriptor_sets(
-            cmd,
-            vk::PipelineBindPoint::COMPUTE,
-            pipeline_layout,
-            0,
-            &descriptor_sets,
-            &[],
-        );
-        device.cmd_bind_pipeline(
-            cmd,
-            vk::PipelineBindPoint::COMPUTE,
-            data.compute_pipeline.handles().unwrap()[0],
-        );
-        device.cmd_push_constants(
-            cmd,
-            data.pipeline_layout.handle(),
-            vk::ShaderStageFlags::COMPUTE,
-            0,
-            &bytemuck::bytes_of(&constants),
-        );
-        device.cmd_dispatch(cmd, weights_count, 1, 1);
-    }
-
-    unsafe {
-        // memory barrier
-        let buffer_barrier = vk::BufferMemoryBarrier::builder()
-            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
-            .dst_access_mask(vk::AccessFlags::SHADER_READ)
-            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-            .buffer(data.scene.vulkan_meshes[0].vertex_buffer.handle())
-            .offset(0)
-            .size(vk::WHOLE_SIZE)
-            .build();
-
-        device.cmd_pipeline_barrier(
-            cmd,
-            vk::PipelineStageFlags::COMPUTE_SHADER,
-            vk::PipelineStageFlags::VERTEX_SHADER,
-            vk::DependencyFlags::default(),
-            &[],
-            &[buffer_barrier],
-            &[],
-        );
-    }
-
+    data.skinning.dispatch_scene(cmd, device, &data.scene);
 
     unsafe {
         device.cmd_set_scissor(cmd, 0, &[app.window.get_rect()]);
@@ -4,27 +4,40 @@ use std::path::PathBuf;
 
 use crate::context::Context;
 //use crate::shader::Shader;
-use crate::pipeline::Shader;
-use crate::Resource;
-use std::ffi::CString;
+use crate::pipeline::{dependencies, Shader, ShaderError, ShaderOptions, ShaderOptionsBuilder};
+use crate::{reflect_shaders, Reload, Resource, ShaderReflection};
 
 pub struct ComputePipeline {
     context: Arc<Context>,
     shaders: Vec<Shader>,
     info: ComputePipelineInfo,
     pipelines: Vec<vk::Pipeline>,
-    create_infos: Vec<vk::ComputePipelineCreateInfo>,
+    dependencies: Vec<PathBuf>,
 }
 
 impl ComputePipeline {
     pub fn new(context: Arc<Context>, info: ComputePipelineInfo) -> Self {
+        let (shaders, pipelines) = Self::create(&context, &info)
+            .unwrap_or_else(|e| panic!("Failed to create compute pipeline: {}", e));
+        let dependencies = dependencies(&shaders);
 
-        let shader_entry_name = CString::new("main").unwrap();
+        ComputePipeline {
+            shaders,
+            context,
+            info,
+            pipelines,
+            dependencies,
+        }
+    }
+
+    fn create(context: &Arc<Context>, info: &ComputePipelineInfo) -> Result<(Vec<Shader>, Vec<vk::Pipeline>), ShaderError> {
+
+        let shader_entry_name = info.shader_options.entry_point_name()?;
 
         let shaders = info.shaders.iter().cloned().map(|(path, stage_flags)| {
-            Shader::new(context.clone(), path, stage_flags)
+            Shader::with_options(context.clone(), path, stage_flags, &info.shader_options)
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>, _>>()?;
 
         let create_infos: Vec<vk::ComputePipelineCreateInfo> = shaders.iter().map(| shader | {
             let shader_stage_create_info = if info.specialization_entries.is_empty() {
@@ -48,17 +61,29 @@ impl ComputePipeline {
         let pipelines = unsafe {
             context
                 .device()
-                .create_compute_pipelines(vk::PipelineCache::null(), &create_infos, None)
-                .expect("Failed to create compute pipeline")
+                .create_compute_pipelines(context.pipeline_cache(), &create_infos, None)
+                .map_err(|(_, e)| ShaderError::Vulkan(e))?
         };
-    
-        ComputePipeline {
-            shaders,
-            create_infos,
-            context,
-            info,
-            pipelines,
+        Ok((shaders, pipelines))
+    }
+}
+
+impl Reload for ComputePipeline {
+    fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    fn reload(&mut self) -> Result<(), ShaderError> {
+        let (shaders, pipelines) = Self::create(&self.context, &self.info)?;
+        unsafe {
+            self.pipelines.iter().for_each(|&pipeline| {
+                self.context.device().destroy_pipeline(pipeline, None);
+            });
         }
+        self.dependencies = dependencies(&shaders);
+        self.shaders = shaders;
+        self.pipelines = pipelines;
+        Ok(())
     }
 }
 
@@ -86,6 +111,7 @@ pub struct ComputePipelineInfo {
     pub shaders: Vec<(PathBuf, vk::ShaderStageFlags)>,
     pub specialization_data: Vec<u8>,
     pub specialization_entries: Vec<vk::SpecializationMapEntry>,
+    pub shader_options: ShaderOptions,
 }
 
 impl Default for ComputePipelineInfo {
@@ -95,6 +121,7 @@ impl Default for ComputePipelineInfo {
             shaders: vec![], // ("assets/glsl/id.comp".to_string().into(), vk::ShaderStageFlags::COMPUTE)
             specialization_data: Vec::new(),
             specialization_entries: Vec::new(),
+            shader_options: ShaderOptions::default(),
         }
     }
 }
@@ -115,6 +142,11 @@ impl ComputePipelineInfo {
         self
     }
 
+    /// Bindings and push constants of all stages, to build or check the pipeline layout.
+    pub fn reflect(&self) -> Result<ShaderReflection, ShaderError> {
+        reflect_shaders(&self.shaders, &self.shader_options)
+    }
+
     pub fn specialization<T>(mut self, data: &T, constant_id: u32) -> Self {
         let slice = unsafe {
             std::slice::from_raw_parts(data as *const T as *const u8, std::mem::size_of_val(data))
@@ -129,4 +161,10 @@ impl ComputePipelineInfo {
         );
         self
     }
-}
\ No newline at end of file
+}
+
+impl ShaderOptionsBuilder for ComputePipelineInfo {
+    fn shader_options_mut(&mut self) -> &mut ShaderOptions {
+        &mut self.shader_options
+    }
+}
@@ -8,6 +8,7 @@ use ash::{
 use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
 use std::borrow::Cow;
 use std::mem::ManuallyDrop;
+use std::path::PathBuf;
 use std::ffi::{CStr, CString};
 use std::{
     collections::{HashSet},
@@ -544,10 +545,12 @@ pub struct Context {
     shared_context: Arc<SharedContext>,
     frame_command_pools: Vec<CommandPool>,
     transient_command_pool: vk::CommandPool,
+    pipeline_cache: PipelineCache,
 }
 
 impl Context {
-    pub fn new(shared_context: Arc<SharedContext>, swapchain_image_count: usize) -> Self {
+    /// `pipeline_cache` is where the pipeline cache is loaded from and saved to, None keeps it in memory.
+    pub fn new(shared_context: Arc<SharedContext>, swapchain_image_count: usize, pipeline_cache: Option<PathBuf>) -> Self {
         unsafe {
             let mut frame_command_pools = Vec::<CommandPool>::new();
             let graphics_index = shared_context.queue_family_indices.graphics;
@@ -562,10 +565,12 @@ impl Context {
                 .device()
                 .create_command_pool(&pool_create_info, None)
                 .unwrap();
+            let pipeline_cache = PipelineCache::new(shared_context.clone(), pipeline_cache);
             Context {
                 shared_context,
                 frame_command_pools,
                 transient_command_pool,
+                pipeline_cache,
             }
         }
     }
@@ -622,6 +627,16 @@ impl Context {
         &self.shared_context
     }
 
+    /// Shared by every pipeline created with this context.
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache.handle()
+    }
+
+    /// Writes the pipeline cache now instead of when the context is dropped.
+    pub fn save_pipeline_cache(&self) -> Result<(), PipelineCacheError> {
+        self.pipeline_cache.save()
+    }
+
     pub fn begin_single_time_cmd(&self) -> vk::CommandBuffer {
         let create_info = vk::CommandBufferAllocateInfo::builder()
             .command_buffer_count(1)
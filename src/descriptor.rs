@@ -369,6 +369,10 @@ impl DescriptorSetLayout {
         }
     }
 
+    pub fn info(&self) -> &DescriptorSetLayoutInfo {
+        &self.info
+    }
+
     pub fn get_descriptor_type(&self, binding: u32) -> vk::DescriptorType {
         println!("looking up type of {:?} -> {:?}", binding, self.info.bindings[&binding].0);
         self.info.bindings[&binding].0
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Context, ShaderError};
+
+/// Something built from shader files that can be rebuilt in place.
+pub trait Reload {
+    /// Shader sources and everything they include.
+    fn dependencies(&self) -> &[PathBuf];
+    /// Rebuilds from the files on disk. On error the old pipeline is kept.
+    fn reload(&mut self) -> Result<(), ShaderError>;
+}
+
+/// Polls modification times, at most once every `interval`.
+pub struct FileWatcher {
+    pub interval: Duration,
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    last_poll: Option<Instant>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(interval: Duration) -> Self {
+        FileWatcher {
+            interval,
+            files: HashMap::new(),
+            last_poll: None,
+        }
+    }
+
+    /// Starts watching `path`, a change is anything after this call.
+    pub fn watch(&mut self, path: &Path) {
+        if !self.files.contains_key(path) {
+            self.files.insert(path.to_path_buf(), modified(path));
+        }
+    }
+
+    pub fn is_watched(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Files that were modified, created or removed since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        if self.last_poll.is_some_and(|last| now - last < self.interval) {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+        let mut changed = Vec::new();
+        for (path, time) in self.files.iter_mut() {
+            let current = modified(path);
+            if current != *time {
+                *time = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+/// Indices of the pipelines that depend on any of the `changed` files.
+pub fn affected_pipelines(changed: &[PathBuf], pipelines: &[&mut dyn Reload]) -> Vec<usize> {
+    pipelines.iter().enumerate()
+        .filter(|(_, pipeline)| pipeline.dependencies().iter().any(|path| changed.contains(path)))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Rebuilds pipelines when one of their shaders, or a file they include, changes on disk.
+/// Call `update` between frames, before recording any command buffers.
+pub struct ShaderHotReload {
+    pub watcher: FileWatcher,
+}
+
+impl Default for ShaderHotReload {
+    fn default() -> Self {
+        ShaderHotReload {
+            watcher: FileWatcher::new(Duration::from_millis(500)),
+        }
+    }
+}
+
+impl ShaderHotReload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the indices of the pipelines that were rebuilt, ray tracing pipelines need a new
+    /// shader binding table afterwards. Compile errors are logged and the old pipeline stays in use.
+    pub fn update(&mut self, context: &Context, pipelines: &mut [&mut dyn Reload]) -> Vec<usize> {
+        for pipeline in pipelines.iter() {
+            for path in pipeline.dependencies() {
+                self.watcher.watch(path);
+            }
+        }
+        let changed = self.watcher.poll();
+        if changed.is_empty() {
+            return Vec::new();
+        }
+        let affected = affected_pipelines(&changed, pipelines);
+        if affected.is_empty() {
+            return Vec::new();
+        }
+
+        // frames in flight may still use the old pipelines
+        unsafe {
+            context.device().device_wait_idle().unwrap();
+        }
+        let mut reloaded = Vec::new();
+        for i in affected {
+            match pipelines[i].reload() {
+                Ok(()) => {
+                    log::info!("Reloaded pipeline {} after changes to {:?}", i, changed);
+                    reloaded.push(i);
+                }
+                Err(e) => log::warn!("Failed to reload pipeline {}: {}", i, e),
+            }
+        }
+        reloaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakePipeline {
+        dependencies: Vec<PathBuf>,
+    }
+
+    impl Reload for FakePipeline {
+        fn dependencies(&self) -> &[PathBuf] {
+            &self.dependencies
+        }
+
+        fn reload(&mut self) -> Result<(), ShaderError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn watches_includes() {
+        let dir = std::env::temp_dir().join("bolt_hot_reload");
+        fs::create_dir_all(&dir).unwrap();
+        let (source, include) = (dir.join("pass.frag"), dir.join("sampling.glsl"));
+        fs::write(&source, "#include \"sampling.glsl\"").unwrap();
+        fs::write(&include, "float pi = 3.14;").unwrap();
+
+        let mut watcher = FileWatcher::new(Duration::ZERO);
+        watcher.watch(&source);
+        watcher.watch(&include);
+        assert!(watcher.poll().is_empty());
+
+        let file = fs::File::options().write(true).open(&include).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        let changed = watcher.poll();
+        assert_eq!(changed, vec![include.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        let mut a = FakePipeline { dependencies: vec![source.clone(), include.clone()] };
+        let mut b = FakePipeline { dependencies: vec![dir.join("other.comp")] };
+        let pipelines: [&mut dyn Reload; 2] = [&mut a, &mut b];
+        assert_eq!(affected_pipelines(&changed, &pipelines), vec![0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -12,7 +12,9 @@ use std::time::{Duration, SystemTime};
 mod buffer;
 mod context;
 mod descriptor;
+pub mod hot_reload;
 pub mod pipeline;
+mod pipeline_cache;
 mod pools;
 pub mod prelude;
 mod renderer;
@@ -31,11 +33,15 @@ pub mod compute_pass;
 pub mod sort;
 pub mod ui;
 pub mod mesh_pipeline;
+pub mod render_graph;
+mod reflect;
 
 pub use crate::buffer::*;
 pub use crate::context::*;
 pub use crate::descriptor::*;
+pub use crate::hot_reload::*;
 pub use crate::pipeline::*;
+pub use crate::pipeline_cache::*;
 pub use crate::pools::*;
 pub use crate::renderer::*;
 pub use crate::renderpass::*;
@@ -46,6 +52,8 @@ pub use crate::compute_pipeline::*;
 pub use crate::sort::*;
 pub use crate::compute_pass::*;
 pub use crate::mesh_pipeline::*;
+pub use crate::render_graph::*;
+pub use crate::reflect::*;
 pub use ash;
 pub use glam;
 pub use winit;
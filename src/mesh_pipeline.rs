@@ -129,7 +129,7 @@ impl MeshPipeline {
         let graphics_pipelines = unsafe {
             context
                 .device()
-                .create_graphics_pipelines(vk::PipelineCache::null(), &create_infos, None)
+                .create_graphics_pipelines(context.pipeline_cache(), &create_infos, None)
                 .expect("Unable to create graphics pipeline")
         };
 
@@ -1,15 +1,29 @@
-use crate::{Context, RenderPass, Resource, TransientRenderPassInfo, Vertex};
+use crate::{reflect_shaders, Context, ReflectError, Reload, RenderPass, Resource, ShaderReflection, TransientRenderPassInfo, Vertex};
 use ash::vk;
-use shaderc::{CompileOptions, Compiler, IncludeType, ResolvedInclude, ShaderKind};
+use shaderc::{CompileOptions, Compiler, IncludeType, OptimizationLevel, ResolvedInclude, ShaderKind};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::error::Error;
 use std::ffi::CString;
+use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 use std::result::Result;
 use std::string::String;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
 
-const STORE_SPIRV: bool = false;
-const LOAD_SPIRV: bool = false;
+static SPIRV_CACHE: AtomicBool = AtomicBool::new(false);
+
+/// Keeps compiled spir-v next to the glsl, with a `.d` listing of the files it includes, and loads
+/// it in later runs while it's newer than all of them. Only shaders with the default options are
+/// stored. Off by default.
+pub fn set_spirv_cache(enabled: bool) {
+    SPIRV_CACHE.store(enabled, Ordering::Relaxed);
+}
 
 pub struct Shader {
     context: Arc<Context>,
@@ -17,26 +31,244 @@ pub struct Shader {
     pub stage_flags: vk::ShaderStageFlags,
     pub path: PathBuf,
     text: Option<String>,
+    includes: Vec<PathBuf>,
+    reflection: ShaderReflection,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, std::io::Error),
+    Compile(String),
+    Reflect(ReflectError),
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // shaderc's messages are multi line and unreadable through Debug
+            ShaderError::Compile(message) => write!(f, "Compile({})", message),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderOptimization {
+    None,
+    Size,
+    Performance,
+}
+
+/// Compiler settings shared by the shaders of a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderOptions {
+    pub defines: Vec<(String, Option<String>)>,
+    pub optimization: ShaderOptimization,
+    pub entry_point: String,
+    /// Searched after the including file's directory for `#include "file"`, and first for `#include <file>`.
+    pub include_dirs: Vec<PathBuf>,
+    pub debug_info: bool,
+}
+
+impl Default for ShaderOptions {
+    fn default() -> Self {
+        ShaderOptions {
+            defines: Vec::new(),
+            optimization: ShaderOptimization::None,
+            entry_point: "main".to_string(),
+            include_dirs: Vec::new(),
+            debug_info: true,
+        }
+    }
+}
+
+impl ShaderOptions {
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines.push((name.to_string(), value.map(str::to_string)));
+        self
+    }
+
+    pub fn optimization(mut self, optimization: ShaderOptimization) -> Self {
+        self.optimization = optimization;
+        self
+    }
+
+    pub fn entry_point(mut self, entry_point: &str) -> Self {
+        self.entry_point = entry_point.to_string();
+        self
+    }
+
+    pub fn include_dir(mut self, dir: PathBuf) -> Self {
+        self.include_dirs.push(dir);
+        self
+    }
+
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    pub(crate) fn entry_point_name(&self) -> Result<CString, ShaderError> {
+        CString::new(self.entry_point.as_str())
+            .map_err(|_| ShaderError::Compile(format!("Invalid entry point {:?}", self.entry_point)))
+    }
+
+    fn compile_options<'a>(&self) -> CompileOptions<'a> {
+        let mut options = CompileOptions::new().unwrap();
+        if self.debug_info {
+            options.set_generate_debug_info();
+        }
+        options.set_optimization_level(match self.optimization {
+            ShaderOptimization::None => OptimizationLevel::Zero,
+            ShaderOptimization::Size => OptimizationLevel::Size,
+            ShaderOptimization::Performance => OptimizationLevel::Performance,
+        });
+        options.set_target_spirv(shaderc::SpirvVersion::V1_6);
+        options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_2 as u32);
+        for (name, value) in &self.defines {
+            options.add_macro_definition(name, value.as_deref());
+        }
+        options
+    }
+}
+
+/// Shader option builders of the pipeline infos, chained like their other settings.
+pub trait ShaderOptionsBuilder: Sized {
+    fn shader_options_mut(&mut self) -> &mut ShaderOptions;
+
+    fn shader_options(mut self, options: ShaderOptions) -> Self {
+        *self.shader_options_mut() = options;
+        self
+    }
+
+    fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        let options = self.shader_options_mut();
+        *options = std::mem::take(options).define(name, value);
+        self
+    }
+
+    fn optimization(mut self, optimization: ShaderOptimization) -> Self {
+        let options = self.shader_options_mut();
+        *options = std::mem::take(options).optimization(optimization);
+        self
+    }
+
+    fn entry_point(mut self, entry_point: &str) -> Self {
+        let options = self.shader_options_mut();
+        *options = std::mem::take(options).entry_point(entry_point);
+        self
+    }
+
+    fn include_dir(mut self, dir: PathBuf) -> Self {
+        let options = self.shader_options_mut();
+        *options = std::mem::take(options).include_dir(dir);
+        self
+    }
+
+    fn debug_info(mut self, debug_info: bool) -> Self {
+        let options = self.shader_options_mut();
+        *options = std::mem::take(options).debug_info(debug_info);
+        self
+    }
+}
+
+/// A compiled shader, the includes carry a hash of their content at compile time.
+#[derive(Clone)]
+struct ShaderVariant {
+    /// `variant_slot` of the shader, a recompile replaces every older variant in the same slot.
+    slot: u64,
+    code: Arc<Vec<u32>>,
+    includes: Vec<(PathBuf, u64)>,
+    reflection: ShaderReflection,
+}
+
+/// Spir-v of a glsl source with everything that was read to build it.
+pub(crate) struct CompiledGlsl {
+    pub code: Vec<u32>,
+    pub source: String,
+    pub includes: Vec<PathBuf>,
+    pub reflection: ShaderReflection,
+}
+
+/// Reflection isn't needed to build a pipeline, a module it can't read only gets an empty one.
+fn reflect_or_empty(path: &Path, code: &[u32]) -> ShaderReflection {
+    ShaderReflection::parse(code).unwrap_or_else(|e| {
+        log::warn!("Failed to reflect {:?}: {}", path, e);
+        ShaderReflection::default()
+    })
+}
+
+/// Latest compiled variant of every shader this process built, by `variant_key`.
+fn shader_variants() -> &'static Mutex<HashMap<u64, ShaderVariant>> {
+    static VARIANTS: OnceLock<Mutex<HashMap<u64, ShaderVariant>>> = OnceLock::new();
+    VARIANTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Everything identifying a shader but its source.
+fn variant_slot(path: &Path, stage_flags: vk::ShaderStageFlags, options: &ShaderOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    stage_flags.as_raw().hash(&mut hasher);
+    options.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn variant_key(path: &Path, source: &str, stage_flags: vk::ShaderStageFlags, options: &ShaderOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    variant_slot(path, stage_flags, options).hash(&mut hasher);
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl ShaderVariant {
+    /// False when an include changed or went missing since the variant was compiled.
+    fn is_current(&self) -> bool {
+        self.includes.iter().all(|(path, hash)| {
+            fs::read_to_string(path).is_ok_and(|content| content_hash(&content) == *hash)
+        })
+    }
+}
+
+/// Where `#include "file"` looks first: next to the including file, then next to the shader being
+/// compiled, then in `include_dirs`. `#include <file>` tries `include_dirs` first.
+fn resolve_include(
+    requested_source: &str,
+    include_type: IncludeType,
+    origin_source: &str,
+    root_dir: &Path,
+    include_dirs: &[PathBuf],
+) -> Option<PathBuf> {
+    let origin_dir = Path::new(origin_source).parent().filter(|dir| !dir.as_os_str().is_empty());
+    let relative = origin_dir.into_iter().chain(std::iter::once(root_dir));
+    let search = include_dirs.iter().map(PathBuf::as_path);
+    let mut candidates: Box<dyn Iterator<Item = &Path>> = match include_type {
+        IncludeType::Relative => Box::new(relative.chain(search)),
+        IncludeType::Standard => Box::new(search.chain(relative)),
+    };
+    candidates.find_map(|dir| Some(dir.join(requested_source)).filter(|path| path.is_file()))
 }
 
 fn get_sharerc_include(
     requested_source: &str,
-    _include_type: IncludeType,
-    _origin_source: &str,
-    _recursion_depth: usize,
-    origin_dir: &Path,
+    include_type: IncludeType,
+    origin_source: &str,
+    root_dir: &Path,
+    include_dirs: &[PathBuf],
 ) -> Result<ResolvedInclude, String> {
-    //TODO: finish implementation
-    let resolved_file = origin_dir.join(requested_source);
-    let resolved_name = resolved_file
-        // .file_name()
-        // .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
-    //println!("Including: {}", resolved_name);
-    let error_msg = format!("Failed to open {}.", resolved_file.to_str().unwrap());
-    let content = fs::read_to_string(resolved_file.as_path()).expect(&error_msg);
+    let resolved_file = resolve_include(requested_source, include_type, origin_source, root_dir, include_dirs)
+        .ok_or_else(|| format!("Failed to find {} included from {}, searched {:?} and {:?}", requested_source, origin_source, root_dir, include_dirs))?;
+    let resolved_name = resolved_file.to_string_lossy().to_string();
+    let content = fs::read_to_string(resolved_file.as_path())
+        .map_err(|e| format!("Failed to open {}: {}", resolved_name, e))?;
     Ok(ResolvedInclude {
         resolved_name,
         content,
@@ -70,8 +302,8 @@ fn get_shaderc_stage(stage: &vk::ShaderStageFlags) -> Option<ShaderKind> {
     None
 }
 
-fn get_spirv_filepath(path: &PathBuf) -> PathBuf {
-    let mut compiled_path = path.clone();
+fn get_spirv_filepath(path: &Path) -> PathBuf {
+    let mut compiled_path = path.to_path_buf();
     let filename = compiled_path
         .file_name()
         .unwrap()
@@ -83,83 +315,191 @@ fn get_spirv_filepath(path: &PathBuf) -> PathBuf {
     compiled_path
 }
 
-fn is_more_recent(path: &PathBuf, other: &PathBuf) -> bool {
-    let timestamp = fs::metadata(path.as_path()).unwrap().modified().unwrap();
-    let other_timestamp = fs::metadata(other.as_path()).unwrap().modified().unwrap();
-    timestamp > other_timestamp
+fn get_dependency_filepath(spirv_path: &Path) -> PathBuf {
+    let mut path = spirv_path.as_os_str().to_owned();
+    path.push(".d");
+    PathBuf::from(path)
 }
 
-impl Shader {
-    pub fn new(context: Arc<Context>, path: PathBuf, stage_flags: vk::ShaderStageFlags) -> Self {
-        let spirv_path = get_spirv_filepath(&path);
-        // Only load spirv directly if its timestamp is more recent than the source file.
-        if spirv_path.exists() && LOAD_SPIRV && is_more_recent(&spirv_path, &path) {
-            let mut file = fs::File::open(&spirv_path).unwrap();
-            let words = ash::util::read_spv(&mut file).unwrap();
-            let shader_info = vk::ShaderModuleCreateInfo::builder().code(&words);
-            unsafe {
-                let module = context
-                    .device()
-                    .create_shader_module(&shader_info, None)
-                    .unwrap();
-                return Shader {
-                    context,
-                    module,
-                    stage_flags,
-                    path,
-                    text: None,
-                };
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn is_more_recent(path: &Path, other: &Path) -> bool {
+    match (modified(path), modified(other)) {
+        (Some(timestamp), Some(other_timestamp)) => timestamp > other_timestamp,
+        _ => false,
+    }
+}
+
+/// Includes recorded next to the spir-v, None when they weren't recorded.
+fn read_dependencies(spirv_path: &Path) -> Option<Vec<PathBuf>> {
+    let text = fs::read_to_string(get_dependency_filepath(spirv_path)).ok()?;
+    Some(text.lines().filter(|line| !line.is_empty()).map(PathBuf::from).collect())
+}
+
+/// Stored spir-v of the glsl at `path` and its includes, None when it's missing or older than
+/// any of them.
+fn load_spirv(path: &Path) -> Option<(Vec<u32>, Vec<PathBuf>)> {
+    let spirv_path = get_spirv_filepath(path);
+    let includes = read_dependencies(&spirv_path)?;
+    let current = is_more_recent(&spirv_path, path) && includes.iter().all(|include| is_more_recent(&spirv_path, include));
+    if !current {
+        return None;
+    }
+    let mut file = fs::File::open(&spirv_path).ok()?;
+    let words = ash::util::read_spv(&mut file).ok()?;
+    Some((words, includes))
+}
+
+fn store_spirv(path: &Path, code: &[u32], includes: &[PathBuf]) -> std::io::Result<()> {
+    let spirv_path = get_spirv_filepath(path);
+    let words: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+    fs::write(&spirv_path, words)?;
+    let listing: String = includes.iter().map(|include| format!("{}\n", include.display())).collect();
+    fs::write(get_dependency_filepath(&spirv_path), listing)
+}
+
+/// Every file a shader was built from, the source first.
+pub(crate) fn dependencies(shaders: &[Shader]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for shader in shaders {
+        for path in shader.dependencies() {
+            if !paths.contains(&path) {
+                paths.push(path);
             }
         }
+    }
+    paths
+}
 
-        let error_msg = format!("Failed to open {}.", path.to_str().unwrap());
-        let source = fs::read_to_string(path.as_path()).expect(&error_msg);
+/// Compiles the glsl at `path`, returning the spir-v, its reflection and every file that was
+/// `#include`d. Reuses an earlier compile when the source, the options and all includes are unchanged.
+pub(crate) fn compile_glsl(path: &Path, stage_flags: vk::ShaderStageFlags, options: &ShaderOptions) -> Result<CompiledGlsl, ShaderError> {
+    let source = fs::read_to_string(path).map_err(|e| ShaderError::Io(path.to_path_buf(), e))?;
+    let key = variant_key(path, &source, stage_flags, options);
+    let cached = shader_variants().lock().unwrap().get(&key).cloned();
+    if let Some(variant) = cached.filter(ShaderVariant::is_current) {
+        let includes = variant.includes.iter().map(|(path, _)| path.clone()).collect();
+        return Ok(CompiledGlsl { code: variant.code.to_vec(), source, includes, reflection: variant.reflection });
+    }
 
-        let compiler = Compiler::new().unwrap();
-        let mut options = CompileOptions::new().unwrap();
-        options.set_generate_debug_info();
-        options.set_target_spirv(shaderc::SpirvVersion::V1_6);
-        options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_2 as u32);
-        let origin_path = path.clone();
-        options.set_include_callback(
-            move |requested_source, include_type, origin_source, recursion_depth| {
-                get_sharerc_include(
-                    requested_source,
-                    include_type,
-                    origin_source,
-                    recursion_depth,
-                    origin_path.parent().unwrap(),
-                )
-            },
-        );
-        let sc_stage = get_shaderc_stage(&stage_flags).unwrap();
-        let code = compiler
-            .compile_into_spirv(
-                &source,
-                sc_stage,
-                path.file_name().unwrap().to_str().unwrap(),
-                "main",
-                Some(&options),
-            )
-            .unwrap();
-
-        if STORE_SPIRV {
-            fs::write(spirv_path, code.as_binary_u8()).expect("Failed to write spir-v.");
-        }
-        let shader_info = vk::ShaderModuleCreateInfo::builder().code(code.as_binary());
-        unsafe {
-            let module = context
-                .device()
-                .create_shader_module(&shader_info, None)
-                .unwrap();
-            Shader {
+    let sc_stage = get_shaderc_stage(&stage_flags)
+        .ok_or_else(|| ShaderError::Compile(format!("Unsupported shader stage {:?}", stage_flags)))?;
+    let includes = RefCell::new(Vec::<(PathBuf, u64)>::new());
+    let compiler = Compiler::new().unwrap();
+    let mut compile_options = options.compile_options();
+    let root_dir = path.parent().unwrap_or(Path::new(""));
+    compile_options.set_include_callback(
+        |requested_source, include_type, origin_source, _recursion_depth| {
+            let include = get_sharerc_include(
+                requested_source,
+                include_type,
+                origin_source,
+                root_dir,
+                &options.include_dirs,
+            )?;
+            let resolved = PathBuf::from(&include.resolved_name);
+            let mut includes = includes.borrow_mut();
+            if !includes.iter().any(|(path, _)| *path == resolved) {
+                includes.push((resolved, content_hash(&include.content)));
+            }
+            Ok(include)
+        },
+    );
+    let code = compiler
+        .compile_into_spirv(
+            &source,
+            sc_stage,
+            &path.to_string_lossy(),
+            &options.entry_point,
+            Some(&compile_options),
+        )
+        .map_err(|e| ShaderError::Compile(e.to_string()))?;
+
+    let variant = ShaderVariant {
+        slot: variant_slot(path, stage_flags, options),
+        code: Arc::new(code.as_binary().to_vec()),
+        includes: includes.take(),
+        reflection: reflect_or_empty(path, code.as_binary()),
+    };
+    let compiled = CompiledGlsl {
+        code: code.as_binary().to_vec(),
+        source,
+        includes: variant.includes.iter().map(|(path, _)| path.clone()).collect(),
+        reflection: variant.reflection.clone(),
+    };
+    let mut variants = shader_variants().lock().unwrap();
+    variants.retain(|_, stale| stale.slot != variant.slot);
+    variants.insert(key, variant);
+    Ok(compiled)
+}
+
+impl Shader {
+    /// Panics when the shader doesn't compile, see `try_new`.
+    pub fn new(context: Arc<Context>, path: PathBuf, stage_flags: vk::ShaderStageFlags) -> Self {
+        Self::try_new(context, path.clone(), stage_flags)
+            .unwrap_or_else(|e| panic!("Failed to build shader {:?}: {}", path, e))
+    }
+
+    pub fn try_new(context: Arc<Context>, path: PathBuf, stage_flags: vk::ShaderStageFlags) -> Result<Self, ShaderError> {
+        Self::with_options(context, path, stage_flags, &ShaderOptions::default())
+    }
+
+    pub fn with_options(context: Arc<Context>, path: PathBuf, stage_flags: vk::ShaderStageFlags, options: &ShaderOptions) -> Result<Self, ShaderError> {
+        // the stored spir-v is built with the default options
+        let use_cache = SPIRV_CACHE.load(Ordering::Relaxed) && *options == ShaderOptions::default();
+        if let Some((words, includes)) = use_cache.then(|| load_spirv(&path)).flatten() {
+            let reflection = reflect_or_empty(&path, &words);
+            let module = Self::create_module(&context, &words)?;
+            return Ok(Shader {
                 context,
                 module,
                 stage_flags,
                 path,
-                text: Some(source),
+                text: None,
+                includes,
+                reflection,
+            });
+        }
+
+        let CompiledGlsl { code, source, includes, reflection } = compile_glsl(&path, stage_flags, options)?;
+
+        if use_cache {
+            if let Err(e) = store_spirv(&path, &code, &includes) {
+                log::warn!("Failed to store spir-v of {:?}: {}", path, e);
             }
         }
+        let module = Self::create_module(&context, &code)?;
+        Ok(Shader {
+            context,
+            module,
+            stage_flags,
+            path,
+            text: Some(source),
+            includes,
+            reflection,
+        })
+    }
+
+    fn create_module(context: &Arc<Context>, code: &[u32]) -> Result<vk::ShaderModule, ShaderError> {
+        let shader_info = vk::ShaderModuleCreateInfo::builder().code(code);
+        unsafe { context.device().create_shader_module(&shader_info, None) }.map_err(ShaderError::Vulkan)
+    }
+
+    /// Files `#include`d by the shader, directly or through other includes.
+    pub fn includes(&self) -> &[PathBuf] {
+        &self.includes
+    }
+
+    /// Descriptor bindings, push constants and specialization constants declared by the shader.
+    pub fn reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+
+    /// The source and its includes, a change to any of them requires rebuilding the shader.
+    pub fn dependencies(&self) -> Vec<PathBuf> {
+        std::iter::once(self.path.clone()).chain(self.includes.iter().cloned()).collect()
     }
 
     pub fn get_create_info(&self, name: &'_ std::ffi::CStr) -> vk::PipelineShaderStageCreateInfo {
@@ -230,6 +570,7 @@ pub struct PipelineInfo {
     pub samples: vk::SampleCountFlags,
     pub specialization_data: Vec<u8>,
     pub specialization_entries: Vec<vk::SpecializationMapEntry>,
+    pub shader_options: ShaderOptions,
     pub primitive_topology: vk::PrimitiveTopology,
 }
 
@@ -255,6 +596,7 @@ impl Default for PipelineInfo {
             specialization_data: Vec::new(),
             specialization_entries: Vec::new(),
             primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            shader_options: ShaderOptions::default(),
         }
     }
 }
@@ -363,6 +705,17 @@ impl PipelineInfo {
         self.blend_mode = mode;
         self
     }
+
+    /// Bindings and push constants of all stages, to build or check the pipeline layout.
+    pub fn reflect(&self) -> Result<ShaderReflection, ShaderError> {
+        reflect_shaders(&self.shaders, &self.shader_options)
+    }
+}
+
+impl ShaderOptionsBuilder for PipelineInfo {
+    fn shader_options_mut(&mut self) -> &mut ShaderOptions {
+        &mut self.shader_options
+    }
 }
 
 pub struct Pipeline {
@@ -370,6 +723,7 @@ pub struct Pipeline {
     info: PipelineInfo,
     pipeline: vk::Pipeline,
     transient_render_pass: Option<RenderPass>,
+    dependencies: Vec<PathBuf>,
 }
 
 impl Pipeline {
@@ -378,11 +732,35 @@ impl Pipeline {
         assert!(!info.vertex_format_offset.is_empty());
         assert!(info.render_pass.is_some() || info.transient_render_pass_info.is_some());
 
+        let transient_render_pass = match info.transient_render_pass_info.clone() {
+            Some(render_pass_info) => Some(RenderPass::new_transient(
+                context.shared().clone(),
+                render_pass_info,
+            )),
+            None => None,
+        };
+        let render_pass = match info.render_pass {
+            Some(render_pass) => render_pass,
+            None => transient_render_pass.as_ref().unwrap().handle(),
+        };
+        let (pipeline, dependencies) = Self::create(&context, &info, render_pass)
+            .unwrap_or_else(|e| panic!("Unable to create graphics pipeline: {}", e));
+
+        Pipeline {
+            context,
+            info,
+            pipeline,
+            transient_render_pass,
+            dependencies,
+        }
+    }
+
+    fn create(context: &Arc<Context>, info: &PipelineInfo, render_pass: vk::RenderPass) -> Result<(vk::Pipeline, Vec<PathBuf>), ShaderError> {
         let mut shaders = Vec::<Shader>::new();
         let mut shader_stage_create_infos = Vec::new();
-        let shader_entry_name = CString::new("main").unwrap();
+        let shader_entry_name = info.shader_options.entry_point_name()?;
         for shader_info in &info.shaders {
-            let shader = Shader::new(context.clone(), shader_info.0.clone(), shader_info.1);
+            let shader = Shader::with_options(context.clone(), shader_info.0.clone(), shader_info.1, &info.shader_options)?;
             if info.specialization_entries.is_empty() {
                 shader_stage_create_infos.push(shader.get_create_info(&shader_entry_name));
             } else {
@@ -523,17 +901,6 @@ impl Pipeline {
         let dynamic_state_info =
             vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_state);
 
-        let transient_render_pass = match info.transient_render_pass_info.clone() {
-            Some(render_pass_info) => Some(RenderPass::new_transient(
-                context.shared().clone(),
-                render_pass_info,
-            )),
-            None => None,
-        };
-        let render_pass = match info.render_pass {
-            Some(render_pass) => render_pass,
-            None => transient_render_pass.as_ref().unwrap().handle(),
-        };
         let create_infos = [vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stage_create_infos)
             .vertex_input_state(&vertex_input_state_info)
@@ -551,15 +918,17 @@ impl Pipeline {
         let graphics_pipelines = unsafe {
             context
                 .device()
-                .create_graphics_pipelines(vk::PipelineCache::null(), &create_infos, None)
-                .expect("Unable to create graphics pipeline")
+                .create_graphics_pipelines(context.pipeline_cache(), &create_infos, None)
+                .map_err(|(_, e)| ShaderError::Vulkan(e))?
         };
 
-        Pipeline {
-            context,
-            info,
-            pipeline: graphics_pipelines[0],
-            transient_render_pass,
+        Ok((graphics_pipelines[0], dependencies(&shaders)))
+    }
+
+    fn render_pass(&self) -> vk::RenderPass {
+        match self.info.render_pass {
+            Some(render_pass) => render_pass,
+            None => self.transient_render_pass.as_ref().unwrap().handle(),
         }
     }
 
@@ -571,6 +940,22 @@ impl Pipeline {
     }
 }
 
+impl Reload for Pipeline {
+    fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    fn reload(&mut self) -> Result<(), ShaderError> {
+        let (pipeline, dependencies) = Self::create(&self.context, &self.info, self.render_pass())?;
+        unsafe {
+            self.context.device().destroy_pipeline(self.pipeline, None);
+        }
+        self.pipeline = pipeline;
+        self.dependencies = dependencies;
+        Ok(())
+    }
+}
+
 impl Resource<vk::Pipeline> for Pipeline {
     fn handle(&self) -> vk::Pipeline {
         self.pipeline
@@ -584,3 +969,86 @@ impl Drop for Pipeline {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_includes() {
+        let dir = std::env::temp_dir().join("bolt_shader_includes");
+        let (shaders, common, nested) = (dir.join("shaders"), dir.join("common"), dir.join("shaders/lib"));
+        for d in [&shaders, &common, &nested] {
+            fs::create_dir_all(d).unwrap();
+        }
+        fs::write(shaders.join("payload.glsl"), "").unwrap();
+        fs::write(common.join("payload.glsl"), "").unwrap();
+        fs::write(common.join("sampling.glsl"), "").unwrap();
+        fs::write(nested.join("random.glsl"), "").unwrap();
+        let include_dirs = vec![common.clone()];
+        let origin = shaders.join("pass.rgen");
+        let origin = origin.to_str().unwrap();
+
+        // quotes prefer the shader's directory, angle brackets the include dirs
+        let relative = resolve_include("payload.glsl", IncludeType::Relative, origin, &shaders, &include_dirs);
+        assert_eq!(relative, Some(shaders.join("payload.glsl")));
+        let standard = resolve_include("payload.glsl", IncludeType::Standard, origin, &shaders, &include_dirs);
+        assert_eq!(standard, Some(common.join("payload.glsl")));
+        let searched = resolve_include("sampling.glsl", IncludeType::Relative, origin, &shaders, &include_dirs);
+        assert_eq!(searched, Some(common.join("sampling.glsl")));
+
+        // nested includes resolve next to the file that includes them
+        let from_nested = nested.join("util.glsl");
+        let sibling = resolve_include("random.glsl", IncludeType::Relative, from_nested.to_str().unwrap(), &shaders, &include_dirs);
+        assert_eq!(sibling, Some(nested.join("random.glsl")));
+
+        assert_eq!(resolve_include("missing.glsl", IncludeType::Relative, origin, &shaders, &include_dirs), None);
+        let error = get_sharerc_include("missing.glsl", IncludeType::Relative, origin, &shaders, &include_dirs);
+        assert!(error.is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn variant_keys() {
+        let path = Path::new("assets/glsl/pathtrace.rgen");
+        let stage = vk::ShaderStageFlags::RAYGEN_KHR;
+        let options = ShaderOptions::default();
+        let key = variant_key(path, "void main() {}", stage, &options);
+        assert_eq!(key, variant_key(path, "void main() {}", stage, &ShaderOptions::default()));
+        assert_ne!(key, variant_key(path, "void main() { }", stage, &options));
+        // edits of the same shader share a slot, so a recompile drops the old variant
+        assert_eq!(variant_slot(path, stage, &options), variant_slot(path, stage, &ShaderOptions::default()));
+        assert_ne!(variant_slot(path, stage, &options), variant_slot(path, vk::ShaderStageFlags::MISS_KHR, &options));
+        assert_ne!(key, variant_key(path, "void main() {}", vk::ShaderStageFlags::MISS_KHR, &options));
+        assert_ne!(key, variant_key(path, "void main() {}", stage, &options.clone().define("SKY", None)));
+        assert_ne!(key, variant_key(path, "void main() {}", stage, &options.clone().optimization(ShaderOptimization::Performance)));
+        assert_ne!(key, variant_key(path, "void main() {}", stage, &options.clone().entry_point("trace")));
+        assert!(options.clone().entry_point("tr\0ce").entry_point_name().is_err());
+        // the pipeline infos share the option builders
+        let info = PipelineInfo::default().define("SKY", Some("1")).optimization(ShaderOptimization::Size).entry_point("trace").debug_info(false);
+        assert_eq!(info.shader_options, options.define("SKY", Some("1")).optimization(ShaderOptimization::Size).entry_point("trace").debug_info(false));
+    }
+
+    #[test]
+    fn stored_spirv_follows_includes() {
+        let dir = std::env::temp_dir().join("bolt_spirv_cache");
+        fs::create_dir_all(&dir).unwrap();
+        let (source, include) = (dir.join("blur.comp"), dir.join("kernel.glsl"));
+        let past = SystemTime::now() - std::time::Duration::from_secs(60);
+        for path in [&source, &include] {
+            fs::write(path, "").unwrap();
+            fs::File::options().write(true).open(path).unwrap().set_modified(past).unwrap();
+        }
+        assert_eq!(load_spirv(&source), None);
+
+        let code = vec![0x0723_0203, 0x0001_0600, 7];
+        store_spirv(&source, &code, &[include.clone()]).unwrap();
+        assert_eq!(load_spirv(&source), Some((code, vec![include.clone()])));
+
+        // editing an include invalidates the stored spir-v
+        let future = SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(&include).unwrap().set_modified(future).unwrap();
+        assert_eq!(load_spirv(&source), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use ash::vk;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::SharedContext;
+
+// Layout of `VkPipelineCacheHeaderVersionOne`:
+//
+//   header length | u32, 32
+//   version       | u32, VK_PIPELINE_CACHE_HEADER_VERSION_ONE
+//   vendor id     | u32
+//   device id     | u32
+//   cache uuid    | 16 bytes
+//
+// Drivers are supposed to reject data from other devices themselves, but not all of them do.
+
+const HEADER_SIZE: usize = 32;
+
+#[derive(Debug)]
+pub enum PipelineCacheError {
+    Truncated,
+    HeaderVersion(u32),
+    Vendor(u32),
+    Device(u32),
+    Uuid,
+    Io(std::io::Error),
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for PipelineCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for PipelineCacheError {}
+
+impl From<std::io::Error> for PipelineCacheError {
+    fn from(e: std::io::Error) -> Self {
+        PipelineCacheError::Io(e)
+    }
+}
+
+impl From<vk::Result> for PipelineCacheError {
+    fn from(e: vk::Result) -> Self {
+        PipelineCacheError::Vulkan(e)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Checks that `data` was written by the driver and device described by `properties`.
+pub fn validate_pipeline_cache(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> Result<(), PipelineCacheError> {
+    if data.len() < HEADER_SIZE || (read_u32(data, 0) as usize) < HEADER_SIZE {
+        return Err(PipelineCacheError::Truncated);
+    }
+    let version = read_u32(data, 4);
+    if version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(PipelineCacheError::HeaderVersion(version));
+    }
+    let vendor = read_u32(data, 8);
+    if vendor != properties.vendor_id {
+        return Err(PipelineCacheError::Vendor(vendor));
+    }
+    let device = read_u32(data, 12);
+    if device != properties.device_id {
+        return Err(PipelineCacheError::Device(device));
+    }
+    if data[16..32] != properties.pipeline_cache_uuid {
+        return Err(PipelineCacheError::Uuid);
+    }
+    Ok(())
+}
+
+/// One `VkPipelineCache` for every pipeline created through a `Context`. With a path it's
+/// loaded on creation and written back when dropped.
+#[derive(Debug)]
+pub struct PipelineCache {
+    context: Arc<SharedContext>,
+    cache: vk::PipelineCache,
+    path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+    /// Starts empty when the file is missing or was written by another device or driver.
+    pub fn new(context: Arc<SharedContext>, path: Option<PathBuf>) -> Self {
+        let data = path.as_ref().and_then(|path| match Self::read(path, &context.get_physical_device_properties()) {
+            Ok(data) => Some(data),
+            Err(PipelineCacheError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("Ignoring pipeline cache {:?}: {}", path, e);
+                None
+            }
+        });
+        let data = data.unwrap_or_default();
+        let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(&data);
+        let cache = unsafe { context.device().create_pipeline_cache(&create_info, None) }
+            .or_else(|_| unsafe {
+                // some drivers still refuse data with a valid header
+                context.device().create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+            })
+            .expect("Unable to create pipeline cache");
+        PipelineCache { context, cache, path }
+    }
+
+    fn read(path: &Path, properties: &vk::PhysicalDeviceProperties) -> Result<Vec<u8>, PipelineCacheError> {
+        let data = fs::read(path)?;
+        validate_pipeline_cache(&data, properties)?;
+        Ok(data)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn data(&self) -> Result<Vec<u8>, PipelineCacheError> {
+        Ok(unsafe { self.context.device().get_pipeline_cache_data(self.cache) }?)
+    }
+
+    /// Writes the cache to its path, through a temporary file so a crash can't leave half a cache.
+    pub fn save(&self) -> Result<(), PipelineCacheError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let data = self.data()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl crate::Resource<vk::PipelineCache> for PipelineCache {
+    fn handle(&self) -> vk::PipelineCache {
+        self.cache
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("Failed to write pipeline cache {:?}: {}", self.path, e);
+        }
+        unsafe {
+            self.context.device().destroy_pipeline_cache(self.cache, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_header() {
+        let properties = vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2684,
+            pipeline_cache_uuid: [7; 16],
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        for word in [32u32, 1, 0x10de, 0x2684] {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&[7; 16]);
+        data.extend_from_slice(&[0; 64]);
+        assert!(validate_pipeline_cache(&data, &properties).is_ok());
+
+        assert!(matches!(validate_pipeline_cache(&data[..20], &properties), Err(PipelineCacheError::Truncated)));
+        let other_device = vk::PhysicalDeviceProperties { device_id: 0x2704, ..properties };
+        assert!(matches!(validate_pipeline_cache(&data, &other_device), Err(PipelineCacheError::Device(0x2684))));
+        let other_vendor = vk::PhysicalDeviceProperties { vendor_id: 0x1002, ..properties };
+        assert!(matches!(validate_pipeline_cache(&data, &other_vendor), Err(PipelineCacheError::Vendor(0x10de))));
+        let other_driver = vk::PhysicalDeviceProperties { pipeline_cache_uuid: [8; 16], ..properties };
+        assert!(matches!(validate_pipeline_cache(&data, &other_driver), Err(PipelineCacheError::Uuid)));
+        data[4] = 2;
+        assert!(matches!(validate_pipeline_cache(&data, &properties), Err(PipelineCacheError::HeaderVersion(2))));
+    }
+}
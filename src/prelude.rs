@@ -1,6 +1,6 @@
 pub use crate::{
     ash::{vk},
     glam::*,
-    winit, Resource, Vertex,
+    winit, Resource, ShaderOptionsBuilder, Vertex,
 };
 pub use std::{default::Default, mem::size_of, result::Result, sync::Arc};
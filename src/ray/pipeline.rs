@@ -1,6 +1,6 @@
-use crate::{pipeline::Shader, Context, Resource};
+use crate::{pipeline::{dependencies, Shader, ShaderError, ShaderOptions, ShaderOptionsBuilder}, reflect_shaders, Context, Reload, Resource, ShaderReflection};
 use ash::{vk};
-use std::{ffi::CString, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc};
 
 pub struct PipelineInfo {
     pub layout: vk::PipelineLayout,
@@ -8,6 +8,7 @@ pub struct PipelineInfo {
     pub name: String,
     pub specialization_data: Vec<u8>,
     pub specialization_entries: Vec<vk::SpecializationMapEntry>,
+    pub shader_options: ShaderOptions,
 }
 
 impl Default for PipelineInfo {
@@ -18,6 +19,7 @@ impl Default for PipelineInfo {
             name: "".to_string(),
             specialization_data: Vec::new(),
             specialization_entries: Vec::new(),
+            shader_options: ShaderOptions::default(),
         }
     }
 }
@@ -35,6 +37,11 @@ impl PipelineInfo {
         self.name = name.to_string();
         self
     }
+
+    /// Bindings and push constants of all stages, to build or check the pipeline layout.
+    pub fn reflect(&self) -> Result<ShaderReflection, ShaderError> {
+        reflect_shaders(&self.shaders, &self.shader_options)
+    }
     pub fn specialization<T>(mut self, data: &T, constant_id: u32) -> Self {
         let slice = unsafe {
             std::slice::from_raw_parts(data as *const T as *const u8, std::mem::size_of_val(data))
@@ -51,20 +58,39 @@ impl PipelineInfo {
     }
 }
 
+impl ShaderOptionsBuilder for PipelineInfo {
+    fn shader_options_mut(&mut self) -> &mut ShaderOptions {
+        &mut self.shader_options
+    }
+}
+
 pub struct Pipeline {
     context: Arc<Context>,
     info: PipelineInfo,
     pipeline: vk::Pipeline,
+    dependencies: Vec<PathBuf>,
 }
 
 impl Pipeline {
     pub fn new(context: Arc<Context>, info: PipelineInfo) -> Self {
+        let (pipeline, dependencies) = Self::create(&context, &info)
+            .unwrap_or_else(|e| panic!("Unable to create ray tracing pipeline: {}", e));
+
+        Pipeline {
+            context,
+            info,
+            pipeline,
+            dependencies,
+        }
+    }
+
+    fn create(context: &Arc<Context>, info: &PipelineInfo) -> Result<(vk::Pipeline, Vec<PathBuf>), ShaderError> {
         let mut shaders = Vec::<Shader>::new();
         let mut stages = Vec::new();
         let mut groups = Vec::new();
-        let shader_entry_name = CString::new("main").unwrap();
+        let shader_entry_name = info.shader_options.entry_point_name()?;
         for (index, shader_info) in info.shaders.iter().enumerate() {
-            let shader = Shader::new(context.clone(), shader_info.0.clone(), shader_info.1);
+            let shader = Shader::with_options(context.clone(), shader_info.0.clone(), shader_info.1, &info.shader_options)?;
             if info.specialization_entries.is_empty() {
                 stages.push(shader.get_create_info(&shader_entry_name));
             } else {
@@ -107,18 +133,13 @@ impl Pipeline {
                 .ray_tracing()
                 .create_ray_tracing_pipelines(
                     vk::DeferredOperationKHR::null(),
-                    vk::PipelineCache::null(),
+                    context.pipeline_cache(),
                     &[create_info],
                     None
                 )
-                .expect("Unable to create graphics pipeline")[0]
+                .map_err(ShaderError::Vulkan)?[0]
         };
-
-        Pipeline {
-            context,
-            info,
-            pipeline,
-        }
+        Ok((pipeline, dependencies(&shaders)))
     }
 
     pub fn update_specialization<T>(&mut self, data: &T) {
@@ -129,6 +150,23 @@ impl Pipeline {
     }
 }
 
+/// The shader group handles change with the pipeline, rebuild the `ShaderBindingTable` after a reload.
+impl Reload for Pipeline {
+    fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    fn reload(&mut self) -> Result<(), ShaderError> {
+        let (pipeline, dependencies) = Self::create(&self.context, &self.info)?;
+        unsafe {
+            self.context.device().destroy_pipeline(self.pipeline, None);
+        }
+        self.pipeline = pipeline;
+        self.dependencies = dependencies;
+        Ok(())
+    }
+}
+
 impl Resource<vk::Pipeline> for Pipeline {
     fn handle(&self) -> vk::Pipeline {
         self.pipeline
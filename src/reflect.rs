@@ -0,0 +1,680 @@
+use ash::vk;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::pipeline::compile_glsl;
+use crate::{DescriptorSetLayoutInfo, PipelineLayoutInfo, ShaderError, ShaderOptions};
+
+// Just enough of the SPIR-V spec to find a module's resource interface. Every instruction is
+// `word count << 16 | opcode` followed by its operands, after a five word header.
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectError {
+    Magic(u32),
+    Truncated,
+    UnknownType(u32),
+    /// Two stages declare the same set and binding with different types.
+    Conflict { set: u32, binding: u32 },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for ReflectError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Array length, 0 for runtime sized arrays and arrays sized by a specialization constant.
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecConstant {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Where a binding or push constant range differs from what the shaders declare.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutMismatch {
+    Missing { set: u32, binding: u32 },
+    Type { set: u32, binding: u32, expected: vk::DescriptorType, found: vk::DescriptorType },
+    Count { set: u32, binding: u32, expected: u32, found: u32 },
+    Stages { set: u32, binding: u32, expected: vk::ShaderStageFlags, found: vk::ShaderStageFlags },
+    PushConstants { stages: vk::ShaderStageFlags, offset: u32, size: u32 },
+}
+
+/// Descriptor bindings, push constants and specialization constants used by one or more shader stages.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderReflection {
+    pub stages: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    pub push_constants: Vec<vk::PushConstantRange>,
+    pub spec_constants: Vec<SpecConstant>,
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Scalar { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct,
+    Pointer { storage: u32, pointee: u32 },
+    AccelerationStructure,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    types: HashMap<u32, Type>,
+    members: HashMap<u32, Vec<u32>>,
+    constants: HashMap<u32, u32>,
+    spec_constants: Vec<u32>,
+    variables: Vec<(u32, u32, u32)>,
+    stages: vk::ShaderStageFlags,
+}
+
+fn execution_model_stage(model: u32) -> vk::ShaderStageFlags {
+    match model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        5267 => vk::ShaderStageFlags::TASK_NV,
+        5268 => vk::ShaderStageFlags::MESH_NV,
+        5313 => vk::ShaderStageFlags::RAYGEN_KHR,
+        5314 => vk::ShaderStageFlags::INTERSECTION_KHR,
+        5315 => vk::ShaderStageFlags::ANY_HIT_KHR,
+        5316 => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        5317 => vk::ShaderStageFlags::MISS_KHR,
+        5318 => vk::ShaderStageFlags::CALLABLE_KHR,
+        _ => vk::ShaderStageFlags::empty(),
+    }
+}
+
+/// Literal strings are nul terminated and padded to whole words.
+fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).take_while(|b| *b != 0).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self, ReflectError> {
+        if words.len() < HEADER_WORDS {
+            return Err(ReflectError::Truncated);
+        }
+        if words[0] != MAGIC {
+            return Err(ReflectError::Magic(words[0]));
+        }
+        let mut module = Module::default();
+        let mut i = HEADER_WORDS;
+        while i < words.len() {
+            let count = (words[i] >> 16) as usize;
+            if count == 0 || i + count > words.len() {
+                return Err(ReflectError::Truncated);
+            }
+            module.instruction(words[i] & 0xffff, &words[i + 1..i + count])?;
+            i += count;
+        }
+        Ok(module)
+    }
+
+    fn instruction(&mut self, opcode: u32, ops: &[u32]) -> Result<(), ReflectError> {
+        let operand = |index: usize| ops.get(index).copied().ok_or(ReflectError::Truncated);
+        match opcode {
+            OP_NAME => {
+                self.names.insert(operand(0)?, literal_string(&ops[1..]));
+            }
+            OP_ENTRY_POINT => self.stages |= execution_model_stage(operand(0)?),
+            OP_DECORATE => {
+                let value = ops.get(2).copied().unwrap_or(0);
+                self.decorations.insert((operand(0)?, operand(1)?), value);
+            }
+            OP_MEMBER_DECORATE => {
+                let value = ops.get(3).copied().unwrap_or(0);
+                self.member_decorations.insert((operand(0)?, operand(1)?, operand(2)?), value);
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                self.types.insert(operand(0)?, Type::Scalar { width: operand(1)? });
+            }
+            OP_TYPE_VECTOR => {
+                self.types.insert(operand(0)?, Type::Vector { component: operand(1)?, count: operand(2)? });
+            }
+            OP_TYPE_MATRIX => {
+                self.types.insert(operand(0)?, Type::Matrix { column: operand(1)?, count: operand(2)? });
+            }
+            OP_TYPE_IMAGE => {
+                self.types.insert(operand(0)?, Type::Image { dim: operand(2)?, sampled: operand(6)? });
+            }
+            OP_TYPE_SAMPLER => {
+                self.types.insert(operand(0)?, Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0)?, Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                self.types.insert(operand(0)?, Type::Array { element: operand(1)?, length: operand(2)? });
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, Type::RuntimeArray { element: operand(1)? });
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(operand(0)?, Type::Struct);
+                self.members.insert(operand(0)?, ops[1..].to_vec());
+            }
+            OP_TYPE_POINTER => {
+                self.types.insert(operand(0)?, Type::Pointer { storage: operand(1)?, pointee: operand(2)? });
+            }
+            OP_TYPE_ACCELERATION_STRUCTURE => {
+                self.types.insert(operand(0)?, Type::AccelerationStructure);
+            }
+            // only the low word, array lengths and specialization defaults fit in 32 bits
+            OP_CONSTANT => {
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE | OP_SPEC_CONSTANT => self.spec_constants.push(operand(1)?),
+            OP_VARIABLE => self.variables.push((operand(0)?, operand(1)?, operand(2)?)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn ty(&self, id: u32) -> Result<Type, ReflectError> {
+        self.types.get(&id).copied().ok_or(ReflectError::UnknownType(id))
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).copied()
+    }
+
+    /// Length of an array from its length constant, 0 when a specialization constant sets it
+    /// since the length is only known when the pipeline is created.
+    fn array_length(&self, length: u32) -> u32 {
+        self.constants.get(&length).copied().unwrap_or(0)
+    }
+
+    /// Size in bytes with the explicit layout from the decorations, 0 for runtime arrays and arrays
+    /// sized by specialization constants.
+    fn size(&self, id: u32) -> Result<u32, ReflectError> {
+        Ok(match self.ty(id)? {
+            Type::Scalar { width } => width / 8,
+            Type::Vector { component, count } => self.size(component)? * count,
+            Type::Matrix { column, count } => self.size(column)? * count,
+            Type::Array { element, length } => {
+                let stride = match self.decoration(id, DECORATION_ARRAY_STRIDE) {
+                    Some(stride) => stride,
+                    None => self.size(element)?,
+                };
+                stride * self.array_length(length)
+            }
+            Type::Struct => self.struct_range(id)?.1,
+            _ => 0,
+        })
+    }
+
+    /// The bytes a struct's members cover, from the first member's offset.
+    fn struct_range(&self, id: u32) -> Result<(u32, u32), ReflectError> {
+        let (mut start, mut end) = (u32::MAX, 0);
+        let members = self.members.get(&id).ok_or(ReflectError::UnknownType(id))?;
+        for (index, &member) in members.iter().enumerate() {
+            let offset = self.member_decorations.get(&(id, index as u32, DECORATION_OFFSET)).copied().unwrap_or(0);
+            let size = match (self.ty(member)?, self.member_decorations.get(&(id, index as u32, DECORATION_MATRIX_STRIDE))) {
+                (Type::Matrix { count, .. }, Some(stride)) => stride * count,
+                _ => self.size(member)?,
+            };
+            start = start.min(offset);
+            end = end.max(offset + size);
+        }
+        Ok((start.min(end), end))
+    }
+
+    fn descriptor_type(&self, storage: u32, id: u32) -> Result<Option<(vk::DescriptorType, u32)>, ReflectError> {
+        let (id, count) = match self.ty(id)? {
+            Type::Array { element, length } => (element, self.array_length(length)),
+            Type::RuntimeArray { element } => (element, 0),
+            _ => (id, 1),
+        };
+        let descriptor_type = match (storage, self.ty(id)?) {
+            (STORAGE_UNIFORM, Type::Struct) if self.decoration(id, DECORATION_BUFFER_BLOCK).is_some() => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (STORAGE_UNIFORM, Type::Struct) => vk::DescriptorType::UNIFORM_BUFFER,
+            (STORAGE_STORAGE_BUFFER, Type::Struct) => vk::DescriptorType::STORAGE_BUFFER,
+            (STORAGE_UNIFORM_CONSTANT, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (STORAGE_UNIFORM_CONSTANT, Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (STORAGE_UNIFORM_CONSTANT, Type::AccelerationStructure) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { dim, sampled }) => match (dim, sampled) {
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some((descriptor_type, count)))
+    }
+}
+
+impl ShaderReflection {
+    /// Reads the resource interface of a SPIR-V module, `stages` comes from its entry points.
+    pub fn parse(words: &[u32]) -> Result<Self, ReflectError> {
+        let module = Module::parse(words)?;
+        let name = |id: u32| module.names.get(&id).cloned().unwrap_or_default();
+        let mut reflection = ShaderReflection {
+            stages: module.stages,
+            ..Default::default()
+        };
+
+        for &(type_id, id, storage) in &module.variables {
+            let pointee = match module.ty(type_id)? {
+                Type::Pointer { pointee, .. } => pointee,
+                _ => continue,
+            };
+            if storage == STORAGE_PUSH_CONSTANT {
+                let (offset, end) = module.struct_range(pointee)?;
+                reflection.push_constants.push(vk::PushConstantRange {
+                    stage_flags: module.stages,
+                    offset,
+                    size: end - offset,
+                });
+                continue;
+            }
+            let (set, binding) = match (
+                module.decoration(id, DECORATION_DESCRIPTOR_SET),
+                module.decoration(id, DECORATION_BINDING),
+            ) {
+                (Some(set), Some(binding)) => (set, binding),
+                _ => continue,
+            };
+            if let Some((descriptor_type, count)) = module.descriptor_type(storage, pointee)? {
+                // blocks are named by their type, the variable itself is often anonymous
+                let name = Some(name(id)).filter(|name| !name.is_empty()).unwrap_or_else(|| name(pointee));
+                reflection.bindings.push(DescriptorBinding {
+                    set,
+                    binding,
+                    descriptor_type,
+                    count,
+                    stages: module.stages,
+                    name,
+                });
+            }
+        }
+        reflection.bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        for &id in &module.spec_constants {
+            if let Some(spec_id) = module.decoration(id, DECORATION_SPEC_ID) {
+                reflection.spec_constants.push(SpecConstant { id: spec_id, name: name(id) });
+            }
+        }
+        reflection.spec_constants.sort_by_key(|constant| constant.id);
+        Ok(reflection)
+    }
+
+    /// Combines the stages of a pipeline, bindings used by several stages get all their stage flags.
+    pub fn merge(reflections: &[ShaderReflection]) -> Result<Self, ReflectError> {
+        let mut merged = ShaderReflection::default();
+        for reflection in reflections {
+            merged.stages |= reflection.stages;
+            for binding in &reflection.bindings {
+                match merged.bindings.iter_mut().find(|b| b.set == binding.set && b.binding == binding.binding) {
+                    Some(existing) if existing.descriptor_type != binding.descriptor_type => {
+                        return Err(ReflectError::Conflict { set: binding.set, binding: binding.binding });
+                    }
+                    Some(existing) => {
+                        existing.stages |= binding.stages;
+                        existing.count = existing.count.max(binding.count);
+                    }
+                    None => merged.bindings.push(binding.clone()),
+                }
+            }
+            for range in &reflection.push_constants {
+                match merged.push_constants.iter_mut().find(|r| r.offset == range.offset && r.size == range.size) {
+                    Some(existing) => existing.stage_flags |= range.stage_flags,
+                    None => merged.push_constants.push(*range),
+                }
+            }
+            for constant in &reflection.spec_constants {
+                if !merged.spec_constants.iter().any(|c| c.id == constant.id) {
+                    merged.spec_constants.push(constant.clone());
+                }
+            }
+        }
+        merged.bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        merged.spec_constants.sort_by_key(|constant| constant.id);
+        Ok(merged)
+    }
+
+    pub fn binding(&self, set: u32, binding: u32) -> Option<&DescriptorBinding> {
+        self.bindings.iter().find(|b| b.set == set && b.binding == binding)
+    }
+
+    /// One layout per set up to the highest one used, sets without bindings are left empty.
+    /// Runtime sized arrays get `runtime_array_count` descriptors.
+    pub fn descriptor_set_layout_infos(&self, runtime_array_count: u32) -> Vec<DescriptorSetLayoutInfo> {
+        let set_count = self.bindings.iter().map(|b| b.set + 1).max().unwrap_or(0);
+        let mut infos: Vec<DescriptorSetLayoutInfo> = (0..set_count).map(|_| DescriptorSetLayoutInfo::default()).collect();
+        for binding in &self.bindings {
+            let count = if binding.count == 0 { runtime_array_count } else { binding.count };
+            infos[binding.set as usize].bindings.insert(binding.binding, (binding.descriptor_type, binding.stages, count));
+        }
+        infos
+    }
+
+    /// Layout over `set_layouts`, usually created from `descriptor_set_layout_infos`, with the push
+    /// constants of every stage. Ranges sharing a stage are joined, a stage may only be in one range.
+    pub fn pipeline_layout_info(&self, set_layouts: &[vk::DescriptorSetLayout]) -> PipelineLayoutInfo {
+        let mut ranges: Vec<vk::PushConstantRange> = Vec::new();
+        for range in &self.push_constants {
+            let mut joined = *range;
+            ranges.retain(|other| {
+                if !other.stage_flags.intersects(joined.stage_flags) {
+                    return true;
+                }
+                let end = (joined.offset + joined.size).max(other.offset + other.size);
+                joined.offset = joined.offset.min(other.offset);
+                joined.size = end - joined.offset;
+                joined.stage_flags |= other.stage_flags;
+                false
+            });
+            ranges.push(joined);
+        }
+        PipelineLayoutInfo::default().desc_set_layouts(set_layouts).push_constant_ranges(&ranges)
+    }
+
+    /// Checks a hand written layout for `set` against the shaders. Extra bindings and stages are
+    /// allowed, runtime sized arrays accept any count.
+    pub fn validate_set_layout(&self, set: u32, info: &DescriptorSetLayoutInfo) -> Result<(), Vec<LayoutMismatch>> {
+        let mut mismatches = Vec::new();
+        for binding in self.bindings.iter().filter(|b| b.set == set) {
+            let (descriptor_type, stages, count) = match info.bindings.get(&binding.binding) {
+                Some(found) => *found,
+                None => {
+                    mismatches.push(LayoutMismatch::Missing { set, binding: binding.binding });
+                    continue;
+                }
+            };
+            if descriptor_type != binding.descriptor_type {
+                mismatches.push(LayoutMismatch::Type {
+                    set,
+                    binding: binding.binding,
+                    expected: binding.descriptor_type,
+                    found: descriptor_type,
+                });
+            }
+            if binding.count != 0 && count < binding.count {
+                mismatches.push(LayoutMismatch::Count { set, binding: binding.binding, expected: binding.count, found: count });
+            }
+            if !stages.contains(binding.stages) {
+                mismatches.push(LayoutMismatch::Stages { set, binding: binding.binding, expected: binding.stages, found: stages });
+            }
+        }
+        if mismatches.is_empty() { Ok(()) } else { Err(mismatches) }
+    }
+
+    /// Every push constant a stage uses has to be inside a range that includes that stage.
+    pub fn validate_push_constants(&self, ranges: &[vk::PushConstantRange]) -> Result<(), Vec<LayoutMismatch>> {
+        let mismatches: Vec<LayoutMismatch> = self.push_constants.iter()
+            .filter(|expected| {
+                !ranges.iter().any(|range| {
+                    range.stage_flags.contains(expected.stage_flags)
+                        && range.offset <= expected.offset
+                        && range.offset + range.size >= expected.offset + expected.size
+                })
+            })
+            .map(|expected| LayoutMismatch::PushConstants {
+                stages: expected.stage_flags,
+                offset: expected.offset,
+                size: expected.size,
+            })
+            .collect();
+        if mismatches.is_empty() { Ok(()) } else { Err(mismatches) }
+    }
+}
+
+/// Reflects the shader sources of a pipeline without creating any modules. Compiles and their
+/// reflections are shared with the pipeline through the variant cache, only new variants are built.
+pub fn reflect_shaders(shaders: &[(PathBuf, vk::ShaderStageFlags)], options: &ShaderOptions) -> Result<ShaderReflection, ShaderError> {
+    let reflections = shaders.iter()
+        .map(|(path, stage_flags)| compile_glsl(path, *stage_flags, options).map(|compiled| compiled.reflection))
+        .collect::<Result<Vec<_>, _>>()?;
+    ShaderReflection::merge(&reflections).map_err(ShaderError::Reflect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inst(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(s: &str) -> Vec<u32> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize(s.len() / 4 * 4 + 4, 0);
+        bytes.chunks(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect()
+    }
+
+    /// A shader with a uniform buffer, a sampler array, an unsized array of storage buffers,
+    /// an acceleration structure, push constants and one specialization constant.
+    fn module(execution_model: u32, sampler_set: u32) -> Vec<u32> {
+        let mut words = vec![MAGIC, 0x0001_0400, 0, 100, 0];
+        let mut entry = vec![execution_model, 30];
+        entry.extend(string("main"));
+        let mut name = vec![20];
+        name.extend(string("ENABLE_SKY"));
+        let mut camera = vec![3];
+        camera.extend(string("Camera"));
+        for (opcode, operands) in [
+            (OP_ENTRY_POINT, entry),
+            (OP_NAME, name),
+            (OP_NAME, camera),
+            (OP_DECORATE, vec![3, DECORATION_BLOCK]),
+            (OP_DECORATE, vec![5, DECORATION_DESCRIPTOR_SET, 0]),
+            (OP_DECORATE, vec![5, DECORATION_BINDING, 0]),
+            (OP_DECORATE, vec![12, DECORATION_DESCRIPTOR_SET, sampler_set]),
+            (OP_DECORATE, vec![12, DECORATION_BINDING, 2]),
+            (OP_DECORATE, vec![13, DECORATION_ARRAY_STRIDE, 16]),
+            (OP_DECORATE, vec![14, DECORATION_BLOCK]),
+            (OP_DECORATE, vec![16, DECORATION_DESCRIPTOR_SET, 0]),
+            (OP_DECORATE, vec![16, DECORATION_BINDING, 1]),
+            (OP_DECORATE, vec![17, DECORATION_BLOCK]),
+            (OP_MEMBER_DECORATE, vec![17, 0, DECORATION_OFFSET, 16]),
+            (OP_MEMBER_DECORATE, vec![17, 1, DECORATION_OFFSET, 32]),
+            (OP_DECORATE, vec![20, DECORATION_SPEC_ID, 3]),
+            (OP_DECORATE, vec![23, DECORATION_DESCRIPTOR_SET, 0]),
+            (OP_DECORATE, vec![23, DECORATION_BINDING, 3]),
+            (OP_TYPE_FLOAT, vec![1, 32]),
+            (OP_TYPE_VECTOR, vec![2, 1, 4]),
+            (OP_TYPE_STRUCT, vec![3, 2, 1]),
+            (OP_TYPE_POINTER, vec![4, STORAGE_UNIFORM, 3]),
+            (OP_VARIABLE, vec![4, 5, STORAGE_UNIFORM]),
+            (OP_TYPE_IMAGE, vec![6, 1, 1, 0, 0, 0, 1, 0]),
+            (OP_TYPE_SAMPLED_IMAGE, vec![7, 6]),
+            (OP_TYPE_INT, vec![10, 32, 0]),
+            (OP_CONSTANT, vec![10, 9, 4]),
+            (OP_TYPE_ARRAY, vec![8, 7, 9]),
+            (OP_TYPE_POINTER, vec![11, STORAGE_UNIFORM_CONSTANT, 8]),
+            (OP_VARIABLE, vec![11, 12, STORAGE_UNIFORM_CONSTANT]),
+            (OP_TYPE_RUNTIME_ARRAY, vec![13, 2]),
+            (OP_TYPE_STRUCT, vec![14, 13]),
+            (OP_TYPE_RUNTIME_ARRAY, vec![24, 14]),
+            (OP_TYPE_POINTER, vec![15, STORAGE_STORAGE_BUFFER, 24]),
+            (OP_VARIABLE, vec![15, 16, STORAGE_STORAGE_BUFFER]),
+            (OP_TYPE_STRUCT, vec![17, 2, 1]),
+            (OP_TYPE_POINTER, vec![18, STORAGE_PUSH_CONSTANT, 17]),
+            (OP_VARIABLE, vec![18, 19, STORAGE_PUSH_CONSTANT]),
+            (OP_SPEC_CONSTANT, vec![10, 20, 1]),
+            (OP_TYPE_ACCELERATION_STRUCTURE, vec![21]),
+            (OP_TYPE_POINTER, vec![22, STORAGE_UNIFORM_CONSTANT, 21]),
+            (OP_VARIABLE, vec![22, 23, STORAGE_UNIFORM_CONSTANT]),
+        ] {
+            words.extend(inst(opcode, &operands));
+        }
+        words
+    }
+
+    #[test]
+    fn reflects_bindings() {
+        let reflection = ShaderReflection::parse(&module(5313, 1)).unwrap();
+        let raygen = vk::ShaderStageFlags::RAYGEN_KHR;
+        assert_eq!(reflection.stages, raygen);
+        let bindings: Vec<_> = reflection.bindings.iter().map(|b| (b.set, b.binding, b.descriptor_type, b.count)).collect();
+        assert_eq!(bindings, vec![
+            (0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1),
+            (0, 1, vk::DescriptorType::STORAGE_BUFFER, 0),
+            (0, 3, vk::DescriptorType::ACCELERATION_STRUCTURE_KHR, 1),
+            (1, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+        ]);
+        assert_eq!(reflection.binding(0, 0).unwrap().name, "Camera");
+        let push_constants: Vec<_> = reflection.push_constants.iter().map(|r| (r.stage_flags, r.offset, r.size)).collect();
+        assert_eq!(push_constants, vec![(raygen, 16, 20)]);
+        assert_eq!(reflection.spec_constants, vec![SpecConstant { id: 3, name: "ENABLE_SKY".to_string() }]);
+        assert_eq!(ShaderReflection::parse(&[0; 5]).unwrap_err(), ReflectError::Magic(0));
+        let mut truncated = module(5313, 1);
+        truncated.pop();
+        assert_eq!(ShaderReflection::parse(&truncated).unwrap_err(), ReflectError::Truncated);
+
+        let miss = ShaderReflection::parse(&module(5317, 1)).unwrap();
+        let merged = ShaderReflection::merge(&[reflection.clone(), miss]).unwrap();
+        let both = raygen | vk::ShaderStageFlags::MISS_KHR;
+        assert!(merged.bindings.iter().all(|b| b.stages == both));
+        assert_eq!(merged.push_constants.len(), 1);
+        assert_eq!(merged.push_constants[0].stage_flags, both);
+        assert_eq!(merged.spec_constants.len(), 1);
+        // the sampler array moves to binding 2 of set 0, the storage buffer is still at 0.1
+        let mut other = ShaderReflection::parse(&module(5317, 1)).unwrap();
+        other.bindings[1].descriptor_type = vk::DescriptorType::UNIFORM_BUFFER;
+        assert_eq!(ShaderReflection::merge(&[reflection, other]).unwrap_err(), ReflectError::Conflict { set: 0, binding: 1 });
+    }
+
+    #[test]
+    fn spec_constant_arrays_are_runtime_sized() {
+        // size the sampler array with the ENABLE_SKY specialization constant instead of the literal 4
+        let mut words = module(5313, 1);
+        let array = words.windows(4).position(|w| w == [(4 << 16) | OP_TYPE_ARRAY, 8, 7, 9]).unwrap();
+        words[array + 3] = 20;
+        let reflection = ShaderReflection::parse(&words).unwrap();
+        assert_eq!(reflection.binding(1, 2).unwrap().count, 0);
+        let infos = reflection.descriptor_set_layout_infos(16);
+        assert_eq!(infos[1].bindings[&2].2, 16);
+    }
+
+    #[test]
+    fn generates_and_validates_layouts() {
+        let reflection = ShaderReflection::parse(&module(5313, 2)).unwrap();
+        let infos = reflection.descriptor_set_layout_infos(1024);
+        assert_eq!(infos.len(), 3);
+        assert!(infos[1].bindings.is_empty());
+        let raygen = vk::ShaderStageFlags::RAYGEN_KHR;
+        assert_eq!(infos[0].bindings[&1], (vk::DescriptorType::STORAGE_BUFFER, raygen, 1024));
+        assert_eq!(infos[2].bindings[&2], (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, raygen, 4));
+        for (set, info) in infos.iter().enumerate() {
+            assert!(reflection.validate_set_layout(set as u32, info).is_ok());
+        }
+
+        let all = vk::ShaderStageFlags::ALL;
+        let hand_written = DescriptorSetLayoutInfo::default()
+            .binding(0, vk::DescriptorType::STORAGE_BUFFER, all)
+            .bindings(1, vk::DescriptorType::STORAGE_BUFFER, all, 8)
+            .binding(3, vk::DescriptorType::ACCELERATION_STRUCTURE_KHR, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(reflection.validate_set_layout(0, &hand_written), Err(vec![
+            LayoutMismatch::Type {
+                set: 0,
+                binding: 0,
+                expected: vk::DescriptorType::UNIFORM_BUFFER,
+                found: vk::DescriptorType::STORAGE_BUFFER,
+            },
+            LayoutMismatch::Stages { set: 0, binding: 3, expected: raygen, found: vk::ShaderStageFlags::FRAGMENT },
+        ]));
+        let too_few = DescriptorSetLayoutInfo::default()
+            .bindings(2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, raygen, 2);
+        assert_eq!(reflection.validate_set_layout(2, &too_few), Err(vec![
+            LayoutMismatch::Count { set: 2, binding: 2, expected: 4, found: 2 },
+        ]));
+        assert_eq!(reflection.validate_set_layout(1, &too_few), Ok(()));
+        assert_eq!(reflection.validate_set_layout(2, &DescriptorSetLayoutInfo::default()), Err(vec![
+            LayoutMismatch::Missing { set: 2, binding: 2 },
+        ]));
+
+        assert!(reflection.validate_push_constants(&[vk::PushConstantRange { stage_flags: all, offset: 0, size: 64 }]).is_ok());
+        let short = vk::PushConstantRange { stage_flags: raygen, offset: 0, size: 32 };
+        assert_eq!(reflection.validate_push_constants(&[short]), Err(vec![
+            LayoutMismatch::PushConstants { stages: raygen, offset: 16, size: 20 },
+        ]));
+
+        // the miss shader reads two blocks, the layout gets a single range for it
+        let mut reflection = reflection;
+        let miss = vk::ShaderStageFlags::MISS_KHR;
+        reflection.push_constants.push(vk::PushConstantRange { stage_flags: miss, offset: 32, size: 8 });
+        reflection.push_constants.push(vk::PushConstantRange { stage_flags: miss, offset: 0, size: 8 });
+        let layout = reflection.pipeline_layout_info(&[vk::DescriptorSetLayout::null(); 3]);
+        assert_eq!(layout.desc_set_layouts.len(), 3);
+        let ranges: Vec<_> = layout.push_constant_ranges.iter().map(|r| (r.stage_flags, r.offset, r.size)).collect();
+        assert_eq!(ranges, vec![(raygen, 16, 20), (miss, 0, 40)]);
+        assert!(reflection.validate_push_constants(&layout.push_constant_ranges).is_ok());
+    }
+}
@@ -0,0 +1,987 @@
+use ash::vk;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{Context, Image2d, Resource};
+
+/// How a pass touches a resource. `layout` is ignored for buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub stage: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+    pub layout: vk::ImageLayout,
+}
+
+impl Access {
+    pub fn new(stage: vk::PipelineStageFlags, access: vk::AccessFlags, layout: vk::ImageLayout) -> Self {
+        Access { stage, access, layout }
+    }
+
+    pub fn compute_read() -> Self {
+        Self::new(vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_READ, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn compute_write() -> Self {
+        Self::new(vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn compute_sampled() -> Self {
+        Self::new(vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    pub fn fragment_sampled() -> Self {
+        Self::new(vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    pub fn vertex_read() -> Self {
+        Self::new(vk::PipelineStageFlags::VERTEX_SHADER, vk::AccessFlags::SHADER_READ, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn vertex_buffer() -> Self {
+        Self::new(vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::VERTEX_ATTRIBUTE_READ, vk::ImageLayout::UNDEFINED)
+    }
+
+    pub fn index_buffer() -> Self {
+        Self::new(vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::INDEX_READ, vk::ImageLayout::UNDEFINED)
+    }
+
+    pub fn indirect_buffer() -> Self {
+        Self::new(vk::PipelineStageFlags::DRAW_INDIRECT, vk::AccessFlags::INDIRECT_COMMAND_READ, vk::ImageLayout::UNDEFINED)
+    }
+
+    pub fn color_attachment() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )
+    }
+
+    pub fn depth_attachment() -> Self {
+        Self::new(
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        )
+    }
+
+    pub fn ray_tracing_read() -> Self {
+        Self::new(vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR, vk::AccessFlags::SHADER_READ, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn ray_tracing_write() -> Self {
+        Self::new(vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn transfer_src() -> Self {
+        Self::new(vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+    }
+
+    pub fn transfer_dst() -> Self {
+        Self::new(vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE, vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+    }
+
+    /// Final access of a swapchain image.
+    pub fn present() -> Self {
+        Self::new(vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::AccessFlags::empty(), vk::ImageLayout::PRESENT_SRC_KHR)
+    }
+
+    /// Whatever came before an imported resource, synchronized against conservatively.
+    fn external(layout: vk::ImageLayout) -> Self {
+        Self::new(vk::PipelineStageFlags::ALL_COMMANDS, vk::AccessFlags::MEMORY_WRITE, layout)
+    }
+
+    fn merge(self, other: Access) -> Access {
+        Access::new(self.stage | other.stage, self.access | other.access, self.layout)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassKind {
+    Compute,
+    Raster,
+    RayTracing,
+    Transfer,
+}
+
+impl PassKind {
+    fn stages(&self) -> vk::PipelineStageFlags {
+        type S = vk::PipelineStageFlags;
+        match self {
+            PassKind::Compute => S::COMPUTE_SHADER | S::DRAW_INDIRECT,
+            PassKind::Raster => {
+                S::DRAW_INDIRECT | S::VERTEX_INPUT | S::VERTEX_SHADER | S::TESSELLATION_CONTROL_SHADER
+                    | S::TESSELLATION_EVALUATION_SHADER | S::GEOMETRY_SHADER | S::FRAGMENT_SHADER
+                    | S::EARLY_FRAGMENT_TESTS | S::LATE_FRAGMENT_TESTS | S::COLOR_ATTACHMENT_OUTPUT
+            }
+            PassKind::RayTracing => S::RAY_TRACING_SHADER_KHR | S::DRAW_INDIRECT,
+            PassKind::Transfer => S::TRANSFER,
+        }
+    }
+
+    pub fn bind_point(&self) -> Option<vk::PipelineBindPoint> {
+        match self {
+            PassKind::Compute => Some(vk::PipelineBindPoint::COMPUTE),
+            PassKind::Raster => Some(vk::PipelineBindPoint::GRAPHICS),
+            PassKind::RayTracing => Some(vk::PipelineBindPoint::RAY_TRACING_KHR),
+            PassKind::Transfer => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(usize);
+
+/// A transient 2d image owned by the graph. The usage flags the passes need are added during compilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub usage: vk::ImageUsageFlags,
+}
+
+impl ImageDesc {
+    pub fn new(format: vk::Format, extent: vk::Extent2D) -> Self {
+        ImageDesc { format, extent, usage: vk::ImageUsageFlags::empty() }
+    }
+
+    pub fn usage(mut self, usage: vk::ImageUsageFlags) -> Self {
+        self.usage |= usage;
+        self
+    }
+
+    fn aspect(&self) -> vk::ImageAspectFlags {
+        aspect_of(self.format)
+    }
+}
+
+fn aspect_of(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => vk::ImageAspectFlags::DEPTH,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+fn usage_of(layout: vk::ImageLayout) -> vk::ImageUsageFlags {
+    match layout {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::ImageUsageFlags::SAMPLED,
+        vk::ImageLayout::GENERAL => vk::ImageUsageFlags::STORAGE,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::ImageUsageFlags::TRANSFER_SRC,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::ImageUsageFlags::TRANSFER_DST,
+        _ => vk::ImageUsageFlags::empty(),
+    }
+}
+
+const WRITE_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw()
+        | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+);
+
+#[derive(Debug)]
+pub enum RenderGraphError {
+    /// A pass used one image in two layouts.
+    LayoutConflict { pass: String, resource: String },
+    /// A pass declared a stage its kind doesn't run.
+    InvalidStage { pass: String, resource: String, stage: vk::PipelineStageFlags },
+    UnknownResource(ResourceId),
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for RenderGraphError {}
+
+#[derive(Debug, Clone, Copy)]
+enum ResourceSource {
+    Buffer {
+        buffer: vk::Buffer,
+        final_access: Option<Access>,
+    },
+    Image {
+        image: vk::Image,
+        view: vk::ImageView,
+        format: vk::Format,
+        layout: vk::ImageLayout,
+        final_access: Option<Access>,
+    },
+    Transient(ImageDesc),
+}
+
+#[derive(Debug, Clone)]
+struct ResourceEntry {
+    name: String,
+    source: ResourceSource,
+}
+
+impl ResourceEntry {
+    fn is_image(&self) -> bool {
+        !matches!(self.source, ResourceSource::Buffer { .. })
+    }
+}
+
+pub type RecordFn<'a> = Box<dyn FnOnce(vk::CommandBuffer, &PassResources) + 'a>;
+
+pub struct Pass<'a> {
+    name: String,
+    kind: PassKind,
+    reads: Vec<(ResourceId, Access)>,
+    writes: Vec<(ResourceId, Access)>,
+    side_effect: bool,
+    record: Option<RecordFn<'a>>,
+}
+
+impl<'a> Pass<'a> {
+    pub fn read(&mut self, resource: ResourceId, access: Access) -> &mut Self {
+        self.reads.push((resource, access));
+        self
+    }
+
+    /// A pass that reads and writes a resource declares both.
+    pub fn write(&mut self, resource: ResourceId, access: Access) -> &mut Self {
+        self.writes.push((resource, access));
+        self
+    }
+
+    /// Keeps the pass even if nothing reads what it writes, e.g. readbacks to the host.
+    pub fn side_effect(&mut self) -> &mut Self {
+        self.side_effect = true;
+        self
+    }
+
+    pub fn record(&mut self, record: impl FnOnce(vk::CommandBuffer, &PassResources) + 'a) -> &mut Self {
+        self.record = Some(Box::new(record));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> PassKind {
+        self.kind
+    }
+
+    /// One access per resource, a read and a write of the same resource are merged.
+    fn accesses(&self) -> Result<Vec<(ResourceId, Access)>, (ResourceId, Access)> {
+        let mut merged: Vec<(ResourceId, Access)> = Vec::new();
+        for &(id, access) in self.reads.iter().chain(self.writes.iter()) {
+            match merged.iter_mut().find(|(other, _)| *other == id) {
+                Some((_, existing)) if existing.layout != access.layout => return Err((id, access)),
+                Some((_, existing)) => *existing = existing.merge(access),
+                None => merged.push((id, access)),
+            }
+        }
+        Ok(merged)
+    }
+}
+
+/// Passes recorded in declaration order, with the barriers between them derived from what each pass
+/// reads and writes. Passes whose results are never used are dropped, and transient images with
+/// matching descriptions and disjoint lifetimes share one image. Build a new graph every frame.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceEntry>,
+    passes: Vec<Pass<'a>>,
+}
+
+/// Image layout change or hazard on one image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageBarrier {
+    pub resource: ResourceId,
+    pub src_access: vk::AccessFlags,
+    pub dst_access: vk::AccessFlags,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+/// Everything that has to happen before a pass, issued as one `vkCmdPipelineBarrier`.
+/// Buffers share a single global memory barrier.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassBarriers {
+    pub src_stage: vk::PipelineStageFlags,
+    pub dst_stage: vk::PipelineStageFlags,
+    pub memory: Option<(vk::AccessFlags, vk::AccessFlags)>,
+    pub images: Vec<ImageBarrier>,
+}
+
+impl PassBarriers {
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.images.is_empty()
+    }
+
+    fn add(&mut self, src: vk::PipelineStageFlags, dst: vk::PipelineStageFlags) {
+        self.src_stage |= src;
+        self.dst_stage |= dst;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledPass {
+    pub pass: usize,
+    pub name: String,
+    pub barriers: PassBarriers,
+}
+
+/// Result of `RenderGraph::compile`, passes that were culled don't show up.
+#[derive(Debug, Clone)]
+pub struct CompiledGraph {
+    pub passes: Vec<CompiledPass>,
+    /// Transitions of imported images to their final access.
+    pub final_barriers: PassBarriers,
+    /// Physical images backing the transient resources.
+    pub slots: Vec<ImageDesc>,
+    /// Slot of every transient resource that survived culling.
+    pub slot_of: Vec<Option<usize>>,
+    /// What the last tenant of every slot did to it, a later graph reusing the image waits for it.
+    pub slot_last_access: Vec<Access>,
+}
+
+impl CompiledGraph {
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    /// Makes the first use of `slot` wait for `previous`, the last access of its image by an
+    /// earlier graph. The compiled barriers assume the image is fresh.
+    pub fn wait_for(&mut self, slot: usize, previous: Access) {
+        let slot_of = &self.slot_of;
+        for pass in &mut self.passes {
+            let first = pass.barriers.images.iter_mut().find(|b| slot_of.get(b.resource.0).copied().flatten() == Some(slot));
+            if let Some(barrier) = first {
+                barrier.src_access |= previous.access & WRITE_ACCESS;
+                pass.barriers.src_stage |= previous.stage;
+                return;
+            }
+        }
+    }
+}
+
+/// State of one physical resource while walking the passes.
+#[derive(Debug, Clone, Copy)]
+struct ResourceState {
+    layout: vk::ImageLayout,
+    last_write: Option<Access>,
+    read_stage: vk::PipelineStageFlags,
+    read_access: vk::AccessFlags,
+}
+
+impl ResourceState {
+    fn new(last_write: Option<Access>, layout: vk::ImageLayout) -> Self {
+        ResourceState {
+            layout,
+            last_write,
+            read_stage: vk::PipelineStageFlags::empty(),
+            read_access: vk::AccessFlags::empty(),
+        }
+    }
+
+    /// Adds whatever is needed before `access` to `barriers` and moves the state past it.
+    fn access(&mut self, id: ResourceId, is_image: bool, access: Access, barriers: &mut PassBarriers) {
+        let writes = access.access.intersects(WRITE_ACCESS);
+        let transition = is_image && self.layout != access.layout;
+        let (write_stage, write_access) = self.last_write.map_or(
+            (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty()),
+            |w| (w.stage, w.access),
+        );
+
+        if writes || transition {
+            // wait for the last write and every read since, the reads only need an execution dependency
+            let src_stage = write_stage | self.read_stage;
+            if is_image {
+                barriers.images.push(ImageBarrier {
+                    resource: id,
+                    src_access: write_access,
+                    dst_access: access.access,
+                    old_layout: self.layout,
+                    new_layout: access.layout,
+                });
+                barriers.add(src_stage, access.stage);
+            } else if !src_stage.is_empty() {
+                Self::add_memory(barriers, write_access, access.access);
+                barriers.add(src_stage, access.stage);
+            }
+            if writes {
+                let write = Access::new(access.stage, access.access & WRITE_ACCESS, access.layout);
+                *self = ResourceState::new(Some(write), access.layout);
+            } else {
+                // later reads chain through the transition
+                self.last_write = Some(Access::new(write_stage | access.stage, write_access, access.layout));
+                self.layout = access.layout;
+                self.read_stage = access.stage;
+                self.read_access = access.access;
+            }
+            return;
+        }
+
+        let covered = self.read_stage.contains(access.stage) && self.read_access.contains(access.access);
+        if self.last_write.is_some() && !covered {
+            if is_image {
+                barriers.images.push(ImageBarrier {
+                    resource: id,
+                    src_access: write_access,
+                    dst_access: access.access,
+                    old_layout: self.layout,
+                    new_layout: access.layout,
+                });
+            } else {
+                Self::add_memory(barriers, write_access, access.access);
+            }
+            barriers.add(write_stage, access.stage);
+        }
+        self.read_stage |= access.stage;
+        self.read_access |= access.access;
+    }
+
+    fn add_memory(barriers: &mut PassBarriers, src: vk::AccessFlags, dst: vk::AccessFlags) {
+        let (s, d) = barriers.memory.unwrap_or_default();
+        barriers.memory = Some((s | src, d | dst));
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_resource(&mut self, name: &str, source: ResourceSource) -> ResourceId {
+        self.resources.push(ResourceEntry { name: name.to_string(), source });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Buffers are synchronized against whatever used them before the graph, `final_access` is
+    /// what uses them after it.
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer, final_access: Option<Access>) -> ResourceId {
+        self.add_resource(name, ResourceSource::Buffer { buffer, final_access })
+    }
+
+    /// `layout` is the layout the image is in when the graph starts, `final_access` what it's
+    /// transitioned to at the end, e.g. `Access::present()` for swapchain images.
+    pub fn import_image(&mut self, name: &str, image: &Image2d, layout: vk::ImageLayout, final_access: Option<Access>) -> ResourceId {
+        self.add_resource(name, ResourceSource::Image {
+            image: image.handle(),
+            view: image.get_image_view(),
+            format: image.get_format(),
+            layout,
+            final_access,
+        })
+    }
+
+    /// An image that only lives during the graph, its content is undefined at its first use.
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ResourceId {
+        self.add_resource(name, ResourceSource::Transient(desc))
+    }
+
+    pub fn add_pass(&mut self, name: &str, kind: PassKind) -> &mut Pass<'a> {
+        self.passes.push(Pass {
+            name: name.to_string(),
+            kind,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            side_effect: false,
+            record: None,
+        });
+        self.passes.last_mut().unwrap()
+    }
+
+    fn resource(&self, id: ResourceId) -> Result<&ResourceEntry, RenderGraphError> {
+        self.resources.get(id.0).ok_or(RenderGraphError::UnknownResource(id))
+    }
+
+    /// A pass is kept when it has side effects, writes an imported resource, or writes something
+    /// a kept pass reads later.
+    fn cull(&self) -> Vec<bool> {
+        let mut needed = vec![false; self.resources.len()];
+        let mut keep = vec![false; self.passes.len()];
+        for (i, pass) in self.passes.iter().enumerate().rev() {
+            keep[i] = pass.side_effect
+                || pass.writes.iter().any(|(id, _)| {
+                    needed.get(id.0).copied().unwrap_or(false)
+                        || !matches!(self.resources.get(id.0).map(|r| r.source), Some(ResourceSource::Transient(_)))
+                });
+            if keep[i] {
+                for (id, _) in &pass.reads {
+                    if let Some(n) = needed.get_mut(id.0) {
+                        *n = true;
+                    }
+                }
+            }
+        }
+        keep
+    }
+
+    pub fn compile(&self) -> Result<CompiledGraph, RenderGraphError> {
+        let keep = self.cull();
+        let mut kept = Vec::new();
+        for (i, pass) in self.passes.iter().enumerate().filter(|(i, _)| keep[*i]) {
+            let accesses = pass.accesses().map_err(|(id, _)| RenderGraphError::LayoutConflict {
+                pass: pass.name.clone(),
+                resource: self.resources.get(id.0).map_or(String::new(), |r| r.name.clone()),
+            })?;
+            for &(id, access) in &accesses {
+                let resource = self.resource(id)?;
+                if !pass.kind.stages().contains(access.stage) {
+                    return Err(RenderGraphError::InvalidStage {
+                        pass: pass.name.clone(),
+                        resource: resource.name.clone(),
+                        stage: access.stage,
+                    });
+                }
+            }
+            kept.push((i, accesses));
+        }
+
+        // lifetimes and usage of the transients in compiled order
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        let mut descs: Vec<Option<ImageDesc>> = self.resources.iter()
+            .map(|r| match r.source {
+                ResourceSource::Transient(desc) => Some(desc),
+                _ => None,
+            })
+            .collect();
+        for (order, (_, accesses)) in kept.iter().enumerate() {
+            for &(id, access) in accesses {
+                if let Some(desc) = descs[id.0].as_mut() {
+                    desc.usage |= usage_of(access.layout);
+                    let lifetime = lifetimes[id.0].get_or_insert((order, order));
+                    lifetime.1 = order;
+                }
+            }
+        }
+
+        // greedy reuse of images, in order of first use
+        let mut transients: Vec<usize> = (0..self.resources.len()).filter(|i| lifetimes[*i].is_some()).collect();
+        transients.sort_by_key(|i| lifetimes[*i].unwrap().0);
+        let mut slots: Vec<ImageDesc> = Vec::new();
+        let mut slot_keys: Vec<ImageDesc> = Vec::new();
+        let mut slot_end: Vec<usize> = Vec::new();
+        let mut slot_of = vec![None; self.resources.len()];
+        for i in transients {
+            let (first, last) = lifetimes[i].unwrap();
+            // match on the declared description, the slot gets the usage of all its tenants
+            let key = match self.resources[i].source {
+                ResourceSource::Transient(desc) => desc,
+                _ => unreachable!(),
+            };
+            let slot = (0..slots.len()).find(|s| slot_keys[*s] == key && slot_end[*s] < first);
+            let slot = slot.unwrap_or_else(|| {
+                slots.push(key);
+                slot_keys.push(key);
+                slot_end.push(0);
+                slots.len() - 1
+            });
+            slots[slot].usage |= descs[i].unwrap().usage;
+            slot_end[slot] = last;
+            slot_of[i] = Some(slot);
+        }
+
+        // states of imported resources come first, then one per slot
+        let physical = |id: ResourceId| slot_of[id.0].map_or(id.0, |slot| self.resources.len() + slot);
+        let mut states: Vec<ResourceState> = self.resources.iter()
+            .map(|r| match r.source {
+                ResourceSource::Buffer { .. } => ResourceState::new(Some(Access::external(vk::ImageLayout::UNDEFINED)), vk::ImageLayout::UNDEFINED),
+                ResourceSource::Image { layout, .. } => ResourceState::new(Some(Access::external(layout)), layout),
+                ResourceSource::Transient(_) => ResourceState::new(None, vk::ImageLayout::UNDEFINED),
+            })
+            .collect();
+        states.extend(slots.iter().map(|_| ResourceState::new(None, vk::ImageLayout::UNDEFINED)));
+
+        let mut passes = Vec::new();
+        for (order, (i, accesses)) in kept.iter().enumerate() {
+            let mut barriers = PassBarriers::default();
+            for &(id, access) in accesses {
+                let state = &mut states[physical(id)];
+                if lifetimes[id.0].is_some_and(|(first, _)| first == order) {
+                    // a new tenant of the slot doesn't care about the previous content
+                    state.layout = vk::ImageLayout::UNDEFINED;
+                }
+                state.access(id, self.resources[id.0].is_image(), access, &mut barriers);
+            }
+            passes.push(CompiledPass { pass: *i, name: self.passes[*i].name.clone(), barriers });
+        }
+
+        let mut final_barriers = PassBarriers::default();
+        for (i, resource) in self.resources.iter().enumerate() {
+            match resource.source {
+                ResourceSource::Buffer { final_access: Some(access), .. } | ResourceSource::Image { final_access: Some(access), .. } => {
+                    states[i].access(ResourceId(i), resource.is_image(), access, &mut final_barriers);
+                }
+                _ => {}
+            }
+        }
+
+        let slot_last_access = states[self.resources.len()..].iter()
+            .map(|state| {
+                let (stage, access) = state.last_write.map_or(
+                    (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty()),
+                    |w| (w.stage, w.access),
+                );
+                Access::new(stage | state.read_stage, access, state.layout)
+            })
+            .collect();
+
+        Ok(CompiledGraph { passes, final_barriers, slots, slot_of, slot_last_access })
+    }
+
+    /// Records every pass that survives culling into `cmd`. Transient images are taken from `transients`,
+    /// keep it around between frames so the images are reused. A reused image waits for its last use
+    /// by the previous graph, so graphs sharing `transients` have to be submitted to the same queue.
+    pub fn execute(mut self, context: &Arc<Context>, cmd: vk::CommandBuffer, transients: &mut TransientImages) -> Result<CompiledGraph, RenderGraphError> {
+        let mut compiled = self.compile()?;
+        let images = transients.acquire(context, &compiled.slots);
+        for (slot, &index) in images.iter().enumerate() {
+            if let Some(previous) = transients.images[index].2 {
+                compiled.wait_for(slot, previous);
+            }
+        }
+
+        let resources: Vec<PhysicalResource> = self.resources.iter().enumerate()
+            .map(|(i, r)| match r.source {
+                ResourceSource::Buffer { buffer, .. } => PhysicalResource::Buffer(buffer),
+                ResourceSource::Image { image, view, format, .. } => PhysicalResource::Image { image, view, aspect: aspect_of(format) },
+                ResourceSource::Transient(desc) => match compiled.slot_of[i] {
+                    Some(slot) => {
+                        let image = &transients.images[images[slot]].1;
+                        PhysicalResource::Image { image: image.handle(), view: image.get_image_view(), aspect: desc.aspect() }
+                    }
+                    None => PhysicalResource::Culled,
+                },
+            })
+            .collect();
+
+        for compiled_pass in &compiled.passes {
+            cmd_barriers(context, cmd, &compiled_pass.barriers, &resources);
+            let pass = &mut self.passes[compiled_pass.pass];
+            let pass_resources = PassResources {
+                kind: pass.kind,
+                resources: &resources,
+                accesses: pass.accesses().unwrap_or_default(),
+            };
+            if let Some(record) = pass.record.take() {
+                record(cmd, &pass_resources);
+            }
+        }
+        cmd_barriers(context, cmd, &compiled.final_barriers, &resources);
+        for (slot, &index) in images.iter().enumerate() {
+            transients.images[index].2 = Some(compiled.slot_last_access[slot]);
+        }
+        Ok(compiled)
+    }
+}
+
+fn cmd_barriers(context: &Arc<Context>, cmd: vk::CommandBuffer, barriers: &PassBarriers, resources: &[PhysicalResource]) {
+    if barriers.is_empty() {
+        return;
+    }
+    let memory: Vec<vk::MemoryBarrier> = barriers.memory.iter()
+        .map(|(src, dst)| vk::MemoryBarrier::builder().src_access_mask(*src).dst_access_mask(*dst).build())
+        .collect();
+    let images: Vec<vk::ImageMemoryBarrier> = barriers.images.iter()
+        .filter_map(|b| match resources[b.resource.0] {
+            PhysicalResource::Image { image, aspect, .. } => Some(
+                vk::ImageMemoryBarrier::builder()
+                    .image(image)
+                    .src_access_mask(b.src_access)
+                    .dst_access_mask(b.dst_access)
+                    .old_layout(b.old_layout)
+                    .new_layout(b.new_layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(
+                        vk::ImageSubresourceRange::builder()
+                            .aspect_mask(aspect)
+                            .level_count(vk::REMAINING_MIP_LEVELS)
+                            .layer_count(vk::REMAINING_ARRAY_LAYERS)
+                            .build(),
+                    )
+                    .build(),
+            ),
+            _ => None,
+        })
+        .collect();
+    let src_stage = if barriers.src_stage.is_empty() { vk::PipelineStageFlags::TOP_OF_PIPE } else { barriers.src_stage };
+    unsafe {
+        context.device().cmd_pipeline_barrier(
+            cmd,
+            src_stage,
+            barriers.dst_stage,
+            vk::DependencyFlags::empty(),
+            &memory,
+            &[],
+            &images,
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PhysicalResource {
+    Buffer(vk::Buffer),
+    Image { image: vk::Image, view: vk::ImageView, aspect: vk::ImageAspectFlags },
+    Culled,
+}
+
+/// What a pass sees of the graph's resources while recording.
+pub struct PassResources<'r> {
+    kind: PassKind,
+    resources: &'r [PhysicalResource],
+    accesses: Vec<(ResourceId, Access)>,
+}
+
+impl PassResources<'_> {
+    pub fn kind(&self) -> PassKind {
+        self.kind
+    }
+
+    pub fn buffer(&self, id: ResourceId) -> vk::Buffer {
+        match self.resources.get(id.0) {
+            Some(PhysicalResource::Buffer(buffer)) => *buffer,
+            _ => vk::Buffer::null(),
+        }
+    }
+
+    pub fn image(&self, id: ResourceId) -> vk::Image {
+        match self.resources.get(id.0) {
+            Some(PhysicalResource::Image { image, .. }) => *image,
+            _ => vk::Image::null(),
+        }
+    }
+
+    pub fn view(&self, id: ResourceId) -> vk::ImageView {
+        match self.resources.get(id.0) {
+            Some(PhysicalResource::Image { view, .. }) => *view,
+            _ => vk::ImageView::null(),
+        }
+    }
+
+    /// Layout the pass declared for the image, for descriptor writes.
+    pub fn layout(&self, id: ResourceId) -> vk::ImageLayout {
+        self.accesses.iter().find(|(other, _)| *other == id).map_or(vk::ImageLayout::UNDEFINED, |(_, a)| a.layout)
+    }
+}
+
+/// Images backing the transient resources, reused across frames while the descriptions match.
+/// Each image keeps the last access of the graph that used it.
+#[derive(Default)]
+pub struct TransientImages {
+    images: Vec<(ImageDesc, Image2d, Option<Access>)>,
+}
+
+impl TransientImages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Drops the images, e.g. after a resize. The device must be done with them.
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    /// Index into `images` for every slot, creating what's missing.
+    fn acquire(&mut self, context: &Arc<Context>, slots: &[ImageDesc]) -> Vec<usize> {
+        let mut used = vec![false; self.images.len()];
+        let mut indices = Vec::with_capacity(slots.len());
+        for (slot, desc) in slots.iter().enumerate() {
+            let found = (0..self.images.len()).find(|i| !used[*i] && self.images[*i].0 == *desc);
+            let index = found.unwrap_or_else(|| {
+                let image_info = vk::ImageCreateInfo::builder()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(desc.format)
+                    .extent(vk::Extent3D { width: desc.extent.width, height: desc.extent.height, depth: 1 })
+                    .mip_levels(1)
+                    .array_layers(1)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(desc.usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .build();
+                let image = Image2d::new(context.shared().clone(), &image_info, desc.aspect(), 1, &format!("transient {}", slot));
+                self.images.push((*desc, image, None));
+                used.push(false);
+                self.images.len() - 1
+            });
+            used[index] = true;
+            indices.push(index);
+        }
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent() -> vk::Extent2D {
+        vk::Extent2D { width: 64, height: 64 }
+    }
+
+    fn with_output<'a>() -> (RenderGraph<'a>, ResourceId) {
+        let mut graph = RenderGraph::new();
+        let output = graph.add_resource("output", ResourceSource::Image {
+            image: vk::Image::null(),
+            view: vk::ImageView::null(),
+            format: vk::Format::B8G8R8A8_UNORM,
+            layout: vk::ImageLayout::UNDEFINED,
+            final_access: Some(Access::present()),
+        });
+        (graph, output)
+    }
+
+    #[test]
+    fn culls_unused_passes() {
+        let (mut graph, output) = with_output();
+        let hdr = graph.create_image("hdr", ImageDesc::new(vk::Format::R16G16B16A16_SFLOAT, extent()));
+        let unused = graph.create_image("unused", ImageDesc::new(vk::Format::R8_UNORM, extent()));
+        graph.add_pass("trace", PassKind::RayTracing).write(hdr, Access::ray_tracing_write());
+        graph.add_pass("debug", PassKind::Compute).read(hdr, Access::compute_read()).write(unused, Access::compute_write());
+        graph.add_pass("tonemap", PassKind::Raster).read(hdr, Access::fragment_sampled()).write(output, Access::color_attachment());
+        graph.add_pass("stats", PassKind::Compute).read(hdr, Access::compute_read()).side_effect();
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.pass_names(), vec!["trace", "tonemap", "stats"]);
+        assert_eq!(compiled.slot_of[unused.0], None);
+        assert_eq!(compiled.slots.len(), 1);
+        assert!(compiled.slots[0].usage.contains(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED));
+    }
+
+    #[test]
+    fn derives_barriers() {
+        let (mut graph, output) = with_output();
+        let hdr = graph.create_image("hdr", ImageDesc::new(vk::Format::R16G16B16A16_SFLOAT, extent()));
+        graph.add_pass("trace", PassKind::RayTracing).write(hdr, Access::ray_tracing_write());
+        graph.add_pass("tonemap", PassKind::Raster).read(hdr, Access::fragment_sampled()).write(output, Access::color_attachment());
+        graph.add_pass("overlay", PassKind::Raster).read(hdr, Access::fragment_sampled()).write(output, Access::color_attachment());
+        let compiled = graph.compile().unwrap();
+
+        // first use of a transient only transitions from undefined
+        let trace = &compiled.passes[0].barriers;
+        assert_eq!(trace.images, vec![ImageBarrier {
+            resource: hdr,
+            src_access: vk::AccessFlags::empty(),
+            dst_access: vk::AccessFlags::SHADER_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::GENERAL,
+        }]);
+
+        let tonemap = &compiled.passes[1].barriers;
+        assert!(tonemap.src_stage.contains(vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR));
+        assert!(tonemap.dst_stage.contains(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT));
+        let hdr_barrier = tonemap.images.iter().find(|b| b.resource == hdr).unwrap();
+        assert_eq!((hdr_barrier.old_layout, hdr_barrier.new_layout), (vk::ImageLayout::GENERAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+        assert_eq!((hdr_barrier.src_access, hdr_barrier.dst_access), (vk::AccessFlags::SHADER_WRITE, vk::AccessFlags::SHADER_READ));
+
+        // hdr was already read in that layout, only the attachment write after write is left
+        let overlay = &compiled.passes[2].barriers;
+        assert_eq!(overlay.images.len(), 1);
+        assert_eq!(overlay.images[0].resource, output);
+        assert_eq!(overlay.images[0].old_layout, overlay.images[0].new_layout);
+
+        assert_eq!(compiled.final_barriers.images[0].new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+
+    #[test]
+    fn buffers_use_memory_barriers() {
+        let mut graph = RenderGraph::new();
+        let keys = graph.import_buffer("keys", vk::Buffer::null(), Some(Access::vertex_buffer()));
+        graph.add_pass("histogram", PassKind::Compute).read(keys, Access::compute_read()).write(keys, Access::compute_write());
+        graph.add_pass("scatter", PassKind::Compute).read(keys, Access::compute_read()).write(keys, Access::compute_write());
+        let compiled = graph.compile().unwrap();
+        let scatter = &compiled.passes[1].barriers;
+        assert!(scatter.images.is_empty());
+        assert_eq!(scatter.memory, Some((vk::AccessFlags::SHADER_WRITE, vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)));
+        assert_eq!(scatter.src_stage, vk::PipelineStageFlags::COMPUTE_SHADER);
+        assert_eq!(compiled.final_barriers.memory, Some((vk::AccessFlags::SHADER_WRITE, vk::AccessFlags::VERTEX_ATTRIBUTE_READ)));
+        assert_eq!(compiled.final_barriers.dst_stage, vk::PipelineStageFlags::VERTEX_INPUT);
+    }
+
+    #[test]
+    fn reuses_transients() {
+        let (mut graph, output) = with_output();
+        let desc = ImageDesc::new(vk::Format::R16G16B16A16_SFLOAT, extent());
+        let a = graph.create_image("a", desc);
+        let b = graph.create_image("b", desc);
+        let c = graph.create_image("c", desc);
+        let depth = graph.create_image("depth", ImageDesc::new(vk::Format::D32_SFLOAT, extent()));
+        graph.add_pass("gbuffer", PassKind::Raster).write(a, Access::color_attachment()).write(depth, Access::depth_attachment());
+        graph.add_pass("blur x", PassKind::Compute).read(a, Access::compute_sampled()).write(b, Access::compute_write());
+        graph.add_pass("blur y", PassKind::Compute).read(b, Access::compute_sampled()).write(c, Access::compute_write());
+        graph.add_pass("composite", PassKind::Raster)
+            .read(c, Access::fragment_sampled())
+            .read(depth, Access::fragment_sampled())
+            .write(output, Access::color_attachment());
+        let compiled = graph.compile().unwrap();
+
+        // c can take a's image, b overlaps both
+        assert_eq!(compiled.slot_of[c.0], compiled.slot_of[a.0]);
+        assert_ne!(compiled.slot_of[b.0], compiled.slot_of[a.0]);
+        assert_ne!(compiled.slot_of[depth.0], compiled.slot_of[a.0]);
+        assert_eq!(compiled.slots.len(), 3);
+        // a is an attachment and c is storage, their slot is both
+        let usage = compiled.slots[compiled.slot_of[a.0].unwrap()].usage;
+        assert!(usage.contains(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE));
+
+        // c starts from undefined but waits for a's last read
+        let blur_y = &compiled.passes[2].barriers;
+        let c_barrier = blur_y.images.iter().find(|b| b.resource == c).unwrap();
+        assert_eq!(c_barrier.old_layout, vk::ImageLayout::UNDEFINED);
+        assert!(blur_y.src_stage.contains(vk::PipelineStageFlags::COMPUTE_SHADER));
+    }
+
+    #[test]
+    fn waits_for_previous_graph() {
+        let build = || {
+            let (mut graph, output) = with_output();
+            let hdr = graph.create_image("hdr", ImageDesc::new(vk::Format::R16G16B16A16_SFLOAT, extent()));
+            graph.add_pass("trace", PassKind::RayTracing).write(hdr, Access::ray_tracing_write());
+            graph.add_pass("tonemap", PassKind::Raster).read(hdr, Access::fragment_sampled()).write(output, Access::color_attachment());
+            (graph.compile().unwrap(), hdr)
+        };
+        let (previous, _) = build();
+        let last = previous.slot_last_access[0];
+        assert_eq!(last.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert!(last.stage.contains(vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR | vk::PipelineStageFlags::FRAGMENT_SHADER));
+        assert_eq!(last.access, vk::AccessFlags::SHADER_WRITE);
+
+        // writing the image again has to wait for last frame's trace and tonemap
+        let (mut compiled, hdr) = build();
+        compiled.wait_for(0, last);
+        let trace = &compiled.passes[0].barriers;
+        assert!(trace.src_stage.contains(last.stage));
+        let barrier = trace.images.iter().find(|b| b.resource == hdr).unwrap();
+        assert_eq!(barrier.src_access, vk::AccessFlags::SHADER_WRITE);
+        assert_eq!(barrier.old_layout, vk::ImageLayout::UNDEFINED);
+    }
+
+    #[test]
+    fn rejects_invalid_passes() {
+        let (mut graph, output) = with_output();
+        graph.add_pass("bad", PassKind::Compute).write(output, Access::color_attachment());
+        assert!(matches!(graph.compile(), Err(RenderGraphError::InvalidStage { .. })));
+
+        let (mut graph, output) = with_output();
+        graph.add_pass("bad", PassKind::Compute).read(output, Access::compute_sampled()).write(output, Access::compute_write());
+        assert!(matches!(graph.compile(), Err(RenderGraphError::LayoutConflict { .. })));
+    }
+}
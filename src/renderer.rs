@@ -1,6 +1,7 @@
 use crate::*;
 //use crate::resource::ResourceManager;
 use ash::vk;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::{ffi::CStr, mem::ManuallyDrop};
 
@@ -29,6 +30,9 @@ pub struct RendererSettings {
     pub extensions: Vec<&'static CStr>,
     pub device_extensions: Vec<&'static CStr>,
     pub debug: bool,
+    /// File the Vulkan pipeline cache is kept in between runs, None keeps it in memory.
+    /// Every application needs a path of its own, e.g. in its config or cache directory.
+    pub pipeline_cache: Option<PathBuf>,
 }
 
 impl Default for RendererSettings {
@@ -42,6 +46,7 @@ impl Default for RendererSettings {
             extensions: Vec::new(),
             device_extensions: Vec::new(),
             debug: true,
+            pipeline_cache: None,
         }
     }
 }
@@ -74,6 +79,7 @@ impl AppRenderer {
             let context = Arc::new(Context::new(
                 shared_context.clone(),
                 swapchain.get_image_count(),
+                render_settings.pipeline_cache.clone(),
             ));
             swapchain.transition_depth_images(&context);
             let renderpass = swapchain.create_compatible_render_pass();
@@ -1,4 +1,4 @@
-use std::{path::PathBuf, cmp::max, self};
+use std::{path::{Path, PathBuf}, cmp::max, self};
 use ash::vk;
 use image::{ImageError, GenericImageView, DynamicImage};
 use rayon::prelude::*;
@@ -242,14 +242,14 @@ impl<T: num::Num> Image<T> {
 }
 
 
-pub fn load_images_par<T: num::Num>(paths: &Vec<String>) -> Vec<Image<T>> {
+pub fn load_images_par<T: num::Num>(paths: &[impl AsRef<Path> + Sync]) -> Vec<Image<T>> {
     let mut images: Vec<Image<T>> = Vec::new();
     paths.into_par_iter().map(|path| {
-        let res = Image::<T>::new(PathBuf::from(path));
+        let res = Image::<T>::new(path.as_ref().to_path_buf());
         let image = match res {
             Ok(i) => i,
             Err(e) => {
-                println!("Failed to load image: {}\n{}", path, e);
+                println!("Failed to load image: {}\n{}", path.as_ref().display(), e);
                 return Image::<T>::new(PathBuf::from("assets/textures/missing.png")).unwrap();
             }
         };
@@ -264,7 +264,7 @@ pub fn load_images<T: num::Num>(base_path: &Option<String>, image_paths: &Vec<St
         Some(base) => {
             let paths = image_paths.iter().map(|path| {
                 base.clone() + &path.clone()
-            }).collect();
+            }).collect::<Vec<_>>();
             load_images_par::<T>(&paths)
         },
         None => {
@@ -100,7 +100,7 @@ impl<T: num::Num> Material<T> {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct MaterialInfo {
     pub color: glam::Vec3,
     pub displacement_scale: f32,
@@ -0,0 +1,208 @@
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+/// Axis aligned bounding box, empty boxes have `min > max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::splat(f32::INFINITY),
+        max: Vec3::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| Aabb {
+            min: aabb.min.min(p),
+            max: aabb.max.max(p),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        0.5 * (self.max - self.min)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Box around the transformed box, Arvo's method.
+    pub fn transform(&self, matrix: &Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = matrix.transform_point3(self.center());
+        let half = self.half_extents();
+        let extent = matrix.x_axis.xyz().abs() * half.x
+            + matrix.y_axis.xyz().abs() * half.y
+            + matrix.z_axis.xyz().abs() * half.z;
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// Scales the radius by the largest axis scale, so the result still holds everything.
+    pub fn transform(&self, matrix: &Mat4) -> Sphere {
+        let scale = matrix.x_axis.xyz().length()
+            .max(matrix.y_axis.xyz().length())
+            .max(matrix.z_axis.xyz().length());
+        Sphere {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Box and sphere around the same points. The sphere is centered on the box, which is
+/// tighter than the box's circumsphere for most meshes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: Sphere,
+}
+
+impl Bounds {
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let aabb = Aabb::from_points(points.iter().copied());
+        if aabb.is_empty() {
+            return Self::default();
+        }
+        let center = aabb.center();
+        let radius = points.iter().map(|p| p.distance_squared(center)).fold(0.0, f32::max).sqrt();
+        Bounds { aabb, sphere: Sphere { center, radius } }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aabb.is_empty()
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let aabb = self.aabb.union(&other.aabb);
+        let center = aabb.center();
+        let radius = (self.sphere.center.distance(center) + self.sphere.radius)
+            .max(other.sphere.center.distance(center) + other.sphere.radius);
+        Bounds { aabb, sphere: Sphere { center, radius } }
+    }
+
+    pub fn transform(&self, matrix: &Mat4) -> Bounds {
+        if self.is_empty() {
+            return *self;
+        }
+        Bounds {
+            aabb: self.aabb.transform(matrix),
+            sphere: self.sphere.transform(matrix),
+        }
+    }
+}
+
+/// Planes of a view frustum as `(normal, distance)` with normals pointing inside,
+/// for projections with a `[0, 1]` depth range like `Mat4::perspective_rh`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Gribb and Hartmann's extraction from `projection * view`, or `projection * view * model`
+    /// for a frustum in the model's space.
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let (r0, r1, r2, r3) = (matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2]
+            .map(|plane| plane / plane.xyz().length());
+        Frustum { planes }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(point) + plane.w >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    /// Conservative, boxes near a frustum corner can pass without touching it.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane normal
+            let normal = plane.xyz();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    /// Sphere first since it's cheaper and rejects most of what is far away.
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        !bounds.is_empty() && self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec3;
+
+    #[test]
+    fn bounds_and_culling() {
+        let points = [vec3(-1.0, 0.0, 0.0), vec3(1.0, 2.0, 0.0), vec3(0.0, 1.0, 4.0)];
+        let bounds = Bounds::from_points(&points);
+        assert_eq!(bounds.aabb.center(), vec3(0.0, 1.0, 2.0));
+        assert!(points.iter().all(|p| p.distance(bounds.sphere.center) <= bounds.sphere.radius + 1e-6));
+        assert!(Bounds::from_points(&[]).is_empty());
+
+        let matrix = Mat4::from_scale_rotation_translation(Vec3::splat(2.0), glam::Quat::from_rotation_y(0.5), Vec3::X);
+        let moved = bounds.transform(&matrix);
+        assert!(points.iter().map(|p| matrix.transform_point3(*p)).all(|p| {
+            p.cmpge(moved.aabb.min - 1e-5).all() && p.cmple(moved.aabb.max + 1e-5).all()
+                && p.distance(moved.sphere.center) <= moved.sphere.radius + 1e-5
+        }));
+
+        let view = Mat4::look_at_rh(vec3(0.0, 0.0, 10.0), Vec3::ZERO, Vec3::Y);
+        let frustum = Frustum::from_matrix(&(Mat4::perspective_rh(1.0, 1.0, 0.1, 100.0) * view));
+        assert!(frustum.contains_point(Vec3::ZERO));
+        assert!(!frustum.contains_point(vec3(0.0, 0.0, 11.0)));
+        assert!(frustum.intersects(&bounds));
+        let behind = bounds.transform(&Mat4::from_translation(vec3(0.0, 0.0, 20.0)));
+        assert!(!frustum.intersects(&behind));
+        let beside = bounds.transform(&Mat4::from_translation(vec3(50.0, 0.0, 0.0)));
+        assert!(!frustum.intersects(&beside));
+        let far = bounds.transform(&Mat4::from_translation(vec3(0.0, 0.0, -200.0)));
+        assert!(!frustum.intersects(&far));
+    }
+}
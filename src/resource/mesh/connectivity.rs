@@ -224,6 +224,99 @@ impl ConnectivityInfo {
     }
 }
 
+/// Flat representation of `ConnectivityInfo` used for caching.
+/// Missing links are stored as `u32::MAX`.
+#[derive(Clone, Debug, Default)]
+pub struct RawConnectivity {
+    pub positions: Vec<Vec3>,
+    pub vertex_halfedges: Vec<u32>,
+    pub halfedges: Vec<[u32; 4]>, // vertex, twin, next, face
+    pub face_halfedges: Vec<u32>,
+    pub free_vertices: Vec<u32>,
+    pub free_halfedges: Vec<u32>,
+    pub free_faces: Vec<u32>,
+}
+
+const NONE: u32 = u32::MAX;
+
+fn to_raw_id<K: ID>(id: Option<K>) -> u32 {
+    id.map(|id| *id).unwrap_or(NONE)
+}
+
+fn unpack_ids<K: ID>(ids: &[u32]) -> Vec<K> {
+    ids.iter().map(|id| unsafe { K::new(*id) }).collect()
+}
+
+fn from_raw_id<K: ID>(id: u32) -> Option<K> {
+    if id == NONE {
+        None
+    } else {
+        Some(unsafe { K::new(id) })
+    }
+}
+
+impl ConnectivityInfo {
+    pub fn to_raw(&self) -> RawConnectivity {
+        let vertices = RefCell::borrow(&self.vertices);
+        let halfedges = RefCell::borrow(&self.halfedges);
+        let faces = RefCell::borrow(&self.faces);
+        RawConnectivity {
+            positions: vertices.values.iter().map(|v| v.position).collect(),
+            vertex_halfedges: vertices.values.iter().map(|v| to_raw_id(v.halfedge)).collect(),
+            halfedges: halfedges
+                .values
+                .iter()
+                .map(|h| [to_raw_id(h.vertex), to_raw_id(h.twin), to_raw_id(h.next), to_raw_id(h.face)])
+                .collect(),
+            face_halfedges: faces.values.iter().map(|f| to_raw_id(f.halfedge)).collect(),
+            free_vertices: vertices.free.iter().map(|id| **id).collect(),
+            free_halfedges: halfedges.free.iter().map(|id| **id).collect(),
+            free_faces: faces.free.iter().map(|id| **id).collect(),
+        }
+    }
+
+    pub fn from_raw(raw: &RawConnectivity) -> ConnectivityInfo {
+        assert_eq!(raw.vertex_halfedges.len(), raw.positions.len());
+        ConnectivityInfo {
+            vertices: RefCell::new(IDMap {
+                values: raw
+                    .vertex_halfedges
+                    .iter()
+                    .zip(&raw.positions)
+                    .map(|(halfedge, position)| Vertex {
+                        halfedge: from_raw_id(*halfedge),
+                        position: *position,
+                    })
+                    .collect(),
+                free: unpack_ids(&raw.free_vertices),
+            }),
+            halfedges: RefCell::new(IDMap {
+                values: raw
+                    .halfedges
+                    .iter()
+                    .map(|[vertex, twin, next, face]| HalfEdge {
+                        vertex: from_raw_id(*vertex),
+                        twin: from_raw_id(*twin),
+                        next: from_raw_id(*next),
+                        face: from_raw_id(*face),
+                    })
+                    .collect(),
+                free: unpack_ids(&raw.free_halfedges),
+            }),
+            faces: RefCell::new(IDMap {
+                values: raw
+                    .face_halfedges
+                    .iter()
+                    .map(|halfedge| Face {
+                        halfedge: from_raw_id(*halfedge),
+                    })
+                    .collect(),
+                free: unpack_ids(&raw.free_faces),
+            }),
+        }
+    }
+}
+
 impl std::fmt::Debug for ConnectivityInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         writeln!(f, "**** VERTICES: ****")?;
@@ -1,8 +1,12 @@
+pub mod bounds;
 pub mod connectivity;
 pub mod indexing;
+pub mod selection;
 
+pub use bounds::*;
 pub use connectivity::*;
 pub use indexing::*;
+pub use selection::*;
 
 use crate::{offset_of, Buffer, Context, Resource, Vertex, BufferInfo};
 use crate::resource::material::MaterialInfo;
@@ -15,7 +19,7 @@ use rayon::prelude::*;
 
 //TODO: solve non-vec4-aligned issues..
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ModelVertex {
     pub pos: glam::Vec4,
     pub color: glam::Vec4,
@@ -204,42 +208,62 @@ impl VulkanMesh {
     }
 
     pub fn cmd_draw(&self, cmd: vk::CommandBuffer) {
+        for section in &self.primitive_sections {
+            self.cmd_draw_section(cmd, section);
+        }
+    }
+
+    /// Draws the sections whose bounds are inside `frustum`, returns how many were drawn.
+    /// The frustum is in world space, the mesh transform is applied to the bounds.
+    pub fn cmd_draw_culled(&self, cmd: vk::CommandBuffer, frustum: &Frustum) -> usize {
+        let mut drawn = 0;
+        for section in self.visible_sections(frustum) {
+            self.cmd_draw_section(cmd, section);
+            drawn += 1;
+        }
+        drawn
+    }
+
+    pub fn visible_sections<'a>(&'a self, frustum: &'a Frustum) -> impl Iterator<Item = &'a PrimitiveSection> + 'a {
+        self.primitive_sections.iter()
+            .filter(move |section| frustum.intersects(&section.bounds.transform(&self.transform)))
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        section_bounds(&self.primitive_sections).transform(&self.transform)
+    }
+
+    fn cmd_draw_section(&self, cmd: vk::CommandBuffer, section: &PrimitiveSection) {
         let device = self.context.device();
         unsafe {
+            device.cmd_bind_vertex_buffers(
+                cmd,
+                0,
+                &[self.vertex_buffer.handle()],
+                &[section.get_vertex_offset_size()],
+            );
             match &self.index_buffer {
                 Some(indices) => {
-                    for section in &self.primitive_sections {
-                        device.cmd_bind_vertex_buffers(
-                            cmd,
-                            0,
-                            &[self.vertex_buffer.handle()],
-                            &[section.get_vertex_offset_size()],
-                        );
-                        device.cmd_bind_index_buffer(
-                            cmd,
-                            indices.handle(),
-                            section.get_index_offset_size::<u32>(),
-                            vk::IndexType::UINT32,
-                        );
-                        device.cmd_draw_indexed(cmd, section.get_index_count(), 1, 0, 0, 1);
-                    }
+                    device.cmd_bind_index_buffer(
+                        cmd,
+                        indices.handle(),
+                        section.get_index_offset_size::<u32>(),
+                        vk::IndexType::UINT32,
+                    );
+                    device.cmd_draw_indexed(cmd, section.get_index_count(), 1, 0, 0, 1);
                 }
                 None => {
-                    for section in &self.primitive_sections {
-                        device.cmd_bind_vertex_buffers(
-                            cmd,
-                            0,
-                            &[self.vertex_buffer.handle()],
-                            &[section.get_vertex_offset_size()],
-                        );
-                        device.cmd_draw(cmd, section.get_vertex_count(), 1, 0, 1);
-                    }
+                    device.cmd_draw(cmd, section.get_vertex_count(), 1, 0, 1);
                 }
             }
         }
     }
 }
 
+fn section_bounds(sections: &[PrimitiveSection]) -> Bounds {
+    sections.iter().fold(Bounds::default(), |bounds, section| bounds.union(&section.bounds))
+}
+
 
 #[derive(Clone, Copy, Debug)]
 pub struct PrimitiveSection {
@@ -247,7 +271,8 @@ pub struct PrimitiveSection {
     pub vertices: BufferPart,
     pub indices: Option<BufferPart>,
     pub material_index: Option<usize>,
-    //aabb: AABB<f32>,
+    /// In mesh space, filled in by `Mesh::update_bounds`.
+    pub bounds: Bounds,
 }
 
 impl PrimitiveSection {
@@ -336,7 +361,8 @@ pub struct Mesh {
     pub indices: Vec<u32>,
     pub transform: glam::Mat4,
     pub primitive_sections: Vec<PrimitiveSection>,
-    pub connectivity_info: ConnectivityInfo
+    pub connectivity_info: ConnectivityInfo,
+    pub face_selections: Vec<FaceSelection>,
 }
 
 impl Mesh {
@@ -348,15 +374,17 @@ impl Mesh {
         let positions = vertices.iter().map(|v| v.pos.xyz()).collect::<Vec<_>>();
         let connectivity_info: ConnectivityInfo = ConnectivityInfo::new(no_vertices, no_faces);
         
-        let mesh = Mesh {
+        let mut mesh = Mesh {
             name,
             vertices,
             indices,
             transform,
             primitive_sections,
-            connectivity_info
+            connectivity_info,
+            face_selections: Vec::new(),
         };
-        
+        mesh.update_bounds();
+
 
         // Create vertices
         positions.iter().for_each(|pos| {
@@ -421,6 +449,20 @@ impl Mesh {
         mesh
     }
 
+    /// Recomputes the section bounds from the vertices, needed after the vertices moved.
+    pub fn update_bounds(&mut self) {
+        for section in &mut self.primitive_sections {
+            let range = section.vertices.offset..section.vertices.offset + section.vertices.element_count;
+            let positions = self.vertices[range].iter().map(|v| v.pos.xyz()).collect::<Vec<_>>();
+            section.bounds = Bounds::from_points(&positions);
+        }
+    }
+
+    /// In world space, including the mesh transform.
+    pub fn bounds(&self) -> Bounds {
+        section_bounds(&self.primitive_sections).transform(&self.transform)
+    }
+
     pub fn to_vulkan_mesh(&self, context: Arc<Context>) -> VulkanMesh {
         let first_id: VertexID;
         unsafe { first_id = VertexID::new(*self.indices.first().unwrap()); };
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+use super::Mesh;
+
+/// Where a face selection came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelectionKind {
+    /// A node of the DAZ region tree. Parent regions contain the faces of all their children.
+    Region,
+    /// A DAZ polygon group (e.g. "Head", "lThighBend").
+    PolygonGroup,
+    /// A DAZ surface (polygon material group).
+    MaterialGroup,
+}
+
+/// A named set of triangles of a [`Mesh`].
+/// Faces are triangle indices, i.e. triangle `f` is `indices[3 * f..3 * f + 3]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaceSelection {
+    pub name: String,
+    pub kind: SelectionKind,
+    pub faces: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn face_selection(&self, name: &str) -> Option<&FaceSelection> {
+        self.face_selections.iter().find(|s| s.name == name)
+    }
+
+    pub fn face_selections_of(&self, kind: SelectionKind) -> impl Iterator<Item = &FaceSelection> {
+        self.face_selections.iter().filter(move |s| s.kind == kind)
+    }
+
+    /// Index buffer containing only the triangles of the selection, e.g. to draw a region with its own material.
+    pub fn selection_indices(&self, selection: &FaceSelection) -> Vec<u32> {
+        selection.faces.iter().flat_map(|f| {
+            let f = *f as usize * 3;
+            self.indices[f..f + 3].iter().copied()
+        }).collect()
+    }
+
+    /// One flag per vertex, set if the vertex belongs to any triangle of the selection.
+    /// Useful as a simulation mask.
+    pub fn selection_vertex_mask(&self, selection: &FaceSelection) -> Vec<bool> {
+        let mut mask = vec![false; self.vertices.len()];
+        for index in self.selection_indices(selection) {
+            mask[index as usize] = true;
+        }
+        mask
+    }
+}
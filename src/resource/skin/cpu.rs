@@ -0,0 +1,193 @@
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::resource::mesh::{Mesh, ModelVertex};
+use super::{DualQuat, Skin, SkinJoint, SkinningMode};
+
+/// `Skin::joints` sorted by vertex, with the influences of vertex `v` at `joints[offsets[v]..offsets[v + 1]]`.
+/// This is the layout `skin_vertices.comp` reads.
+#[derive(Debug, Clone)]
+pub struct SkinInfluences {
+    pub joints: Vec<SkinJoint>,
+    pub offsets: Vec<u32>,
+}
+
+impl SkinInfluences {
+    pub fn of(&self, vertex: usize) -> &[SkinJoint] {
+        match (self.offsets.get(vertex), self.offsets.get(vertex + 1)) {
+            (Some(begin), Some(end)) => &self.joints[*begin as usize..*end as usize],
+            _ => &[],
+        }
+    }
+}
+
+fn with_w(v: Vec3, w: f32) -> Vec4 {
+    Vec4::new(v.x, v.y, v.z, w)
+}
+
+impl Skin {
+    /// Per joint matrix taking a bind pose vertex to its posed position.
+    pub fn skinning_matrices(&self) -> Vec<Mat4> {
+        let joint_count = if self.joint_bones.is_empty() { self.global_bone_transforms.len() } else { self.joint_bones.len() };
+        (0..joint_count).map(|joint| {
+            let global = self.global_bone_transforms.get(self.joint_bone(joint)).copied().unwrap_or(Mat4::IDENTITY);
+            global * self.inverse_bind_matrices.get(joint).copied().unwrap_or(Mat4::IDENTITY)
+        }).collect()
+    }
+
+    pub fn dual_quaternions(&self) -> Vec<DualQuat> {
+        self.skinning_matrices().iter().map(DualQuat::from_mat4).collect()
+    }
+
+    pub fn influences(&self) -> SkinInfluences {
+        let mut joints = self.joints.clone();
+        joints.sort_by_key(|j| j.vertex_id);
+        let vertex_count = joints.last().map_or(0, |j| j.vertex_id as usize + 1);
+        let mut offsets = vec![0u32; vertex_count + 1];
+        for joint in &joints {
+            offsets[joint.vertex_id as usize + 1] += 1;
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+        SkinInfluences { joints, offsets }
+    }
+
+    /// `influences()` covering all `vertex_count` vertices of the skinned mesh,
+    /// vertices past the last weighted one get an empty range.
+    pub fn influences_for(&self, vertex_count: usize) -> SkinInfluences {
+        let mut influences = self.influences();
+        let end = influences.offsets.last().copied().unwrap_or(0);
+        let len = influences.offsets.len().max(vertex_count + 1);
+        influences.offsets.resize(len, end);
+        influences
+    }
+
+    /// CPU reference of `skin_vertices.comp`, e.g. to check the GPU result in headless tests.
+    pub fn skin_vertices(&self, rest: &[ModelVertex], mode: SkinningMode) -> Vec<ModelVertex> {
+        let influences = self.influences();
+        let matrices = self.skinning_matrices();
+        let dual_quats = self.dual_quaternions();
+
+        rest.iter().enumerate().map(|(index, vertex)| {
+            let joints = influences.of(index);
+            let total: f32 = joints.iter().map(|j| j.weight).sum();
+            if joints.is_empty() || total <= 0.0 {
+                return *vertex;
+            }
+            let (pos, normal) = match mode {
+                SkinningMode::Linear => {
+                    let m = joints.iter().fold(Mat4::ZERO, |m, j| m + matrices[j.joint_id as usize] * (j.weight / total));
+                    (m.transform_point3(vertex.pos.xyz()), m.transform_vector3(vertex.normal.xyz()))
+                }
+                SkinningMode::DualQuaternion => {
+                    let pivot = dual_quats[joints[0].joint_id as usize].real;
+                    let blend = joints.iter().fold(DualQuat { real: glam::Quat::from_xyzw(0.0, 0.0, 0.0, 0.0), ..DualQuat::IDENTITY }, |acc, j| {
+                        let dq = dual_quats[j.joint_id as usize];
+                        // keep all quaternions in the same hemisphere to take the short way around
+                        let w = if pivot.dot(dq.real) < 0.0 { -j.weight / total } else { j.weight / total };
+                        DualQuat { real: acc.real + dq.real * w, dual: acc.dual + dq.dual * w }
+                    }).normalize();
+                    (blend.transform_point(vertex.pos.xyz()), blend.transform_vector(vertex.normal.xyz()))
+                }
+            };
+            ModelVertex {
+                pos: with_w(pos, vertex.pos.w),
+                normal: with_w(normal.normalize_or_zero(), vertex.normal.w),
+                ..*vertex
+            }
+        }).collect()
+    }
+
+    pub fn skin_mesh(&self, mesh: &Mesh, mode: SkinningMode) -> Vec<ModelVertex> {
+        self.skin_vertices(&mesh.vertices, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+    use crate::resource::skin::PackedInfluence;
+    use std::collections::HashMap;
+
+    fn vertex(pos: Vec3) -> ModelVertex {
+        ModelVertex { pos: with_w(pos, 1.0), color: Vec4::ONE, normal: Vec4::new(0.0, 1.0, 0.0, 0.0), uv: Vec4::ZERO }
+    }
+
+    #[test]
+    fn dual_quaternion_keeps_volume() {
+        // a vertex at an elbow, half bound to a still upper arm and a forearm bent by 180 degrees
+        let bent = Mat4::from_rotation_z(std::f32::consts::PI);
+        let skin = Skin {
+            name: "elbow".to_string(),
+            transforms: vec![Mat4::IDENTITY, bent],
+            global_bone_transforms: vec![Mat4::IDENTITY, bent],
+            inverse_bind_matrices: vec![Mat4::IDENTITY, Mat4::IDENTITY],
+            joints: vec![
+                SkinJoint { joint_id: 1, vertex_id: 0, weight: 0.5 },
+                SkinJoint { joint_id: 0, vertex_id: 0, weight: 0.5 },
+                SkinJoint { joint_id: 1, vertex_id: 1, weight: 1.0 },
+            ],
+            joint_id_map: HashMap::new(),
+            joint_bones: Vec::new(),
+        };
+        let rest = [vertex(Vec3::X), vertex(Vec3::X), vertex(Vec3::Y)];
+
+        let linear = skin.skin_vertices(&rest, SkinningMode::Linear);
+        let dual = skin.skin_vertices(&rest, SkinningMode::DualQuaternion);
+        // linear blending collapses the joint, dual quaternions rotate around it
+        assert!(linear[0].pos.xyz().length() < 1e-5);
+        assert!((dual[0].pos.xyz().length() - 1.0).abs() < 1e-5);
+        // fully weighted and unweighted vertices agree in both modes
+        assert!(linear[1].pos.abs_diff_eq(dual[1].pos, 1e-5));
+        assert!(dual[1].pos.xyz().abs_diff_eq(-Vec3::X, 1e-5));
+        assert_eq!(dual[2].pos, rest[2].pos);
+
+        // the trailing unweighted vertex still gets an (empty) influence range
+        assert_eq!(skin.influences().offsets, vec![0, 2, 3]);
+        let influences = skin.influences_for(rest.len());
+        assert_eq!(influences.offsets, vec![0, 2, 3, 3]);
+        assert!(influences.of(2).is_empty());
+        assert_eq!(skin.packed_influences(rest.len())[2], PackedInfluence::default());
+
+        let dq = DualQuat::from_rotation_translation(Quat::from_rotation_y(0.3), Vec3::new(1.0, 2.0, 3.0));
+        assert!(DualQuat::from_mat4(&dq.to_mat4()).real.abs_diff_eq(dq.real, 1e-5));
+        assert!(dq.translation().abs_diff_eq(Vec3::new(1.0, 2.0, 3.0), 1e-5));
+    }
+
+    #[test]
+    fn posed_parent_moves_child_vertices() {
+        use crate::resource::skin::{Bone, GlobalTransforms};
+        use crate::scene::daz::format::test_rig;
+
+        // skin joints listed in a different order than the rig bones
+        let arm_bind = Mat4::from_translation(Vec3::X);
+        let mut rig = test_rig(&[("hip", None, Mat4::IDENTITY), ("arm", Some(0), arm_bind)]);
+        let mut skin = Skin {
+            name: "arm".to_string(),
+            transforms: Vec::new(),
+            global_bone_transforms: Vec::new(),
+            inverse_bind_matrices: Vec::new(),
+            joints: vec![SkinJoint { joint_id: 0, vertex_id: 0, weight: 1.0 }],
+            joint_id_map: [("arm".to_string(), 0), ("hip".to_string(), 1)].into(),
+            joint_bones: Vec::new(),
+        };
+        skin.bind_to_rig(&rig);
+        assert_eq!(skin.joint_bones, vec![1, 0]);
+
+        let rest = [vertex(Vec3::new(2.0, 0.0, 0.0))];
+        let mut globals = GlobalTransforms::new(&rig);
+        assert!(skin.transforms_from(&rig, &mut globals));
+        assert!(!skin.transforms_from(&rig, &mut globals));
+        assert!(skin.skin_vertices(&rest, SkinningMode::Linear)[0].pos.abs_diff_eq(rest[0].pos, 1e-5));
+
+        // only the parent is posed
+        rig.bones[0].set_local_transform(Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!(skin.transforms_from(&rig, &mut globals));
+        let expected = (rig.local_to_global(1) * arm_bind.inverse()).transform_point3(rest[0].pos.xyz());
+        assert!(expected.distance(rest[0].pos.xyz()) > 0.5);
+        for mode in [SkinningMode::Linear, SkinningMode::DualQuaternion] {
+            assert!(skin.skin_vertices(&rest, mode)[0].pos.xyz().abs_diff_eq(expected, 1e-4));
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use glam::{Mat4, Quat, Vec3};
+
+/// Rigid transform as a unit dual quaternion, laid out like `vec4 real; vec4 dual;` in glsl.
+/// Scale and shear can't be represented and are dropped.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DualQuat {
+    pub real: Quat,
+    pub dual: Quat,
+}
+
+impl Default for DualQuat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl DualQuat {
+    pub const IDENTITY: Self = Self {
+        real: Quat::IDENTITY,
+        dual: Quat::from_xyzw(0.0, 0.0, 0.0, 0.0),
+    };
+
+    pub fn from_rotation_translation(rotation: Quat, translation: Vec3) -> Self {
+        let t = Quat::from_xyzw(translation.x, translation.y, translation.z, 0.0);
+        Self {
+            real: rotation,
+            dual: (t * rotation) * 0.5,
+        }
+    }
+
+    pub fn from_mat4(matrix: &Mat4) -> Self {
+        let (_, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self::from_rotation_translation(rotation.normalize(), translation)
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.real, self.translation())
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        let t = (self.dual * 2.0) * self.real.conjugate();
+        Vec3::new(t.x, t.y, t.z)
+    }
+
+    pub fn normalize(&self) -> Self {
+        let length = self.real.length();
+        Self {
+            real: self.real * (1.0 / length),
+            dual: self.dual * (1.0 / length),
+        }
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.real * point + self.translation()
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.real * vector
+    }
+}
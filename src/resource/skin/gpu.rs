@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::{
+    ComputePipeline, ComputePipelineInfo, Context, DescriptorSetInfo, DescriptorSetLayout,
+    DescriptorSetLayoutInfo, PipelineLayout, PipelineLayoutInfo, Resource,
+};
+use crate::resource::mesh::VulkanMesh;
+use super::{SkinningMode, VulkanSkin};
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SkinningConstants {
+    vertex_count: u32,
+    mode: u32,
+}
+
+/// Skins vertex buffers in place with `skin_vertices.comp`, blending as selected by `mode`.
+pub struct GpuSkinning {
+    pipeline: ComputePipeline,
+    pub desc_set_layout: DescriptorSetLayout,
+    pub pipeline_layout: PipelineLayout,
+    pub mode: SkinningMode,
+}
+
+impl GpuSkinning {
+    pub fn new(context: Arc<Context>, mode: SkinningMode) -> Self {
+        let desc_set_layout = DescriptorSetLayout::new(
+            context.clone(),
+            DescriptorSetLayoutInfo::default()
+                // skinned vertices, written
+                .binding(0, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+                // bind pose vertices
+                .binding(1, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+                // skinning matrices
+                .binding(2, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+                // dual quaternions
+                .binding(3, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+                // influences sorted by vertex
+                .binding(4, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)
+                // per vertex offsets into the influences
+                .binding(5, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE),
+        );
+        let pipeline_layout = PipelineLayout::new(
+            context.clone(),
+            PipelineLayoutInfo::default()
+                .desc_set_layout(desc_set_layout.handle())
+                .push_constant_range(
+                    vk::PushConstantRange::builder()
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                        .offset(0)
+                        .size(std::mem::size_of::<SkinningConstants>() as u32)
+                        .build(),
+                ),
+        );
+        let pipeline = ComputePipeline::new(
+            context,
+            ComputePipelineInfo::default()
+                .layout(pipeline_layout.handle())
+                .comp(crate::util::find_asset("glsl/skin_vertices.comp").unwrap()),
+        );
+
+        Self {
+            pipeline,
+            desc_set_layout,
+            pipeline_layout,
+            mode,
+        }
+    }
+
+    /// Records the skinning of `mesh` by `skin`. The vertex buffer is ready for vertex input
+    /// and vertex shader reads once this returns.
+    pub fn dispatch(&mut self, cmd: vk::CommandBuffer, device: &ash::Device, skin: &VulkanSkin, mesh: &VulkanMesh) {
+        let desc_set = self.desc_set_layout.get_or_create(
+            DescriptorSetInfo::default()
+                .buffer(0, mesh.vertex_buffer.get_descriptor_info())
+                .buffer(1, skin.rest_vertices.get_descriptor_info())
+                .buffer(2, skin.skinning_matrices.get_descriptor_info())
+                .buffer(3, skin.dual_quaternions.get_descriptor_info())
+                .buffer(4, skin.influences.get_descriptor_info())
+                .buffer(5, skin.vertex_offsets.get_descriptor_info()),
+        );
+        let vertex_count = mesh.vertex_buffer.get_element_count().min(skin.rest_vertices.get_element_count());
+        let constants = SkinningConstants {
+            vertex_count,
+            mode: self.mode as u32,
+        };
+
+        // the previous frame may still be drawing from the vertex buffer
+        let before = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(mesh.vertex_buffer.handle())
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+        let after = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::SHADER_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(mesh.vertex_buffer.handle())
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::default(),
+                &[],
+                &[before],
+                &[],
+            );
+            device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.handles().unwrap()[0],
+            );
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout.handle(),
+                0,
+                &[desc_set.handle()],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.pipeline_layout.handle(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+            device.cmd_dispatch(cmd, vertex_count.div_ceil(64), 1, 1);
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::DependencyFlags::default(),
+                &[],
+                &[after],
+                &[],
+            );
+        }
+    }
+
+    /// Skins every mesh of the scene that has a skin, pairing `vulkan_skins[i]` with `vulkan_meshes[i]`.
+    pub fn dispatch_scene(&mut self, cmd: vk::CommandBuffer, device: &ash::Device, scene: &crate::scene::Scene) {
+        scene.vulkan_skins.iter().zip(scene.vulkan_meshes.iter()).for_each(|(skin, mesh)| {
+            self.dispatch(cmd, device, skin, mesh);
+        });
+    }
+}
@@ -0,0 +1,180 @@
+use glam::Mat4;
+use rayon::prelude::*;
+
+use super::{Bone, Rig, Transform};
+
+/// Global transforms of a rig, evaluated in one pass over the bones sorted parents first.
+/// Changed locals mark their bone dirty and `update` only recomputes the dirty subtrees.
+/// Same convention as `Rig::local_to_global`: `global = local * parent_global`.
+#[derive(Debug, Clone)]
+pub struct GlobalTransforms {
+    /// Bone indices, every parent comes before its children.
+    pub order: Vec<usize>,
+    parents: Vec<Option<usize>>,
+    locals: Vec<Mat4>,
+    globals: Vec<Mat4>,
+    dirty: Vec<bool>,
+}
+
+fn depth(parents: &[Option<usize>], mut bone: usize) -> usize {
+    let mut depth = 0;
+    while let Some(parent) = parents[bone] {
+        depth += 1;
+        // broken hierarchies with cycles are cut off rather than looping forever
+        if depth > parents.len() {
+            break;
+        }
+        bone = parent;
+    }
+    depth
+}
+
+impl GlobalTransforms {
+    pub fn new<R: Rig<S, T>, S: Transform, T: Bone<S>>(rig: &R) -> Self {
+        let bones = rig.get_bones();
+        let parents = bones.iter()
+            .map(|bone| bone.get_parent().filter(|p| *p < bones.len()))
+            .collect::<Vec<_>>();
+        let depths = (0..bones.len()).map(|i| depth(&parents, i)).collect::<Vec<_>>();
+        let mut order = (0..bones.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| depths[*i]);
+
+        let mut transforms = GlobalTransforms {
+            order,
+            parents,
+            locals: bones.iter().map(|bone| bone.get_local_transform().get_matrix()).collect(),
+            globals: vec![Mat4::IDENTITY; bones.len()],
+            dirty: vec![true; bones.len()],
+        };
+        transforms.update();
+        transforms
+    }
+
+    pub fn len(&self) -> usize {
+        self.locals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locals.is_empty()
+    }
+
+    pub fn local(&self, bone: usize) -> Mat4 {
+        self.locals[bone]
+    }
+
+    pub fn set_local(&mut self, bone: usize, local: Mat4) {
+        if self.locals[bone] != local {
+            self.locals[bone] = local;
+            self.dirty[bone] = true;
+        }
+    }
+
+    /// Takes over the locals of `rig`, only bones whose local changed become dirty.
+    pub fn sync<R: Rig<S, T>, S: Transform, T: Bone<S>>(&mut self, rig: &R) {
+        for (i, bone) in rig.get_bones().iter().enumerate().take(self.len()) {
+            self.set_local(i, bone.get_local_transform().get_matrix());
+        }
+    }
+
+    /// Marks every bone dirty, the next `update` evaluates the whole rig.
+    pub fn invalidate(&mut self) {
+        self.dirty.iter_mut().for_each(|d| *d = true);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.contains(&true)
+    }
+
+    /// Recomputes dirty bones and everything below them, returns how many bones were evaluated.
+    pub fn update(&mut self) -> usize {
+        let mut evaluated = 0;
+        for &bone in &self.order {
+            let parent = self.parents[bone];
+            if let Some(parent) = parent {
+                // a recomputed parent leaves its flag set until the end of the pass
+                self.dirty[bone] |= self.dirty[parent];
+            }
+            if self.dirty[bone] {
+                self.globals[bone] = match parent {
+                    Some(parent) => self.locals[bone] * self.globals[parent],
+                    None => self.locals[bone],
+                };
+                evaluated += 1;
+            }
+        }
+        self.dirty.iter_mut().for_each(|d| *d = false);
+        evaluated
+    }
+
+    pub fn global(&self, bone: usize) -> Mat4 {
+        self.globals[bone]
+    }
+
+    /// Indexed like the rig's bones.
+    pub fn globals(&self) -> &[Mat4] {
+        &self.globals
+    }
+
+    /// Updates many rigs at once, e.g. a crowd. Rigs are spread over threads, single rigs are
+    /// too small to be worth splitting.
+    pub fn update_all(rigs: &mut [GlobalTransforms]) -> usize {
+        rigs.par_iter_mut().map(|rig| rig.update()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+    use crate::resource::skin::Skin;
+    use crate::scene::daz::format::test_rig;
+
+    #[test]
+    fn matches_local_to_global() {
+        // children listed before their parents on purpose
+        let parents = [Some(2), Some(0), None, Some(2), Some(3)];
+        let names = parents.iter().enumerate().map(|(i, _)| i.to_string()).collect::<Vec<_>>();
+        let joints = parents.iter().enumerate().map(|(i, parent)| {
+            let global = Mat4::from_translation(Vec3::new(i as f32, 1.0, 0.0)) * Mat4::from_rotation_z(0.1 * i as f32);
+            (names[i].as_str(), *parent, global)
+        }).collect::<Vec<_>>();
+        let mut rig = test_rig(&joints);
+        assert_eq!(rig.root_bone, 2);
+
+        let mut transforms = GlobalTransforms::new(&rig);
+        assert_eq!(transforms.order[0], 2);
+        for i in 0..rig.bones.len() {
+            assert!(transforms.global(i).abs_diff_eq(rig.local_to_global(i), 1e-5));
+        }
+        assert_eq!(transforms.update(), 0);
+
+        // only bone 3 and its child 4 change
+        rig.bones[3].set_local_transform(Mat4::from_rotation_x(1.0));
+        transforms.sync(&rig);
+        assert_eq!(transforms.update(), 2);
+        for i in 0..rig.bones.len() {
+            assert!(transforms.global(i).abs_diff_eq(rig.local_to_global(i), 1e-5));
+        }
+
+        // a skin reuses the cached transforms
+        let mut skin = Skin {
+            name: "skin".to_string(),
+            transforms: Vec::new(),
+            global_bone_transforms: Vec::new(),
+            inverse_bind_matrices: Vec::new(),
+            joints: Vec::new(),
+            joint_id_map: Default::default(),
+            joint_bones: Vec::new(),
+        };
+        rig.bones[4].set_local_transform(Mat4::from_rotation_y(0.5));
+        skin.transforms_from(&rig, &mut transforms);
+        assert_eq!(skin.global_bone_transforms.len(), rig.bones.len());
+        assert!(skin.global_bone_transforms[4].abs_diff_eq(rig.local_to_global(4), 1e-5));
+        assert_eq!(transforms.update(), 0);
+
+        let mut crowd = vec![transforms.clone(); 4];
+        crowd[1].set_local(2, Mat4::from_translation(Vec3::X));
+        assert_eq!(GlobalTransforms::update_all(&mut crowd), 5);
+        assert!(crowd[1].global(4).abs_diff_eq(rig.local_to_global(4) * rig.bones[2].local_transform.inverse() * Mat4::from_translation(Vec3::X), 1e-4));
+    }
+}
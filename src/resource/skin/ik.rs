@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use glam::{Mat4, Quat, Vec3, Vec4Swizzles};
+
+use crate::scene::daz::JointConstraint;
+use crate::scene::daz::format::RigV1;
+use super::{Bone, Transform};
+
+#[derive(Debug)]
+pub enum IkError {
+    UnknownBone(String),
+    NotAChain { root: String, tip: String },
+    ChainLength { expected: usize, found: usize },
+}
+
+impl fmt::Display for IkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for IkError {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IkSettings {
+    pub iterations: usize,
+    /// Distance between effector and target at which the solver stops.
+    pub tolerance: f32,
+}
+
+impl Default for IkSettings {
+    fn default() -> Self {
+        IkSettings {
+            iterations: 20,
+            tolerance: 1e-3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IkResult {
+    pub iterations: usize,
+    /// Remaining distance between effector and target.
+    pub error: f32,
+}
+
+fn position(m: &Mat4) -> Vec3 {
+    m.w_axis.xyz()
+}
+
+fn rotation(m: &Mat4) -> Quat {
+    m.to_scale_rotation_translation().1.normalize()
+}
+
+/// A run of bones from `root` down to `tip`. The tip's joint is the effector.
+#[derive(Debug, Clone)]
+pub struct IkChain {
+    pub bones: Vec<usize>,
+}
+
+// Working copy of the chain. Solvers rotate joints in world space, children follow.
+struct ChainPose<'a> {
+    chain: &'a [usize],
+    // the limits stored on each bone by the rig parser
+    constraints: Vec<Option<JointConstraint>>,
+    globals: Vec<Mat4>,
+    // global of the bone above the chain root, it doesn't move
+    root_parent: Mat4,
+    // rest (bind) pose rotation of each joint relative to its parent
+    rest_locals: Vec<Quat>,
+}
+
+impl<'a> ChainPose<'a> {
+    fn new<S: Transform, T: Bone<S>>(rig: &RigV1<S, T>, chain: &'a [usize]) -> Self {
+        let bind = |i: usize| rotation(&rig.bones[i].global_transform().get_matrix());
+        let root_parent_index = rig.bones[chain[0]].get_parent();
+        let mut parent_bind = root_parent_index.map_or(Quat::IDENTITY, bind);
+        let rest_locals = chain.iter().map(|i| {
+            let local = parent_bind.inverse() * bind(*i);
+            parent_bind = bind(*i);
+            local
+        }).collect();
+        ChainPose {
+            chain,
+            constraints: chain.iter().map(|i| rig.bones[*i].get_constraint().cloned()).collect(),
+            globals: chain.iter().map(|i| rig.local_to_global(*i).get_matrix()).collect(),
+            root_parent: root_parent_index.map_or(Mat4::IDENTITY, |p| rig.local_to_global(p).get_matrix()),
+            rest_locals,
+        }
+    }
+
+    fn joint(&self, j: usize) -> Vec3 {
+        position(&self.globals[j])
+    }
+
+    fn effector(&self) -> Vec3 {
+        self.joint(self.globals.len() - 1)
+    }
+
+    fn parent_rotation(&self, j: usize) -> Quat {
+        rotation(if j == 0 { &self.root_parent } else { &self.globals[j - 1] })
+    }
+
+    /// Rotates joint `j` by `delta` (world space) around its pivot, limited by the clamped
+    /// rotation channels of the joint. Everything below the joint follows.
+    fn rotate(&mut self, j: usize, delta: Quat) {
+        let current = rotation(&self.globals[j]);
+        let mut wanted = delta * current;
+        if let Some(constraint) = &self.constraints[j] {
+            let parent = self.parent_rotation(j);
+            let rest = self.rest_locals[j];
+            let from_rest = (parent * rest).inverse() * wanted;
+            wanted = parent * rest * constraint.clamp_rotation(from_rest);
+        }
+        let applied = (wanted * current.inverse()).normalize();
+        let pivot = self.joint(j);
+        let d = Mat4::from_translation(pivot) * Mat4::from_quat(applied) * Mat4::from_translation(-pivot);
+        for global in &mut self.globals[j..] {
+            *global = d * *global;
+        }
+    }
+
+    /// Turns joint `j` so the direction from it to `from` points towards `to`.
+    fn aim(&mut self, j: usize, from: Vec3, to: Vec3) {
+        let pivot = self.joint(j);
+        let (a, b) = ((from - pivot).normalize_or_zero(), (to - pivot).normalize_or_zero());
+        if a == Vec3::ZERO || b == Vec3::ZERO {
+            return;
+        }
+        self.rotate(j, Quat::from_rotation_arc(a, b));
+    }
+
+    /// Writes the pose back into the rig. Bones hanging off the chain move along
+    /// with their closest chain ancestor.
+    fn apply<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>) {
+        let old = (0..rig.bones.len()).map(|i| rig.local_to_global(i).get_matrix()).collect::<Vec<_>>();
+        let deltas = self.chain.iter().zip(&self.globals)
+            .map(|(i, global)| (*i, *global * old[*i].inverse()))
+            .collect::<HashMap<usize, Mat4>>();
+
+        let mut new = old.clone();
+        for i in 0..rig.bones.len() {
+            let mut ancestor = Some(i);
+            while let Some(a) = ancestor {
+                if let Some(delta) = deltas.get(&a) {
+                    new[i] = *delta * old[i];
+                    break;
+                }
+                ancestor = rig.bones[a].get_parent();
+            }
+        }
+        rig.set_global_transforms(&new);
+    }
+}
+
+impl IkChain {
+    pub fn new<S: Transform, T: Bone<S>>(rig: &RigV1<S, T>, root: &str, tip: &str) -> Result<Self, IkError> {
+        let index = |name: &str| {
+            rig.bones.iter().position(|b| b.get_name() == name).ok_or_else(|| IkError::UnknownBone(name.to_string()))
+        };
+        let root_index = index(root)?;
+        let mut bones = vec![index(tip)?];
+        while *bones.last().unwrap() != root_index {
+            match rig.bones[*bones.last().unwrap()].get_parent() {
+                Some(parent) => bones.push(parent),
+                None => return Err(IkError::NotAChain { root: root.to_string(), tip: tip.to_string() }),
+            }
+        }
+        bones.reverse();
+        Ok(IkChain { bones })
+    }
+
+    /// Cyclic coordinate descent: turns one joint at a time, from the tip upwards,
+    /// to point the effector at the target.
+    pub fn solve_ccd<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>, target: Vec3, settings: &IkSettings) -> IkResult {
+        let mut pose = ChainPose::new(rig, &self.bones);
+        let mut iterations = 0;
+        while iterations < settings.iterations && pose.effector().distance(target) > settings.tolerance {
+            for j in (0..self.bones.len() - 1).rev() {
+                pose.aim(j, pose.effector(), target);
+            }
+            iterations += 1;
+        }
+        pose.apply(rig);
+        IkResult { iterations, error: pose.effector().distance(target) }
+    }
+
+    /// Forward and backward reaching IK: solves joint positions with fixed bone lengths,
+    /// then turns the joints to match them.
+    pub fn solve_fabrik<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>, target: Vec3, settings: &IkSettings) -> IkResult {
+        let mut pose = ChainPose::new(rig, &self.bones);
+        let mut joints = (0..self.bones.len()).map(|j| pose.joint(j)).collect::<Vec<_>>();
+        let lengths = joints.windows(2).map(|w| w[0].distance(w[1])).collect::<Vec<_>>();
+        let base = joints[0];
+        let last = joints.len() - 1;
+
+        let mut iterations = 0;
+        if base.distance(target) >= lengths.iter().sum::<f32>() {
+            // out of reach, stretch towards the target
+            let direction = (target - base).normalize_or_zero();
+            for j in 1..joints.len() {
+                joints[j] = joints[j - 1] + direction * lengths[j - 1];
+            }
+        } else {
+            while iterations < settings.iterations && joints[last].distance(target) > settings.tolerance {
+                joints[last] = target;
+                for j in (0..last).rev() {
+                    joints[j] = joints[j + 1] + (joints[j] - joints[j + 1]).normalize_or_zero() * lengths[j];
+                }
+                joints[0] = base;
+                for j in 1..joints.len() {
+                    joints[j] = joints[j - 1] + (joints[j] - joints[j - 1]).normalize_or_zero() * lengths[j - 1];
+                }
+                iterations += 1;
+            }
+        }
+
+        for j in 0..last {
+            pose.aim(j, pose.joint(j + 1), joints[j + 1]);
+        }
+        pose.apply(rig);
+        IkResult { iterations, error: pose.effector().distance(target) }
+    }
+
+    /// Analytic solver for a chain of exactly two bones (three joints), e.g. thigh, shin, foot.
+    /// The middle joint bends towards `pole`, or keeps its current bend plane without one.
+    pub fn solve_two_bone<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>, target: Vec3, pole: Option<Vec3>) -> Result<IkResult, IkError> {
+        if self.bones.len() != 3 {
+            return Err(IkError::ChainLength { expected: 3, found: self.bones.len() });
+        }
+        let mut pose = ChainPose::new(rig, &self.bones);
+        let (a, b, c) = (pose.joint(0), pose.joint(1), pose.joint(2));
+        let (upper, lower) = (a.distance(b), b.distance(c));
+        let reach = (target - a).length().clamp((upper - lower).abs() + 1e-4, upper + lower - 1e-4);
+        let forward = (target - a).normalize_or_zero();
+
+        // place the middle joint in the plane spanned by the target and the pole
+        let pole = pole.unwrap_or(b) - a;
+        let mut side = (pole - forward * pole.dot(forward)).normalize_or_zero();
+        if side == Vec3::ZERO {
+            side = forward.any_orthonormal_vector();
+        }
+        let along = (upper * upper - lower * lower + reach * reach) / (2.0 * reach);
+        let height = (upper * upper - along * along).max(0.0).sqrt();
+        let middle = a + forward * along + side * height;
+
+        pose.aim(0, b, middle);
+        pose.aim(1, pose.joint(2), target);
+        pose.apply(rig);
+        Ok(IkResult { iterations: 1, error: pose.effector().distance(target) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::daz::JointChannel;
+    use crate::scene::daz::format::{test_rig, BoneV1};
+
+    // shoulder -> elbow -> wrist along x, with a finger past the wrist
+    fn arm() -> RigV1<Mat4, BoneV1<Mat4>> {
+        let x = |x: f32| Mat4::from_translation(Vec3::new(x, 0.0, 0.0));
+        test_rig(&[("shoulder", None, x(0.0)), ("elbow", Some(0), x(1.0)), ("wrist", Some(1), x(2.0)), ("finger", Some(2), x(2.5))])
+    }
+
+    fn joint(rig: &RigV1<Mat4, BoneV1<Mat4>>, name: &str) -> Vec3 {
+        position(&rig.local_to_global(rig.bone_map[name]))
+    }
+
+    #[test]
+    fn solvers_reach_target() {
+        let target = Vec3::new(1.0, 1.0, 0.0);
+        let settings = IkSettings { iterations: 64, tolerance: 1e-4 };
+
+        let mut ccd = arm();
+        let chain = IkChain::new(&ccd, "shoulder", "wrist").unwrap();
+        assert_eq!(chain.bones, vec![0, 1, 2]);
+        assert!(chain.solve_ccd(&mut ccd, target, &settings).error < 1e-3);
+
+        let mut fabrik = arm();
+        assert!(chain.solve_fabrik(&mut fabrik, target, &settings).error < 1e-3);
+
+        let mut two_bone = arm();
+        let result = chain.solve_two_bone(&mut two_bone, target, Some(Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert!(result.error < 1e-3);
+        assert!(joint(&two_bone, "elbow").z > 0.0);
+
+        for rig in [&ccd, &fabrik, &two_bone] {
+            assert!(joint(rig, "wrist").distance(target) < 1e-3);
+            // bone lengths are kept and the finger follows the wrist
+            assert!((joint(rig, "elbow").distance(joint(rig, "shoulder")) - 1.0).abs() < 1e-3);
+            assert!((joint(rig, "finger").distance(joint(rig, "wrist")) - 0.5).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn limits_are_respected() {
+        let mut rig = arm();
+        let chain = IkChain::new(&rig, "shoulder", "wrist").unwrap();
+        let mut shoulder = JointConstraint::free("shoulder", Vec3::ZERO);
+        for channel in &mut shoulder.rotation {
+            *channel = JointChannel { min: -10.0, max: 10.0, clamped: true, ..*channel };
+        }
+        rig.bones[0].set_constraint(shoulder);
+        let result = chain.solve_ccd(&mut rig, Vec3::new(0.0, 2.0, 0.0), &IkSettings::default());
+        assert!(result.error > 0.1);
+        let elbow = joint(&rig, "elbow");
+        assert!(elbow.y.atan2(elbow.x) <= 10f32.to_radians() + 1e-4);
+    }
+}
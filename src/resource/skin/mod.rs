@@ -4,17 +4,46 @@ use ash::vk;
 use glam::Mat4;
 use gpu_allocator::MemoryLocation;
 
-use crate::{Buffer, Context, BufferInfo, scene::daz::format::{RigV1, BoneV1}};
+use crate::{Buffer, Context, BufferInfo, resource::mesh::Mesh, scene::daz::format::{RigV1, BoneV1}};
 mod rig;
 pub use rig::*;
+mod dual_quat;
+pub use dual_quat::*;
+mod cpu;
+pub use cpu::*;
+mod gpu;
+pub use gpu::*;
+mod ik;
+pub use ik::*;
+mod retarget;
+pub use retarget::*;
+mod weights;
+pub use weights::*;
+mod pose;
+pub use pose::*;
+mod symmetry;
+pub use symmetry::*;
+mod hierarchy;
+pub use hierarchy::*;
 
-#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct SkinJoint {
     pub joint_id: u32,
     pub vertex_id: u32,
     pub weight: f32,
 }
 
+/// How `skin_vertices.comp` (and `Skin::skin_vertices` on the CPU) blends joint influences.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SkinningMode {
+    #[default]
+    Linear = 0,
+    /// Avoids the candy wrapper collapse of linear blending at twisting joints like shoulders and elbows.
+    DualQuaternion = 1,
+}
+
 pub struct Skin {
     pub name: String,
     pub transforms: Vec<Mat4>,
@@ -22,17 +51,42 @@ pub struct Skin {
     pub inverse_bind_matrices: Vec<Mat4>,
     pub joints: Vec<SkinJoint>,
     pub joint_id_map: HashMap<String, u32>,
+    /// Rig bone index of every skin joint, see `bind_to_rig`. Empty means joints are rig bones.
+    pub joint_bones: Vec<usize>,
 }
 
 impl Skin {
-    pub fn transforms_from<R: Rig<S, T>, S: Transform, T: Bone<S>>(&mut self, rig: &R) {
-        self.transforms = rig.get_bones().iter().map(|bone| {
-            bone.get_local_transform().get_matrix()
-        }).collect();
-
-        self.global_bone_transforms = (0..rig.get_bones().len()).into_iter().map(|i| {
-            rig.local_to_global(i).get_matrix()
-        }).collect();
+    /// Maps the skin joints to the bones of `rig` by name and takes their inverse bind matrices,
+    /// in skin joint order like `SkinJoint::joint_id`.
+    pub fn bind_to_rig(&mut self, rig: &RigV1<Mat4, BoneV1<Mat4>>) {
+        let mut joint_bones = vec![0; self.joint_id_map.len()];
+        for (name, joint) in &self.joint_id_map {
+            let bone = rig.get_bone_by_id(name).or_else(|| rig.get_bone(name));
+            match (bone, joint_bones.get_mut(*joint as usize)) {
+                (Some(bone), Some(slot)) => *slot = bone.get_index(),
+                _ => log::warn!("skin {}: joint {} not found in the rig", self.name, name),
+            }
+        }
+        self.inverse_bind_matrices = joint_bones.iter().map(|bone| rig.bones[*bone].inverse_bind_matrix).collect();
+        self.joint_bones = joint_bones;
+    }
+
+    /// Rig bone index of skin joint `joint`.
+    pub fn joint_bone(&self, joint: usize) -> usize {
+        self.joint_bones.get(joint).copied().unwrap_or(joint)
+    }
+
+    /// Takes the pose of `rig`. `globals` is kept by the caller across frames, made with
+    /// `GlobalTransforms::new(rig)`, so only bones whose local transform changed are re-evaluated.
+    /// Returns whether the pose changed, an unchanged skin doesn't need `VulkanSkin::update`.
+    pub fn transforms_from<R: Rig<S, T>, S: Transform, T: Bone<S>>(&mut self, rig: &R, globals: &mut GlobalTransforms) -> bool {
+        globals.sync(rig);
+        if globals.update() == 0 && self.global_bone_transforms.len() == globals.len() {
+            return false;
+        }
+        self.transforms = (0..globals.len()).map(|bone| globals.local(bone)).collect();
+        self.global_bone_transforms = globals.globals().to_vec();
+        true
     }
 }
 
@@ -42,12 +96,19 @@ pub struct VulkanSkin {
     pub joints: Buffer,
     pub global_bone_transforms: Buffer,
     pub inverse_bind_matrices: Buffer,
+    // inputs of skin_vertices.comp, see `GpuSkinning`
+    pub rest_vertices: Buffer,
+    pub skinning_matrices: Buffer,
+    pub dual_quaternions: Buffer,
+    pub influences: Buffer,
+    pub vertex_offsets: Buffer,
 }
 
 
 impl VulkanSkin {
     // TODO: make this generic over <T: Into<Skin>>
-    pub fn from_data(context: Arc<Context>, name: String, skin: &Skin) -> Self {
+    /// `mesh` is the skinned mesh, its vertices are kept as the bind pose to skin from.
+    pub fn from_data(context: Arc<Context>, name: String, skin: &Skin, mesh: &Mesh) -> Self {
 
         let transforms = Buffer::from_data(
                 context.clone(),
@@ -91,12 +152,45 @@ impl VulkanSkin {
             &skin.inverse_bind_matrices,
         );
 
+        let rest_vertices = Buffer::from_data(
+                context.clone(),
+                BufferInfo::default().usage_storage().gpu_only(),
+            &mesh.vertices,
+        );
+        let skinning_matrices = Buffer::from_data(
+                context.clone(),
+                BufferInfo::default().usage_storage().cpu_to_gpu(),
+            &skin.skinning_matrices(),
+        );
+        let dual_quaternions = Buffer::from_data(
+                context.clone(),
+                BufferInfo::default().usage_storage().cpu_to_gpu(),
+            &skin.dual_quaternions(),
+        );
+
+        let SkinInfluences { joints: sorted_joints, offsets } = skin.influences_for(mesh.vertices.len());
+        let influences = Buffer::from_data(
+                context.clone(),
+                BufferInfo::default().usage_storage().gpu_only(),
+            &sorted_joints,
+        );
+        let vertex_offsets = Buffer::from_data(
+                context.clone(),
+                BufferInfo::default().usage_storage().gpu_only(),
+            &offsets,
+        );
+
         VulkanSkin {
             name,
             transforms,
             joints,
             global_bone_transforms,
             inverse_bind_matrices,
+            rest_vertices,
+            skinning_matrices,
+            dual_quaternions,
+            influences,
+            vertex_offsets,
         }
     }
 
@@ -104,6 +198,9 @@ impl VulkanSkin {
         self.transforms.update(&skin.transforms);
         self.global_bone_transforms.update(&skin.global_bone_transforms);
         self.joints.update(&skin.joints);
+        let skinning_matrices = skin.skinning_matrices();
+        self.skinning_matrices.update(&skinning_matrices);
+        self.dual_quaternions.update(&skinning_matrices.iter().map(DualQuat::from_mat4).collect::<Vec<_>>());
     }
 
 }
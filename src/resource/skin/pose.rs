@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::scene::daz::format::RigV1;
+use super::{Bone, Rig, Transform};
+
+fn unit_scale() -> Vec3 {
+    Vec3::ONE
+}
+
+/// Local transform of one bone, the decomposed `Bone::get_local_transform`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BonePose {
+    pub rotation: Quat,
+    #[serde(default)]
+    pub translation: Vec3,
+    #[serde(default = "unit_scale")]
+    pub scale: Vec3,
+}
+
+impl BonePose {
+    pub fn from_mat4(matrix: &Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        BonePose { rotation, translation, scale }
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// A rig pose keyed by bone id, sorted so saved poses diff cleanly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pose {
+    pub bones: BTreeMap<String, BonePose>,
+}
+
+impl Pose {
+    /// Local transforms of every bone of `rig`.
+    pub fn capture<R: Rig<S, T>, S: Transform, T: Bone<S>>(rig: &R) -> Self {
+        Pose {
+            bones: rig.get_bones().iter()
+                .map(|bone| (bone.get_id().to_string(), BonePose::from_mat4(&bone.get_local_transform().get_matrix())))
+                .collect(),
+        }
+    }
+
+    /// Sets the local transform of every bone with an id in the pose, returns the ids that have no bone.
+    pub fn apply<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>) -> Vec<&str> {
+        let mut missing = Vec::new();
+        for (id, pose) in &self.bones {
+            match rig.bones.iter_mut().find(|bone| bone.get_id() == id) {
+                Some(bone) => bone.set_local_transform(S::from_mat4(pose.to_mat4())),
+                None => missing.push(id.as_str()),
+            }
+        }
+        missing
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_json(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::daz::format::test_rig;
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut rig = test_rig(&[("hip", None, Mat4::IDENTITY), ("spine", Some(0), Mat4::IDENTITY)]);
+        let local = Mat4::from_scale_rotation_translation(Vec3::splat(1.5), Quat::from_rotation_x(0.4), Vec3::new(0.0, 2.0, 0.0));
+        rig.bones[1].set_local_transform(local);
+
+        let pose = Pose::capture(&rig);
+        let loaded = Pose::from_json(&pose.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, pose);
+
+        // scale and translation are optional in hand written poses
+        let partial = Pose::from_json(r#"{ "bones": { "hip": { "rotation": [0, 0, 0, 1] }, "tail": { "rotation": [0, 0, 0, 1] } } }"#).unwrap();
+        assert_eq!(partial.bones["hip"].scale, Vec3::ONE);
+
+        let mut other = rig.clone();
+        other.bones[1].set_local_transform(Mat4::IDENTITY);
+        assert!(loaded.apply(&mut other).is_empty());
+        assert!(other.bones[1].local_transform.abs_diff_eq(local, 1e-5));
+        assert_eq!(partial.apply(&mut other), vec!["tail"]);
+    }
+}
@@ -0,0 +1,255 @@
+use glam::{Mat4, Quat, Vec3};
+
+use crate::scene::daz::format::RigV1;
+use super::{Bone, Rig, Transform};
+
+pub(super) const SIDES: [(&str, &str, &str); 2] = [("l", "l_", "Left"), ("r", "r_", "Right")];
+
+/// Which source bone drives which target bone. The root pair carries root motion.
+#[derive(Debug, Clone)]
+pub struct BoneMapping {
+    pub root: (String, String),
+    pub pairs: Vec<(String, String)>,
+}
+
+// (genesis 8, genesis 9, humanoid) names of the unsided bones
+const CENTER: [(&str, &str, &str); 9] = [
+    ("hip", "hip", "Hips"),
+    ("abdomenLower", "spine1", "Spine"),
+    ("abdomenUpper", "spine2", "Spine1"),
+    ("chestLower", "spine3", "Spine2"),
+    ("chestUpper", "spine4", "Spine3"),
+    ("neckLower", "neck1", "Neck"),
+    ("neckUpper", "neck2", "Neck1"),
+    ("head", "head", "Head"),
+    ("pelvis", "pelvis", "Pelvis"),
+];
+
+// the same for bones that exist on both sides, without the side prefix
+const SIDED: [(&str, &str, &str); 24] = [
+    ("Collar", "shoulder", "Shoulder"),
+    ("ShldrBend", "upperarm", "Arm"),
+    ("ForearmBend", "forearm", "ForeArm"),
+    ("Hand", "hand", "Hand"),
+    ("ThighBend", "thigh", "UpLeg"),
+    ("Shin", "shin", "Leg"),
+    ("Foot", "foot", "Foot"),
+    ("Toe", "toes", "ToeBase"),
+    ("Eye", "eye", "Eye"),
+    ("Thumb1", "thumb1", "HandThumb1"),
+    ("Thumb2", "thumb2", "HandThumb2"),
+    ("Thumb3", "thumb3", "HandThumb3"),
+    ("Index1", "index1", "HandIndex1"),
+    ("Index2", "index2", "HandIndex2"),
+    ("Index3", "index3", "HandIndex3"),
+    ("Mid1", "mid1", "HandMiddle1"),
+    ("Mid2", "mid2", "HandMiddle2"),
+    ("Mid3", "mid3", "HandMiddle3"),
+    ("Ring1", "ring1", "HandRing1"),
+    ("Ring2", "ring2", "HandRing2"),
+    ("Ring3", "ring3", "HandRing3"),
+    ("Pinky1", "pinky1", "HandPinky1"),
+    ("Pinky2", "pinky2", "HandPinky2"),
+    ("Pinky3", "pinky3", "HandPinky3"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skeleton {
+    Genesis8,
+    Genesis9,
+    /// Mixamo style names (`Hips`, `LeftUpLeg`, ...), used by most glTF humanoids.
+    Humanoid,
+}
+
+impl Skeleton {
+    fn pick(self, (g8, g9, humanoid): (&'static str, &'static str, &'static str)) -> &'static str {
+        match self {
+            Skeleton::Genesis8 => g8,
+            Skeleton::Genesis9 => g9,
+            Skeleton::Humanoid => humanoid,
+        }
+    }
+
+    fn names(self) -> Vec<String> {
+        let mut names = CENTER.iter().map(|names| self.pick(*names).to_string()).collect::<Vec<_>>();
+        for side in SIDES {
+            names.extend(SIDED.iter().map(|names| format!("{}{}", self.pick(side), self.pick(*names))));
+        }
+        names
+    }
+}
+
+impl BoneMapping {
+    /// Preset between two known skeletons, e.g. `BoneMapping::preset(Skeleton::Genesis8, Skeleton::Humanoid)`.
+    pub fn preset(source: Skeleton, target: Skeleton) -> Self {
+        let pairs = source.names().into_iter().zip(target.names()).collect::<Vec<_>>();
+        BoneMapping {
+            root: pairs[0].clone(),
+            pairs,
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        BoneMapping {
+            root: (self.root.1.clone(), self.root.0.clone()),
+            pairs: self.pairs.iter().map(|(s, t)| (t.clone(), s.clone())).collect(),
+        }
+    }
+
+    pub fn target_of(&self, source: &str) -> Option<&str> {
+        self.pairs.iter().find(|(s, _)| s == source).map(|(_, t)| t.as_str())
+    }
+}
+
+// glTF exporters like to namespace bones (`mixamorig:Hips`)
+fn find_bone<S: Transform, T: Bone<S>>(bones: &[T], name: &str) -> Option<usize> {
+    bones.iter().position(|b| b.get_name() == name)
+        .or_else(|| bones.iter().position(|b| b.get_name().rsplit(':').next() == Some(name)))
+}
+
+fn decompose(m: &Mat4) -> (Vec3, Quat, Vec3) {
+    let (scale, rotation, translation) = m.to_scale_rotation_translation();
+    (scale, rotation.normalize(), translation)
+}
+
+/// Transfers poses between rigs with different bone names, rest orientations and proportions.
+/// Rotations are carried over as world space offsets from the rest pose, so differing
+/// bone axes and rotation orders don't matter.
+pub struct Retargeter {
+    /// (source, target) bone indices
+    pairs: Vec<(usize, usize)>,
+    root: Option<(usize, usize)>,
+    source_rest: Vec<Mat4>,
+    target_rest: Vec<Mat4>,
+    /// Factor applied to the root translation, defaults to the ratio of the root heights.
+    pub root_scale: f32,
+}
+
+impl Retargeter {
+    /// Captures the rest (bind) poses of both rigs. Mapped bones missing from either rig are ignored.
+    pub fn new<R, S, T, S2, T2>(source: &R, target: &RigV1<S2, T2>, mapping: &BoneMapping) -> Self
+    where
+        R: Rig<S, T>,
+        S: Transform,
+        T: Bone<S>,
+        S2: Transform,
+        T2: Bone<S2>,
+    {
+        let pair = |(s, t): &(String, String)| Some((find_bone(source.get_bones(), s)?, find_bone(&target.bones, t)?));
+        let source_rest = source.get_bones().iter().map(|b| b.global_transform().get_matrix()).collect::<Vec<_>>();
+        let target_rest = target.bones.iter().map(|b| b.global_transform().get_matrix()).collect::<Vec<_>>();
+        let root = pair(&mapping.root);
+        let root_scale = root
+            .map(|(s, t)| target_rest[t].w_axis.y / source_rest[s].w_axis.y)
+            .filter(|scale| scale.is_finite() && *scale > 0.0)
+            .unwrap_or(1.0);
+        Retargeter {
+            pairs: mapping.pairs.iter().filter_map(pair).collect(),
+            root,
+            source_rest,
+            target_rest,
+            root_scale,
+        }
+    }
+
+    /// Poses `target` like the current pose of `source`.
+    pub fn retarget<R, S, T, S2, T2>(&self, source: &R, target: &mut RigV1<S2, T2>)
+    where
+        R: Rig<S, T>,
+        S: Transform,
+        T: Bone<S>,
+        S2: Transform,
+        T2: Bone<S2>,
+    {
+        let count = target.bones.len();
+        let depth = |mut i: usize| {
+            let mut depth = 0;
+            while let Some(parent) = target.bones[i].get_parent() {
+                i = parent;
+                depth += 1;
+            }
+            depth
+        };
+        let mut order = (0..count).collect::<Vec<_>>();
+        order.sort_by_key(|i| depth(*i));
+
+        // world offset from the rest pose for every target bone
+        let mut deltas = vec![Mat4::IDENTITY; count];
+        let mut globals = self.target_rest.clone();
+        for i in order {
+            let parent_delta = target.bones[i].get_parent().map_or(Mat4::IDENTITY, |p| deltas[p]);
+            let rest = self.target_rest[i];
+            globals[i] = match self.pairs.iter().find(|(_, t)| *t == i) {
+                Some((s, _)) => {
+                    let (_, source_rest_rotation, source_rest_position) = decompose(&self.source_rest[*s]);
+                    let (_, source_rotation, source_position) = decompose(&source.local_to_global(*s).get_matrix());
+                    let (scale, rest_rotation, rest_position) = decompose(&rest);
+                    let rotation = source_rotation * source_rest_rotation.inverse() * rest_rotation;
+                    let position = if self.root == Some((*s, i)) {
+                        rest_position + (source_position - source_rest_position) * self.root_scale
+                    } else {
+                        parent_delta.transform_point3(rest_position)
+                    };
+                    Mat4::from_scale_rotation_translation(scale, rotation, position)
+                }
+                None => parent_delta * rest,
+            };
+            deltas[i] = globals[i] * rest.inverse();
+        }
+
+        target.set_global_transforms(&globals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::daz::format::test_rig as rig;
+    use glam::Vec4Swizzles;
+
+    #[test]
+    fn presets_line_up() {
+        let mapping = BoneMapping::preset(Skeleton::Genesis8, Skeleton::Humanoid);
+        assert_eq!(mapping.root, ("hip".to_string(), "Hips".to_string()));
+        assert_eq!(mapping.target_of("lShldrBend"), Some("LeftArm"));
+        assert_eq!(mapping.target_of("rShin"), Some("RightLeg"));
+        let g9 = BoneMapping::preset(Skeleton::Genesis9, Skeleton::Genesis8);
+        assert_eq!(g9.target_of("l_upperarm"), Some("lShldrBend"));
+        assert_eq!(g9.inverse().target_of("lShldrBend"), Some("l_upperarm"));
+    }
+
+    #[test]
+    fn transfers_rotation_and_scaled_root_motion() {
+        let mut source = rig(&[
+            ("hip", None, Mat4::from_translation(Vec3::new(0.0, 1.0, 0.0))),
+            ("abdomenLower", Some(0), Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0))),
+        ]);
+        // the target is half as tall, its bones are rotated at rest and namespaced
+        let turned = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let mut target = rig(&[
+            ("mixamorig:Hips", None, Mat4::from_rotation_translation(turned, Vec3::new(0.0, 0.5, 0.0))),
+            ("mixamorig:Spine", Some(0), Mat4::from_rotation_translation(turned, Vec3::new(0.0, 1.0, 0.0))),
+            ("mixamorig:Spine1", Some(1), Mat4::from_rotation_translation(turned, Vec3::new(0.0, 1.5, 0.0))),
+        ]);
+        let retargeter = Retargeter::new(&source, &target, &BoneMapping::preset(Skeleton::Genesis8, Skeleton::Humanoid));
+        assert_eq!(retargeter.root_scale, 0.5);
+
+        // move the hip sideways and lean it
+        let lean = Quat::from_rotation_z(std::f32::consts::FRAC_PI_4);
+        let hip = Mat4::from_rotation_translation(lean, Vec3::new(0.4, 1.0, 0.0));
+        let abdomen = hip * Mat4::from_translation(Vec3::Y);
+        source.bones[0].set_local_transform(hip);
+        source.bones[1].set_local_transform(abdomen * hip.inverse());
+        retargeter.retarget(&source, &mut target);
+
+        let hips = target.local_to_global(0);
+        let (_, rotation, position) = decompose(&hips);
+        assert!(position.abs_diff_eq(Vec3::new(0.2, 0.5, 0.0), 1e-5));
+        assert!(rotation.abs_diff_eq(lean * turned, 1e-5));
+        // children keep their offsets and follow the lean
+        let spine = target.local_to_global(1).w_axis.xyz();
+        assert!(spine.abs_diff_eq(position + lean * Vec3::new(0.0, 0.5, 0.0), 1e-5));
+        let spine1 = target.local_to_global(2).w_axis.xyz();
+        assert!((spine1.distance(spine) - 0.5).abs() < 1e-5);
+    }
+}
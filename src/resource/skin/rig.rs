@@ -3,7 +3,7 @@ use std::error::Error;
 use std::ops::{Mul, Add};
 use std::fmt::Debug;
 
-use crate::scene::daz::dsf;
+use crate::scene::daz::{dsf, JointConstraint};
 
 pub trait Transform: Mul<Output = Self> + Add<Output = Self> + Sized + Clone + Copy + Send + Sync + Debug {
     fn from_mat4(mat: Mat4) -> Self;
@@ -21,7 +21,7 @@ pub trait Transform: Mul<Output = Self> + Add<Output = Self> + Sized + Clone + C
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RotationOrder {
     XYZ,
     XZY,
@@ -31,6 +31,55 @@ pub enum RotationOrder {
     ZYX,
 }
 
+impl RotationOrder {
+    /// glam equivalent, `Quat::from_euler` composes the axes like `apply_rotation_order`.
+    pub fn euler_rot(self) -> EulerRot {
+        match self {
+            RotationOrder::XYZ => EulerRot::XYZ,
+            RotationOrder::XZY => EulerRot::XZY,
+            RotationOrder::YXZ => EulerRot::YXZ,
+            RotationOrder::YZX => EulerRot::YZX,
+            RotationOrder::ZXY => EulerRot::ZXY,
+            RotationOrder::ZYX => EulerRot::ZYX,
+        }
+    }
+
+    /// Axis (0 = x, 1 = y, 2 = z) of the first, second and third rotation.
+    pub fn axes(self) -> [usize; 3] {
+        match self {
+            RotationOrder::XYZ => [0, 1, 2],
+            RotationOrder::XZY => [0, 2, 1],
+            RotationOrder::YXZ => [1, 0, 2],
+            RotationOrder::YZX => [1, 2, 0],
+            RotationOrder::ZXY => [2, 0, 1],
+            RotationOrder::ZYX => [2, 1, 0],
+        }
+    }
+
+    /// Order in which the axes are listed, e.g. `[2, 0, 1]` is ZXY.
+    pub fn from_axes(axes: [usize; 3]) -> Option<Self> {
+        [RotationOrder::XYZ, RotationOrder::XZY, RotationOrder::YXZ, RotationOrder::YZX, RotationOrder::ZXY, RotationOrder::ZYX]
+            .into_iter()
+            .find(|order| order.axes() == axes)
+    }
+
+    /// Rotation from per axis angles in radians.
+    pub fn to_quat(self, angles: Vec3) -> Quat {
+        let [a, b, c] = self.axes();
+        Quat::from_euler(self.euler_rot(), angles[a], angles[b], angles[c])
+    }
+
+    /// Per axis angles in radians, the inverse of `to_quat`.
+    pub fn to_angles(self, rotation: Quat) -> Vec3 {
+        let (first, second, third) = rotation.to_euler(self.euler_rot());
+        let mut angles = Vec3::ZERO;
+        for (axis, angle) in self.axes().into_iter().zip([first, second, third]) {
+            angles[axis] = angle;
+        }
+        angles
+    }
+}
+
 impl Into<RotationOrder> for dsf::RotationOrder {
     fn into(self) -> RotationOrder {
         match self {
@@ -44,6 +93,11 @@ impl Into<RotationOrder> for dsf::RotationOrder {
     }   
 }
 
+/// Rotation of per axis angles in radians, matches `RotationOrder::to_quat`.
+pub fn rotation_matrix(rotation_order: RotationOrder, rotation: Vec3) -> Mat4 {
+    apply_rotation_order(rotation_order, Mat4::from_rotation_x(rotation.x), Mat4::from_rotation_y(rotation.y), Mat4::from_rotation_z(rotation.z))
+}
+
 fn apply_rotation_order(rotation_order: RotationOrder, rX: Mat4, rY: Mat4, rZ: Mat4) -> Mat4 {
     match rotation_order {
         RotationOrder::XYZ => rX * rY * rZ,
@@ -60,40 +114,9 @@ impl Transform for Mat4 {
         mat
     }
     fn from_scale_rotation_translation(scale: Vec3, orientation:Vec3, rotation: Vec3, translation: Vec3, rotation_order: RotationOrder) -> Self {
-        // would be nice if this just worked vvvv
-        // Mat4::from_scale_rotation_translation(scale, Quat::from_rotation_arc(rotation, orientation), translation)
-        
-        let cos_x = rotation.x.cos();
-        let cos_y = rotation.y.cos();
-        let cos_z = rotation.z.cos();
-        let sin_x = rotation.x.sin();   
-        let sin_y = rotation.y.sin();
-        let sin_z = rotation.z.sin();
-
-        println!("orientation: {:?}", orientation);
-
-        let rX = glam::mat4(
-            vec4(1.0, 0.0, 0.0, 0.0),
-            vec4(0.0, cos_x, -sin_x, 0.0),
-            vec4(0.0, sin_x, cos_x, 0.0), 
-            vec4(0.0, 0.0, 0.0, 0.0) // <- zero because we add the translation later
-            );
-        let rY = glam::mat4(
-            vec4(cos_y, 0.0, sin_y, 0.0),
-            vec4(0.0, 1.0, 0.0, 0.0),
-            vec4(-sin_y, 0.0, cos_y, 0.0),
-            vec4(0.0, 0.0, 0.0, 0.0)
-        );
-        
-        let rZ = glam::mat4(
-            vec4(cos_z, -sin_z, 0.0, 0.0),
-            vec4(sin_z, cos_z, 0.0, 0.0),
-            vec4(0.0, 0.0, 1.0, 0.0),
-            vec4(0.0, 0.0, 0.0, 0.0)
-        );
-
-        apply_rotation_order(rotation_order, rX, rY, rZ) * Mat4::from_scale(scale) + Mat4::from_translation(translation)
-
+        // rotation and scale act on the axes of the orientation frame, like DAZ Studio does
+        let orientation = rotation_matrix(RotationOrder::XYZ, orientation);
+        Mat4::from_translation(translation) * orientation * rotation_matrix(rotation_order, rotation) * Mat4::from_scale(scale) * orientation.inverse()
     }
     fn get_position(&self) -> Vec3 {
         self.w_axis.xyz()
@@ -114,15 +137,8 @@ impl Transform for Mat4 {
         self.inverse().transpose()
     }
     fn get_d_quat(&self) -> (Quat, Quat) {
-        let real = glam::Quat::from_mat4(self);
-        // super ugly because the type stored in the matrix is not known
-        let mut v = glam::Vec4::ZERO;
-        let z = self.z_axis.xyz();
-        v[1] = z.x;
-        v[2] = z.y;
-        v[3] = z.z;
-        let dual = glam::Quat::from_vec4(v);
-        (real, dual)
+        let dq = super::DualQuat::from_mat4(self);
+        (dq.real, dq.dual)
     }
     fn zero() -> Self {
         Mat4::IDENTITY
@@ -146,6 +162,11 @@ pub trait Bone<T: Transform>: Clone + Send + Sync {
     fn set_global_transform(&mut self, global_transform: T);
     fn global_transform(&self) -> &T;
     fn add_child(&mut self, child: String);
+    /// Channel limits and scale inheritance of the joint, for formats that describe them.
+    fn get_constraint(&self) -> Option<&JointConstraint> {
+        None
+    }
+    fn set_constraint(&mut self, _constraint: JointConstraint) {}
 }
 
 pub trait Rig<S: Transform, T: Bone<S>>: IntoIterator<Item = T> + Clone {
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use glam::{IVec3, Mat4, Vec3, Vec4Swizzles};
+
+use crate::resource::mesh::Mesh;
+use crate::scene::daz::dsf;
+use crate::scene::daz::format::RigV1;
+use super::retarget::SIDES;
+use super::{Bone, Skin, SkinJoint, Transform};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// +x, the figure's left when it faces +z like DAZ figures do.
+    Left,
+    Right,
+}
+
+impl Side {
+    fn contains(self, x: f32, tolerance: f32) -> bool {
+        match self {
+            Side::Left => x > tolerance,
+            Side::Right => x < -tolerance,
+        }
+    }
+}
+
+/// Left/right bone pairs of a rig, mirrored across the x = 0 plane.
+#[derive(Debug, Clone, Default)]
+pub struct Symmetry {
+    pub pairs: Vec<(String, String)>,
+}
+
+/// Name of the bone on the other side from the naming conventions in `SIDES`, e.g. `lShin` and `r_shin`.
+fn mirror_by_name(name: &str) -> Option<String> {
+    let [left, right] = SIDES;
+    for (from, to) in [(left, right), (right, left)] {
+        for (prefix, other) in [(from.2, to.2), (from.1, to.1), (from.0, to.0)] {
+            if let Some(rest) = name.strip_prefix(prefix) {
+                // the bare `l`/`r` prefix needs a capital after it, `lShin` but not `lowerJaw`
+                if prefix.len() == 1 && !rest.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    continue;
+                }
+                return Some(format!("{}{}", other, rest));
+            }
+        }
+    }
+    None
+}
+
+impl Symmetry {
+    /// Pairs the names that have a counterpart on the other side.
+    pub fn by_name<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let names = names.into_iter().collect::<Vec<_>>();
+        let mut symmetry = Symmetry::default();
+        for name in &names {
+            if let Some(other) = mirror_by_name(name).filter(|other| names.contains(&other.as_str())) {
+                symmetry.add(name, &other);
+            }
+        }
+        symmetry
+    }
+
+    /// Pairs the bones a DSF skin binding deforms by name. The binding's selection map only ties
+    /// face groups to the bone of the same name, so it says nothing about sides.
+    pub fn from_dsf(skin: &dsf::Skin) -> Self {
+        let names = skin.joints.iter()
+            .map(|joint| joint.node.rsplit('#').next().unwrap_or(&joint.node))
+            .collect::<Vec<_>>();
+        Symmetry::by_name(names)
+    }
+
+    fn add(&mut self, a: &str, b: &str) {
+        if self.mirror(a).is_none() && self.mirror(b).is_none() {
+            self.pairs.push((a.to_string(), b.to_string()));
+        }
+    }
+
+    /// The bone on the other side, `None` for bones on the symmetry plane.
+    pub fn mirror(&self, name: &str) -> Option<&str> {
+        self.pairs.iter().find_map(|(a, b)| {
+            if a == name {
+                Some(b.as_str())
+            } else if b == name {
+                Some(a.as_str())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn mirror_index<S: Transform, T: Bone<S>>(&self, rig: &RigV1<S, T>, index: usize) -> usize {
+        self.mirror(rig.bones[index].get_name())
+            .and_then(|name| rig.bones.iter().position(|b| b.get_name() == name))
+            .unwrap_or(index)
+    }
+
+    fn pose<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>, keep: impl Fn(usize, usize) -> bool) {
+        let flip = Mat4::from_scale(Vec3::new(-1.0, 1.0, 1.0));
+        let count = rig.bones.len();
+        let binds = rig.bones.iter().map(|b| b.global_transform().get_matrix()).collect::<Vec<_>>();
+        let globals = (0..count).map(|i| rig.local_to_global(i).get_matrix()).collect::<Vec<_>>();
+        // world offset from the rest pose, mirrored from the other side
+        let mirrored = (0..count).map(|i| {
+            let other = self.mirror_index(rig, i);
+            if keep(i, other) {
+                return globals[i];
+            }
+            let delta = globals[other] * binds[other].inverse();
+            flip * delta * flip * binds[i]
+        }).collect::<Vec<_>>();
+        rig.set_global_transforms(&mirrored);
+    }
+
+    /// Flips the whole pose, left bones take the mirrored pose of the right ones and the other way round.
+    pub fn mirror_pose<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>) {
+        self.pose(rig, |_, _| false);
+    }
+
+    /// Copies the pose of the bones on `from` to their counterparts, the rest keeps its pose.
+    pub fn copy_pose<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>, from: Side) {
+        let centers = rig.bones.iter().map(|b| b.global_transform().get_matrix().w_axis.x).collect::<Vec<_>>();
+        self.pose(rig, |i, other| i == other || !from.contains(centers[other], 0.0));
+    }
+
+    /// Replaces the weights of the vertices on the other side with the mirrored weights of their
+    /// counterpart on `from`. Counterparts are matched by position within `tolerance`,
+    /// returns how many vertices had none and were left alone.
+    pub fn mirror_weights(&self, skin: &mut Skin, mesh: &Mesh, from: Side, tolerance: f32) -> usize {
+        let positions = mesh.vertices.iter().map(|v| v.pos.xyz()).collect::<Vec<_>>();
+        let cell = |p: Vec3| (p / tolerance.max(f32::EPSILON)).floor().as_ivec3();
+        let mut grid: HashMap<IVec3, Vec<usize>> = HashMap::new();
+        for (i, p) in positions.iter().enumerate().filter(|(_, p)| from.contains(p.x, tolerance)) {
+            grid.entry(cell(*p)).or_default().push(i);
+        }
+
+        let joint_names = skin.joint_id_map.iter().map(|(name, id)| (*id, name.as_str())).collect::<HashMap<_, _>>();
+        let mirror_joint = |id: u32| {
+            joint_names.get(&id)
+                .and_then(|name| self.mirror(name))
+                .and_then(|name| skin.joint_id_map.get(name).copied())
+                .unwrap_or(id)
+        };
+
+        let influences = skin.influences();
+        let mut replaced = vec![false; positions.len()];
+        let mut mirrored = Vec::new();
+        let mut unmatched = 0;
+        for (i, p) in positions.iter().enumerate() {
+            if p.x.abs() <= tolerance || from.contains(p.x, tolerance) {
+                continue;
+            }
+            let target = Vec3::new(-p.x, p.y, p.z);
+            let center = cell(target);
+            let mut best: Option<(f32, usize)> = None;
+            for offset in (-1..=1).flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z)))) {
+                for &candidate in grid.get(&(center + offset)).into_iter().flatten() {
+                    let distance = positions[candidate].distance(target);
+                    if distance <= tolerance && best.is_none_or(|(d, _)| distance < d) {
+                        best = Some((distance, candidate));
+                    }
+                }
+            }
+            match best {
+                Some((_, source)) => {
+                    replaced[i] = true;
+                    mirrored.extend(influences.of(source).iter().map(|j| SkinJoint {
+                        joint_id: mirror_joint(j.joint_id),
+                        vertex_id: i as u32,
+                        weight: j.weight,
+                    }));
+                }
+                None => unmatched += 1,
+            }
+        }
+
+        skin.joints.retain(|j| !replaced.get(j.vertex_id as usize).copied().unwrap_or(false));
+        skin.joints.extend(mirrored);
+        unmatched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::mesh::ModelVertex;
+    use crate::scene::daz::format::{test_rig, BoneV1};
+    use glam::{Quat, Vec4};
+
+    fn rig() -> RigV1<Mat4, BoneV1<Mat4>> {
+        test_rig(&[
+            ("hip", None, Mat4::IDENTITY),
+            ("lShldr", Some(0), Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0))),
+            ("rShldr", Some(0), Mat4::from_translation(Vec3::new(-10.0, 0.0, 0.0))),
+        ])
+    }
+
+    #[test]
+    fn mirrors_pose() {
+        let mut rig = rig();
+        let symmetry = Symmetry::by_name(rig.bones.iter().map(|b| b.name.as_str()));
+        assert_eq!(symmetry.mirror("lShldr"), Some("rShldr"));
+        assert_eq!(symmetry.mirror("hip"), None);
+        assert_eq!(mirror_by_name("lowerJaw"), None);
+        assert_eq!(mirror_by_name("l_upperarm").as_deref(), Some("r_upperarm"));
+        let binding: dsf::Skin = serde_json::from_value(serde_json::json!({
+            "node": "#hip", "geometry": "#body", "vertex_count": 3,
+            "joints": [{ "id": "hip", "node": "#hip" }, { "id": "lShldr", "node": "#lShldr" }, { "id": "rShldr", "node": "figure.dsf#rShldr" }],
+            "selection_map": [{ "id": "default", "mappings": [["hip", "hip"], ["lShldr", "lShldr"], ["rShldr", "rShldr"]] }]
+        })).unwrap();
+        assert_eq!(Symmetry::from_dsf(&binding).pairs, symmetry.pairs);
+
+        // raise the left arm by rotating it around z
+        let raised = Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0)) * Mat4::from_quat(Quat::from_rotation_z(0.5));
+        rig.bones[1].set_local_transform(raised);
+        let left_tip = rig.local_to_global(1).transform_point3(Vec3::new(5.0, 0.0, 0.0));
+
+        symmetry.copy_pose(&mut rig, Side::Left);
+        let right_tip = rig.local_to_global(2).transform_point3(Vec3::new(-5.0, 0.0, 0.0));
+        assert!(right_tip.abs_diff_eq(Vec3::new(-left_tip.x, left_tip.y, left_tip.z), 1e-4));
+        assert!(rig.local_to_global(1).abs_diff_eq(raised, 1e-5));
+
+        // flipping swaps the sides, twice gives the original pose
+        rig.bones[2].set_local_transform(Mat4::from_translation(Vec3::new(-10.0, 0.0, 0.0)));
+        symmetry.mirror_pose(&mut rig);
+        assert!(rig.local_to_global(1).abs_diff_eq(Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0)), 1e-5));
+        symmetry.mirror_pose(&mut rig);
+        assert!(rig.local_to_global(1).abs_diff_eq(raised, 1e-5));
+    }
+
+    #[test]
+    fn mirrors_weights() {
+        let vertex = |x: f32| ModelVertex { pos: Vec4::new(x, 1.0, 0.0, 1.0), color: Vec4::ONE, normal: Vec4::Y, uv: Vec4::ZERO };
+        let mesh = Mesh::new("arms".to_string(), vec![vertex(12.0), vertex(0.0), vertex(-12.001), vertex(-30.0)], Vec::new(), Mat4::IDENTITY, Vec::new());
+        let mut skin = Skin {
+            name: "arms".to_string(),
+            transforms: vec![Mat4::IDENTITY; 3],
+            global_bone_transforms: vec![Mat4::IDENTITY; 3],
+            inverse_bind_matrices: vec![Mat4::IDENTITY; 3],
+            joints: vec![
+                SkinJoint { joint_id: 1, vertex_id: 0, weight: 0.75 },
+                SkinJoint { joint_id: 0, vertex_id: 0, weight: 0.25 },
+                SkinJoint { joint_id: 0, vertex_id: 1, weight: 1.0 },
+                SkinJoint { joint_id: 0, vertex_id: 2, weight: 1.0 },
+            ],
+            joint_id_map: [("hip".to_string(), 0), ("lShldr".to_string(), 1), ("rShldr".to_string(), 2)].into(),
+            joint_bones: Vec::new(),
+        };
+        let symmetry = Symmetry::by_name(["hip", "lShldr", "rShldr"]);
+
+        // vertex 3 has no counterpart
+        assert_eq!(symmetry.mirror_weights(&mut skin, &mesh, Side::Left, 0.01), 1);
+        let mut right = skin.joints.iter().filter(|j| j.vertex_id == 2).map(|j| (j.joint_id, j.weight)).collect::<Vec<_>>();
+        right.sort_by_key(|(id, _)| *id);
+        assert_eq!(right, vec![(0, 0.25), (2, 0.75)]);
+        assert_eq!(skin.joints.iter().filter(|j| j.vertex_id == 1).count(), 1);
+    }
+}
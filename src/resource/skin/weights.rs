@@ -0,0 +1,123 @@
+use glam::{UVec4, Vec4};
+
+use super::{Skin, SkinJoint};
+
+/// Influences a `PackedInfluence` can hold, the usual limit of realtime skinning.
+pub const MAX_PACKED_INFLUENCES: usize = 4;
+
+/// Up to four influences of one vertex, laid out like `uvec4 joints; vec4 weights;` in glsl.
+/// Unused slots have a weight of zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PackedInfluence {
+    pub joints: UVec4,
+    pub weights: Vec4,
+}
+
+impl PackedInfluence {
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        (0..MAX_PACKED_INFLUENCES)
+            .map(|i| (self.joints[i], self.weights[i]))
+            .filter(|(_, weight)| *weight > 0.0)
+    }
+}
+
+/// Keeps the `max` heaviest influences of a vertex and scales them to sum up to one.
+/// Weights that aren't positive are dropped.
+fn limit(joints: &[SkinJoint], max: usize) -> Vec<SkinJoint> {
+    let mut kept = joints.iter().copied().filter(|j| j.weight.is_finite() && j.weight > 0.0).collect::<Vec<_>>();
+    // ties go to the lower joint id so the result doesn't depend on the input order
+    kept.sort_by(|a, b| b.weight.total_cmp(&a.weight).then(a.joint_id.cmp(&b.joint_id)));
+    kept.truncate(max);
+    let total: f32 = kept.iter().map(|j| j.weight).sum();
+    for joint in &mut kept {
+        joint.weight /= total;
+    }
+    kept
+}
+
+impl Skin {
+    /// Most influences any vertex has.
+    pub fn max_influences(&self) -> usize {
+        let influences = self.influences();
+        influences.offsets.windows(2).map(|w| (w[1] - w[0]) as usize).max().unwrap_or(0)
+    }
+
+    /// Scales the weights of every vertex to sum up to one.
+    pub fn normalize_weights(&mut self) {
+        self.limit_influences(usize::MAX);
+    }
+
+    /// Keeps the `max` heaviest influences per vertex and renormalizes them.
+    /// `joints` ends up sorted by vertex, heaviest influence first.
+    pub fn limit_influences(&mut self, max: usize) {
+        let influences = self.influences();
+        self.joints = (0..influences.offsets.len().saturating_sub(1))
+            .flat_map(|vertex| limit(influences.of(vertex), max))
+            .collect();
+    }
+
+    /// One `PackedInfluence` per vertex, limited to the four heaviest influences and renormalized.
+    /// Vertices past the last weighted one are padded so the result matches the vertex buffer.
+    pub fn packed_influences(&self, vertex_count: usize) -> Vec<PackedInfluence> {
+        let influences = self.influences();
+        (0..vertex_count).map(|vertex| {
+            let mut packed = PackedInfluence::default();
+            for (i, joint) in limit(influences.of(vertex), MAX_PACKED_INFLUENCES).iter().enumerate() {
+                packed.joints[i] = joint.joint_id;
+                packed.weights[i] = joint.weight;
+            }
+            packed
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Mat4;
+    use std::collections::HashMap;
+
+    fn skin(joints: Vec<SkinJoint>) -> Skin {
+        Skin {
+            name: "weights".to_string(),
+            transforms: vec![Mat4::IDENTITY; 6],
+            global_bone_transforms: vec![Mat4::IDENTITY; 6],
+            inverse_bind_matrices: vec![Mat4::IDENTITY; 6],
+            joints,
+            joint_id_map: HashMap::new(),
+            joint_bones: Vec::new(),
+        }
+    }
+
+    fn joint(joint_id: u32, vertex_id: u32, weight: f32) -> SkinJoint {
+        SkinJoint { joint_id, vertex_id, weight }
+    }
+
+    #[test]
+    fn limits_and_packs() {
+        let mut skin = skin(vec![
+            joint(0, 1, 0.1), joint(1, 1, 0.4), joint(2, 1, 0.2), joint(3, 1, 0.2), joint(4, 1, 0.05), joint(5, 1, 0.05),
+            joint(0, 0, 2.0), joint(1, 0, 2.0), joint(2, 0, 0.0),
+        ]);
+        assert_eq!(skin.max_influences(), 6);
+
+        let packed = skin.packed_influences(3);
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packed[0].iter().collect::<Vec<_>>(), vec![(0, 0.5), (1, 0.5)]);
+        assert_eq!(packed[1].joints, UVec4::new(1, 2, 3, 0));
+        assert!((packed[1].weights.x - 0.4 / 0.9).abs() < 1e-6);
+        assert!((packed[1].weights.dot(Vec4::ONE) - 1.0).abs() < 1e-6);
+        assert_eq!(packed[2], PackedInfluence::default());
+
+        skin.normalize_weights();
+        assert_eq!(skin.joints.len(), 8);
+        assert_eq!(skin.max_influences(), 6);
+        skin.limit_influences(2);
+        assert_eq!(skin.max_influences(), 2);
+        let vertex = |v| skin.joints.iter().filter(|j| j.vertex_id == v).map(|j| (j.joint_id, j.weight)).collect::<Vec<_>>();
+        assert_eq!(vertex(0), vec![(0, 0.5), (1, 0.5)]);
+        assert_eq!(vertex(1).iter().map(|(j, _)| *j).collect::<Vec<_>>(), vec![1, 2]);
+        assert!((vertex(1).iter().map(|(_, w)| w).sum::<f32>() - 1.0).abs() < 1e-6);
+    }
+}
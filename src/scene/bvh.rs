@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::resource::skin::{Bone, RotationOrder, Transform};
+use super::daz::format::RigV1;
+
+#[derive(Debug)]
+pub enum BvhError {
+    Io(std::io::Error),
+    UnexpectedEnd,
+    Unexpected { line: usize, found: String, expected: &'static str },
+    InvalidNumber { line: usize, token: String },
+    UnknownChannel { line: usize, channel: String },
+    MissingValues { frame: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for BvhError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for BvhError {}
+
+impl From<std::io::Error> for BvhError {
+    fn from(error: std::io::Error) -> Self {
+        BvhError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Xposition,
+    Yposition,
+    Zposition,
+    Xrotation,
+    Yrotation,
+    Zrotation,
+}
+
+impl Channel {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "xposition" => Some(Channel::Xposition),
+            "yposition" => Some(Channel::Yposition),
+            "zposition" => Some(Channel::Zposition),
+            "xrotation" => Some(Channel::Xrotation),
+            "yrotation" => Some(Channel::Yrotation),
+            "zrotation" => Some(Channel::Zrotation),
+            _ => None,
+        }
+    }
+
+    fn rotation_axis(self) -> Option<usize> {
+        match self {
+            Channel::Xrotation => Some(0),
+            Channel::Yrotation => Some(1),
+            Channel::Zrotation => Some(2),
+            _ => None,
+        }
+    }
+
+    fn position_axis(self) -> Option<usize> {
+        match self {
+            Channel::Xposition => Some(0),
+            Channel::Yposition => Some(1),
+            Channel::Zposition => Some(2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BvhJoint {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// Rest translation relative to the parent joint.
+    pub offset: Vec3,
+    pub channels: Vec<Channel>,
+    /// Index of the first channel of this joint in a frame.
+    pub channel_offset: usize,
+    /// Taken from the order the rotation channels are listed in, e.g. `Zrotation Xrotation Yrotation` is ZXY.
+    pub rotation_order: RotationOrder,
+    pub end_site: Option<Vec3>,
+}
+
+/// Local rotation and translation of a joint in one frame, the BVH local matrix is `T * R`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointPose {
+    pub rotation: Quat,
+    pub translation: Vec3,
+}
+
+impl JointPose {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.rotation, self.translation)
+    }
+
+    pub fn lerp(&self, other: &JointPose, t: f32) -> JointPose {
+        JointPose {
+            rotation: self.rotation.slerp(other.rotation, t),
+            translation: self.translation.lerp(other.translation, t),
+        }
+    }
+}
+
+/// A Biovision hierarchy with its motion, channel values are stored per frame in file order.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    pub joints: Vec<BvhJoint>,
+    pub frame_time: f32,
+    pub frames: Vec<Vec<f32>>,
+}
+
+struct Tokens<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(source: &'a str) -> Self {
+        let tokens = source.lines().enumerate()
+            .flat_map(|(line, text)| text.split_whitespace().map(move |token| (line + 1, token)))
+            .collect();
+        Tokens { tokens, position: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|(_, token)| *token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).or(self.tokens.last()).map_or(0, |(line, _)| *line)
+    }
+
+    fn next(&mut self) -> Result<(usize, &'a str), BvhError> {
+        let token = self.tokens.get(self.position).copied().ok_or(BvhError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &'static str) -> Result<(), BvhError> {
+        let (line, token) = self.next()?;
+        if token.eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(BvhError::Unexpected { line, found: token.to_string(), expected })
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, BvhError> {
+        let (line, token) = self.next()?;
+        token.parse().map_err(|_| BvhError::InvalidNumber { line, token: token.to_string() })
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, BvhError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+}
+
+fn rotation_order(channels: &[Channel]) -> RotationOrder {
+    // listed axes first, missing ones after them in xyz order
+    let mut axes = channels.iter().filter_map(|c| c.rotation_axis()).collect::<Vec<_>>();
+    for axis in 0..3 {
+        if !axes.contains(&axis) {
+            axes.push(axis);
+        }
+    }
+    RotationOrder::from_axes([axes[0], axes[1], axes[2]]).unwrap_or(RotationOrder::ZXY)
+}
+
+fn parse_joint(tokens: &mut Tokens, joints: &mut Vec<BvhJoint>, parent: Option<usize>, channel_count: &mut usize) -> Result<(), BvhError> {
+    let (_, name) = tokens.next()?;
+    tokens.expect("{")?;
+    tokens.expect("OFFSET")?;
+    let offset = tokens.vec3()?;
+
+    let mut channels = Vec::new();
+    if tokens.peek().is_some_and(|t| t.eq_ignore_ascii_case("CHANNELS")) {
+        tokens.next()?;
+        let count: usize = tokens.number()?;
+        for _ in 0..count {
+            let (line, channel) = tokens.next()?;
+            channels.push(Channel::parse(channel).ok_or_else(|| BvhError::UnknownChannel { line, channel: channel.to_string() })?);
+        }
+    }
+
+    let index = joints.len();
+    joints.push(BvhJoint {
+        name: name.to_string(),
+        parent,
+        offset,
+        rotation_order: rotation_order(&channels),
+        channel_offset: *channel_count,
+        channels,
+        end_site: None,
+    });
+    *channel_count += joints[index].channels.len();
+
+    loop {
+        let (line, token) = tokens.next()?;
+        match token {
+            "}" => return Ok(()),
+            _ if token.eq_ignore_ascii_case("JOINT") => parse_joint(tokens, joints, Some(index), channel_count)?,
+            _ if token.eq_ignore_ascii_case("End") => {
+                tokens.expect("Site")?;
+                tokens.expect("{")?;
+                tokens.expect("OFFSET")?;
+                joints[index].end_site = Some(tokens.vec3()?);
+                tokens.expect("}")?;
+            }
+            _ => return Err(BvhError::Unexpected { line, found: token.to_string(), expected: "JOINT, End Site or }" }),
+        }
+    }
+}
+
+impl Bvh {
+    pub fn parse(source: &str) -> Result<Self, BvhError> {
+        let mut tokens = Tokens::new(source);
+        tokens.expect("HIERARCHY")?;
+
+        let mut joints = Vec::new();
+        let mut channel_count = 0;
+        // some exporters write several roots
+        while tokens.peek().is_some_and(|t| t.eq_ignore_ascii_case("ROOT")) {
+            tokens.next()?;
+            parse_joint(&mut tokens, &mut joints, None, &mut channel_count)?;
+        }
+        if joints.is_empty() {
+            let line = tokens.line();
+            let found = tokens.peek().unwrap_or_default().to_string();
+            return Err(BvhError::Unexpected { line, found, expected: "ROOT" });
+        }
+
+        tokens.expect("MOTION")?;
+        tokens.expect("Frames:")?;
+        let frame_count: usize = tokens.number()?;
+        tokens.expect("Frame")?;
+        tokens.expect("Time:")?;
+        let frame_time: f32 = tokens.number()?;
+
+        let mut frames = Vec::with_capacity(frame_count);
+        for frame in 0..frame_count {
+            let mut values = Vec::with_capacity(channel_count);
+            for _ in 0..channel_count {
+                if tokens.peek().is_none() {
+                    return Err(BvhError::MissingValues { frame, expected: channel_count, found: values.len() });
+                }
+                values.push(tokens.number()?);
+            }
+            frames.push(values);
+        }
+
+        Ok(Bvh { joints, frame_time, frames })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frame_time * self.frames.len().saturating_sub(1) as f32
+    }
+
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|j| j.name == name)
+    }
+
+    /// Local pose of every joint in a frame, the offset plus position channels and the rotation channels.
+    pub fn frame_pose(&self, frame: usize) -> Vec<JointPose> {
+        let values = self.frames.get(frame);
+        self.joints.iter().map(|joint| {
+            let mut angles = Vec3::ZERO;
+            let mut translation = joint.offset;
+            if let Some(values) = values {
+                for (i, channel) in joint.channels.iter().enumerate() {
+                    let value = values[joint.channel_offset + i];
+                    if let Some(axis) = channel.rotation_axis() {
+                        angles[axis] = value.to_radians();
+                    } else if let Some(axis) = channel.position_axis() {
+                        translation[axis] += value;
+                    }
+                }
+            }
+            JointPose { rotation: joint.rotation_order.to_quat(angles), translation }
+        }).collect()
+    }
+
+    /// Pose at `time` seconds, interpolated between the two closest frames and held at both ends.
+    pub fn sample(&self, time: f32) -> Vec<JointPose> {
+        if self.frames.len() < 2 || self.frame_time <= 0.0 {
+            return self.frame_pose(0);
+        }
+        let frame = (time / self.frame_time).clamp(0.0, (self.frames.len() - 1) as f32);
+        let first = frame.floor() as usize;
+        let second = (first + 1).min(self.frames.len() - 1);
+        let t = frame - first as f32;
+        self.frame_pose(first).iter().zip(self.frame_pose(second).iter())
+            .map(|(a, b)| a.lerp(b, t))
+            .collect()
+    }
+
+    /// World transforms of a pose, parents are always listed before their children.
+    pub fn global_transforms(&self, pose: &[JointPose]) -> Vec<Mat4> {
+        let mut globals: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(pose) {
+            let parent = joint.parent.map_or(Mat4::IDENTITY, |p| globals[p]);
+            globals.push(parent * local.matrix());
+        }
+        globals
+    }
+
+    fn rest_positions(&self) -> Vec<Vec3> {
+        let mut positions: Vec<Vec3> = Vec::with_capacity(self.joints.len());
+        for joint in &self.joints {
+            let parent = joint.parent.map_or(Vec3::ZERO, |p| positions[p]);
+            positions.push(parent + joint.offset);
+        }
+        positions
+    }
+
+    /// Rig of the hierarchy in its rest pose, bone `i` is joint `i`.
+    pub fn rig<S: Transform, T: Bone<S>>(&self) -> RigV1<S, T> {
+        let positions = self.rest_positions();
+        let bones = self.joints.iter().enumerate().map(|(i, joint)| {
+            let children = self.joints.iter().filter(|c| c.parent == Some(i)).map(|c| c.name.clone()).collect::<Vec<_>>();
+            // the bone points at its end site or its first child
+            let end_point = match (joint.end_site, self.joints.iter().position(|c| c.parent == Some(i))) {
+                (Some(end), _) => positions[i] + end,
+                (None, Some(child)) => positions[child],
+                (None, None) => positions[i],
+            };
+            let global = Mat4::from_translation(positions[i]);
+            // the locals are derived from the globals below
+            let mut bone = T::from(
+                joint.name.clone(),
+                joint.name.clone(),
+                joint.name.clone(),
+                i,
+                joint.parent,
+                children,
+                positions[i],
+                end_point,
+                S::from_mat4(global),
+                S::from_mat4(global),
+            );
+            bone.set_inverse_bind_matrix(S::from_mat4(global.inverse()));
+            bone
+        }).collect();
+
+        let mut rig = RigV1 {
+            bone_map: self.joints.iter().enumerate().map(|(i, joint)| (joint.name.clone(), i)).collect::<HashMap<_, _>>(),
+            bones,
+            root_bone: 0,
+            root_transform: S::identity(),
+        };
+        rig.set_global_transforms(&positions.iter().map(|p| Mat4::from_translation(*p)).collect::<Vec<_>>());
+        rig
+    }
+
+    /// Poses the bones of `rig` that share a name with a joint, e.g. a rig made by `Bvh::rig`.
+    /// The result can be read with `Rig::local_to_global` or passed on to a `Retargeter`.
+    pub fn apply<S: Transform, T: Bone<S>>(&self, pose: &[JointPose], rig: &mut RigV1<S, T>) {
+        let globals = self.global_transforms(pose);
+        for (i, joint) in self.joints.iter().enumerate() {
+            let Some(&bone) = rig.bone_map.get(&joint.name) else {
+                continue;
+            };
+            let local = joint.parent.map_or(globals[i], |p| globals[i] * globals[p].inverse());
+            rig.bones[bone].set_local_transform(S::from_mat4(local));
+        }
+    }
+}
+
+pub fn load_bvh(path: &Path) -> Result<Bvh, BvhError> {
+    Bvh::parse(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4Swizzles;
+    use crate::resource::skin::Rig;
+    use crate::scene::daz::format::BoneV1;
+
+    const ARM: &str = "
+HIERARCHY
+ROOT Hips
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+    JOINT Arm
+    {
+        OFFSET 0.0 10.0 0.0
+        CHANNELS 3 Xrotation Yrotation Zrotation
+        End Site
+        {
+            OFFSET 5.0 0.0 0.0
+        }
+    }
+}
+MOTION
+Frames: 2
+Frame Time: 0.5
+0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0
+2.0 0.0 0.0 0.0 0.0 90.0 0.0 0.0 90.0
+";
+
+    #[test]
+    fn parses_hierarchy_and_motion() {
+        let bvh = Bvh::parse(ARM).unwrap();
+        assert_eq!(bvh.joints.len(), 2);
+        assert!(matches!(bvh.joints[0].rotation_order, RotationOrder::ZXY));
+        assert!(matches!(bvh.joints[1].rotation_order, RotationOrder::XYZ));
+        assert_eq!(bvh.joints[1].parent, Some(0));
+        assert_eq!(bvh.joints[1].channel_offset, 6);
+        assert_eq!(bvh.joints[1].end_site, Some(Vec3::new(5.0, 0.0, 0.0)));
+        assert_eq!(bvh.frame_count(), 2);
+        assert_eq!(bvh.duration(), 0.5);
+
+        let broken = ARM.replace("CHANNELS 3 Xrotation", "CHANNELS 3 Wrotation");
+        assert!(matches!(Bvh::parse(&broken), Err(BvhError::UnknownChannel { line: 10, .. })));
+        let truncated = &ARM[..ARM.rfind("90.0").unwrap()];
+        assert!(matches!(Bvh::parse(truncated), Err(BvhError::MissingValues { frame: 1, .. })));
+    }
+
+    #[test]
+    fn drives_rig() {
+        let bvh = Bvh::parse(ARM).unwrap();
+        let mut rig = bvh.rig::<Mat4, BoneV1<Mat4>>();
+        assert_eq!(rig.get_bone("Arm").unwrap().end_point, Vec3::new(5.0, 10.0, 0.0));
+        assert!(rig.local_to_global(1).w_axis.xyz().abs_diff_eq(Vec3::new(0.0, 10.0, 0.0), 1e-5));
+
+        // hips move by 2 and turn 90 degrees around y, the arm turns 90 degrees around its z
+        bvh.apply(&bvh.frame_pose(1), &mut rig);
+        let arm = rig.local_to_global(1);
+        assert!(arm.w_axis.xyz().abs_diff_eq(Vec3::new(2.0, 10.0, 0.0), 1e-4));
+        let tip = arm.transform_point3(Vec3::new(5.0, 0.0, 0.0));
+        assert!(tip.abs_diff_eq(Vec3::new(2.0, 15.0, 0.0), 1e-4));
+        let hip_x = rig.local_to_global(0).transform_vector3(Vec3::X);
+        assert!(hip_x.abs_diff_eq(-Vec3::Z, 1e-4));
+
+        // half way between the frames
+        let pose = bvh.sample(0.25);
+        assert!(pose[0].translation.abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-5));
+        assert!(pose[1].rotation.abs_diff_eq(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4), 1e-5));
+        assert_eq!(bvh.sample(10.0), bvh.frame_pose(1));
+    }
+}
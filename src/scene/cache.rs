@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use glam::{Mat4, Vec3};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::resource::mesh::{ConnectivityInfo, FaceSelection, RawConnectivity};
+use crate::resource::skin::{Bone, Skin, SkinJoint};
+use super::daz::format::{BoneV1, RigV1};
+use super::{Bounds, BufferPart, Camera, MaterialInfo, Mesh, ModelVertex, PrimitiveSection, Scene};
+
+// Layout of a cache file (little endian):
+//
+//   header   | magic, version, section count, source length, table checksum
+//   table    | one `SectionEntry` per section
+//   sections | 16 byte aligned blobs, section 0 is the json manifest
+//
+// Bulk data (vertices, indices, connectivity, matrices, joints, materials) is stored
+// as raw `Pod` arrays so it can be viewed straight out of the memory map.
+
+const MAGIC: [u8; 8] = *b"BOLTSCN\0";
+pub const SCENE_CACHE_VERSION: u32 = 4;
+const HEADER_SIZE: usize = 32;
+const SECTION_ENTRY_SIZE: usize = 24;
+const SECTION_ALIGNMENT: usize = 16;
+const CACHE_EXTENSION: &str = "boltcache";
+
+#[derive(Debug)]
+pub enum CacheError {
+    BadMagic,
+    Version(u32),
+    Truncated,
+    Checksum(usize),
+    Stale,
+    Manifest(serde_json::Error),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::BadMagic => write!(f, "not a scene cache file"),
+            CacheError::Version(version) => write!(f, "cache version {} doesn't match {}", version, SCENE_CACHE_VERSION),
+            CacheError::Truncated => write!(f, "cache file is truncated"),
+            CacheError::Checksum(usize::MAX) => write!(f, "checksum mismatch in the section table"),
+            CacheError::Checksum(section) => write!(f, "checksum mismatch in section {}", section),
+            CacheError::Stale => write!(f, "cache is older than its sources or missing"),
+            CacheError::Manifest(e) => write!(f, "unreadable manifest: {}", e),
+        }
+    }
+}
+
+impl Error for CacheError {}
+
+#[derive(Debug, Clone, Copy)]
+struct SectionEntry {
+    offset: u64,
+    length: u64,
+    checksum: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    meshes: Vec<MeshEntry>,
+    skins: Vec<SkinEntry>,
+    rigs: Vec<RigEntry>,
+    materials: usize,
+    texture_paths: Vec<PathBuf>,
+    camera: Option<Camera>,
+    /// Files besides the source with the length they had when the cache was written.
+    dependencies: Vec<(PathBuf, u64)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MeshEntry {
+    name: String,
+    transform: Mat4,
+    vertices: usize,
+    indices: usize,
+    sections: Vec<SectionRange>,
+    connectivity: ConnectivityEntry,
+    face_selections: Vec<FaceSelection>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SectionRange {
+    index: usize,
+    vertices: (usize, usize),
+    indices: Option<(usize, usize)>,
+    material_index: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConnectivityEntry {
+    positions: usize,
+    vertex_halfedges: usize,
+    halfedges: usize,
+    face_halfedges: usize,
+    free_vertices: usize,
+    free_halfedges: usize,
+    free_faces: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SkinEntry {
+    name: String,
+    transforms: usize,
+    global_bone_transforms: usize,
+    inverse_bind_matrices: usize,
+    joints: usize,
+    joint_id_map: HashMap<String, u32>,
+    joint_bones: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RigEntry {
+    bone_map: HashMap<String, usize>,
+    bones: Vec<BoneEntry>,
+    root_bone: usize,
+    root_transform: Mat4,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BoneEntry {
+    name: String,
+    id: String,
+    label: String,
+    index: usize,
+    parent: Option<usize>,
+    children: Vec<String>,
+    center_point: Vec3,
+    end_point: Vec3,
+    local_transform: Mat4,
+    global_transform: Mat4,
+    inverse_bind_matrix: Mat4,
+}
+
+/// Writes the cpu side of a scene into a cache file.
+#[derive(Default)]
+pub struct SceneCacheWriter<'a> {
+    meshes: &'a [Mesh],
+    skins: &'a [Skin],
+    rigs: &'a [RigV1<Mat4, BoneV1<Mat4>>],
+    materials: &'a [MaterialInfo],
+    texture_paths: &'a [PathBuf],
+    camera: Option<&'a Camera>,
+    dependencies: &'a [PathBuf],
+    sections: Vec<Vec<u8>>,
+}
+
+impl<'a> SceneCacheWriter<'a> {
+    pub fn from_scene(scene: &'a Scene) -> Self {
+        SceneCacheWriter::default()
+            .meshes(&scene.meshes)
+            .skins(&scene.skins)
+            .rigs(&scene.rigs)
+            .materials(&scene.materials)
+            .texture_paths(&scene.texture_paths)
+            .camera(scene.camera.as_ref())
+            .dependencies(&scene.dependencies)
+    }
+    pub fn meshes(mut self, meshes: &'a [Mesh]) -> Self {
+        self.meshes = meshes;
+        self
+    }
+    pub fn skins(mut self, skins: &'a [Skin]) -> Self {
+        self.skins = skins;
+        self
+    }
+    pub fn rigs(mut self, rigs: &'a [RigV1<Mat4, BoneV1<Mat4>>]) -> Self {
+        self.rigs = rigs;
+        self
+    }
+    pub fn materials(mut self, materials: &'a [MaterialInfo]) -> Self {
+        self.materials = materials;
+        self
+    }
+    pub fn texture_paths(mut self, texture_paths: &'a [PathBuf]) -> Self {
+        self.texture_paths = texture_paths;
+        self
+    }
+    pub fn camera(mut self, camera: Option<&'a Camera>) -> Self {
+        self.camera = camera;
+        self
+    }
+    /// Files the scene was built from besides the source, a change to any of them makes the cache stale.
+    pub fn dependencies(mut self, dependencies: &'a [PathBuf]) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    // The manifest is inserted in front once everything else is written,
+    // so a section pushed here ends up at index `len()`.
+    fn push<T: bytemuck::Pod>(&mut self, data: &[T]) -> usize {
+        self.sections.push(bytemuck::cast_slice(data).to_vec());
+        self.sections.len()
+    }
+
+    /// Writes the cache for `source` to `path`.
+    pub fn write(mut self, path: &Path, source: &Path) -> Result<(), Box<dyn Error>> {
+        let source_len = fs::metadata(source)?.len();
+        let dependencies = self.dependencies.iter()
+            .map(|path| Ok((path.clone(), fs::metadata(path)?.len())))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        let (meshes, skins, rigs, materials) = (self.meshes, self.skins, self.rigs, self.materials);
+
+        let meshes = meshes.iter().map(|mesh| {
+            let raw = mesh.connectivity_info.to_raw();
+            MeshEntry {
+                name: mesh.name.clone(),
+                transform: mesh.transform,
+                vertices: self.push(&mesh.vertices),
+                indices: self.push(&mesh.indices),
+                sections: mesh.primitive_sections.iter().map(|section| SectionRange {
+                    index: section.index,
+                    vertices: (section.vertices.offset, section.vertices.element_count),
+                    indices: section.indices.map(|i| (i.offset, i.element_count)),
+                    material_index: section.material_index,
+                }).collect(),
+                connectivity: ConnectivityEntry {
+                    positions: self.push(&raw.positions),
+                    vertex_halfedges: self.push(&raw.vertex_halfedges),
+                    halfedges: self.push(&raw.halfedges),
+                    face_halfedges: self.push(&raw.face_halfedges),
+                    free_vertices: self.push(&raw.free_vertices),
+                    free_halfedges: self.push(&raw.free_halfedges),
+                    free_faces: self.push(&raw.free_faces),
+                },
+                face_selections: mesh.face_selections.clone(),
+            }
+        }).collect();
+
+        let skins = skins.iter().map(|skin| SkinEntry {
+            name: skin.name.clone(),
+            transforms: self.push(&skin.transforms),
+            global_bone_transforms: self.push(&skin.global_bone_transforms),
+            inverse_bind_matrices: self.push(&skin.inverse_bind_matrices),
+            joints: self.push(&skin.joints),
+            joint_id_map: skin.joint_id_map.clone(),
+            joint_bones: skin.joint_bones.clone(),
+        }).collect();
+
+        let rigs = rigs.iter().map(|rig| RigEntry {
+            bone_map: rig.bone_map.clone(),
+            bones: rig.bones.iter().map(|bone| BoneEntry {
+                name: bone.name.clone(),
+                id: bone.id.clone(),
+                label: bone.label.clone(),
+                index: bone.index,
+                parent: bone.parent,
+                children: bone.children.clone(),
+                center_point: bone.center_point,
+                end_point: bone.end_point,
+                local_transform: bone.local_transform,
+                global_transform: bone.global_transform,
+                inverse_bind_matrix: bone.inverse_bind_matrix,
+            }).collect(),
+            root_bone: rig.root_bone,
+            root_transform: rig.root_transform,
+        }).collect();
+
+        let manifest = Manifest {
+            meshes,
+            skins,
+            rigs,
+            materials: self.push(materials),
+            texture_paths: self.texture_paths.to_vec(),
+            camera: self.camera.cloned(),
+            dependencies,
+        };
+        self.sections.insert(0, serde_json::to_vec(&manifest)?);
+
+        // lay out the sections after the header and table
+        let mut offset = HEADER_SIZE + SECTION_ENTRY_SIZE * self.sections.len();
+        let mut entries = Vec::new();
+        for section in &self.sections {
+            offset = align(offset);
+            entries.push(SectionEntry {
+                offset: offset as u64,
+                length: section.len() as u64,
+                checksum: crc32fast::hash(section),
+            });
+            offset += section.len();
+        }
+        let mut table = Vec::with_capacity(SECTION_ENTRY_SIZE * entries.len());
+        for entry in &entries {
+            table.extend_from_slice(&entry.offset.to_le_bytes());
+            table.extend_from_slice(&entry.length.to_le_bytes());
+            table.extend_from_slice(&entry.checksum.to_le_bytes());
+            table.extend_from_slice(&[0u8; 4]);
+        }
+
+        // write to a temporary file first so a crashed write never looks like a valid cache
+        let tmp_path = path.with_extension("tmp");
+        let mut file = std::io::BufWriter::new(fs::File::create(&tmp_path)?);
+        file.write_all(&MAGIC)?;
+        file.write_all(&SCENE_CACHE_VERSION.to_le_bytes())?;
+        file.write_all(&(self.sections.len() as u32).to_le_bytes())?;
+        file.write_all(&source_len.to_le_bytes())?;
+        file.write_all(&crc32fast::hash(&table).to_le_bytes())?;
+        file.write_all(&[0u8; 4])?;
+        file.write_all(&table)?;
+        let mut written = HEADER_SIZE + table.len();
+        for (section, entry) in self.sections.iter().zip(&entries) {
+            file.write_all(&vec![0u8; entry.offset as usize - written])?;
+            file.write_all(section)?;
+            written = entry.offset as usize + section.len();
+        }
+        file.flush()?;
+        drop(file);
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+fn align(offset: usize) -> usize {
+    offset.div_ceil(SECTION_ALIGNMENT) * SECTION_ALIGNMENT
+}
+
+fn is_more_recent(path: &Path, other: &Path) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(path), modified(other)) {
+        (Some(timestamp), Some(other_timestamp)) => timestamp > other_timestamp,
+        _ => false,
+    }
+}
+
+/// A memory mapped, validated scene cache.
+pub struct SceneCache {
+    map: Mmap,
+    sections: Vec<SectionEntry>,
+    manifest: Manifest,
+}
+
+impl SceneCache {
+    /// The conventional location of the cache belonging to `source`, next to it.
+    pub fn path_for(source: &Path) -> PathBuf {
+        let mut name = source.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(CACHE_EXTENSION);
+        source.with_file_name(name)
+    }
+
+    /// Opens the cache at `path` if it passes validation and is newer than `source` and
+    /// every file the scene was built from.
+    pub fn open_for(path: &Path, source: &Path) -> Result<Self, Box<dyn Error>> {
+        if !is_more_recent(path, source) {
+            return Err(Box::new(CacheError::Stale));
+        }
+        let cache = Self::open(path)?;
+        if cache.source_len() != fs::metadata(source)?.len() {
+            return Err(Box::new(CacheError::Stale));
+        }
+        for (dependency, len) in &cache.manifest.dependencies {
+            let current_len = fs::metadata(dependency).map(|m| m.len()).ok();
+            if !is_more_recent(path, dependency) || current_len != Some(*len) {
+                return Err(Box::new(CacheError::Stale));
+            }
+        }
+        Ok(cache)
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = fs::File::open(path)?;
+        // The map is only valid as long as nobody truncates the file underneath us,
+        // which we accept for a cache we own.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_SIZE {
+            return Err(Box::new(CacheError::Truncated));
+        }
+        if map[0..8] != MAGIC {
+            return Err(Box::new(CacheError::BadMagic));
+        }
+        let version = read_u32(&map, 8);
+        if version != SCENE_CACHE_VERSION {
+            return Err(Box::new(CacheError::Version(version)));
+        }
+        let section_count = read_u32(&map, 12) as usize;
+        let table_end = HEADER_SIZE + section_count * SECTION_ENTRY_SIZE;
+        if section_count == 0 || map.len() < table_end {
+            return Err(Box::new(CacheError::Truncated));
+        }
+        if crc32fast::hash(&map[HEADER_SIZE..table_end]) != read_u32(&map, 24) {
+            return Err(Box::new(CacheError::Checksum(usize::MAX)));
+        }
+
+        let mut sections = Vec::with_capacity(section_count);
+        for i in 0..section_count {
+            let base = HEADER_SIZE + i * SECTION_ENTRY_SIZE;
+            let entry = SectionEntry {
+                offset: read_u64(&map, base),
+                length: read_u64(&map, base + 8),
+                checksum: read_u32(&map, base + 16),
+            };
+            let end = entry.offset.checked_add(entry.length).ok_or(CacheError::Truncated)?;
+            if end > map.len() as u64 {
+                return Err(Box::new(CacheError::Truncated));
+            }
+            if crc32fast::hash(&map[entry.offset as usize..end as usize]) != entry.checksum {
+                return Err(Box::new(CacheError::Checksum(i)));
+            }
+            sections.push(entry);
+        }
+
+        let manifest_entry = sections[0];
+        let manifest = serde_json::from_slice(
+            &map[manifest_entry.offset as usize..(manifest_entry.offset + manifest_entry.length) as usize],
+        )
+        .map_err(CacheError::Manifest)?;
+
+        Ok(SceneCache {
+            map,
+            sections,
+            manifest,
+        })
+    }
+
+    pub fn source_len(&self) -> u64 {
+        read_u64(&self.map, 16)
+    }
+
+    /// Views a raw section without copying.
+    pub fn section<T: bytemuck::Pod>(&self, index: usize) -> &[T] {
+        let entry = self.sections[index];
+        bytemuck::cast_slice(&self.map[entry.offset as usize..(entry.offset + entry.length) as usize])
+    }
+
+    pub fn meshes(&self) -> Vec<Mesh> {
+        self.manifest.meshes.iter().map(|entry| {
+            let c = &entry.connectivity;
+            let raw = RawConnectivity {
+                positions: self.section(c.positions).to_vec(),
+                vertex_halfedges: self.section(c.vertex_halfedges).to_vec(),
+                halfedges: self.section(c.halfedges).to_vec(),
+                face_halfedges: self.section(c.face_halfedges).to_vec(),
+                free_vertices: self.section(c.free_vertices).to_vec(),
+                free_halfedges: self.section(c.free_halfedges).to_vec(),
+                free_faces: self.section(c.free_faces).to_vec(),
+            };
+            let mut mesh = Mesh {
+                name: entry.name.clone(),
+                vertices: self.section::<ModelVertex>(entry.vertices).to_vec(),
+                indices: self.section(entry.indices).to_vec(),
+                transform: entry.transform,
+                primitive_sections: entry.sections.iter().map(|s| PrimitiveSection {
+                    index: s.index,
+                    vertices: BufferPart { offset: s.vertices.0, element_count: s.vertices.1 },
+                    indices: s.indices.map(|(offset, element_count)| BufferPart { offset, element_count }),
+                    material_index: s.material_index,
+                    bounds: Bounds::default(),
+                }).collect(),
+                connectivity_info: ConnectivityInfo::from_raw(&raw),
+                face_selections: entry.face_selections.clone(),
+            };
+            // bounds aren't stored, they are cheap to rebuild from the vertices
+            mesh.update_bounds();
+            mesh
+        }).collect()
+    }
+
+    pub fn skins(&self) -> Vec<Skin> {
+        self.manifest.skins.iter().map(|entry| Skin {
+            name: entry.name.clone(),
+            transforms: self.section(entry.transforms).to_vec(),
+            global_bone_transforms: self.section(entry.global_bone_transforms).to_vec(),
+            inverse_bind_matrices: self.section(entry.inverse_bind_matrices).to_vec(),
+            joints: self.section::<SkinJoint>(entry.joints).to_vec(),
+            joint_id_map: entry.joint_id_map.clone(),
+            joint_bones: entry.joint_bones.clone(),
+        }).collect()
+    }
+
+    pub fn rigs(&self) -> Vec<RigV1<Mat4, BoneV1<Mat4>>> {
+        self.manifest.rigs.iter().map(|entry| RigV1 {
+            bone_map: entry.bone_map.clone(),
+            bones: entry.bones.iter().map(|b| {
+                let mut bone = <BoneV1<Mat4> as Bone<Mat4>>::from(
+                    b.name.clone(),
+                    b.id.clone(),
+                    b.label.clone(),
+                    b.index,
+                    b.parent,
+                    b.children.clone(),
+                    b.center_point,
+                    b.end_point,
+                    b.local_transform,
+                    b.global_transform,
+                );
+                bone.set_inverse_bind_matrix(b.inverse_bind_matrix);
+                bone
+            }).collect(),
+            root_bone: entry.root_bone,
+            root_transform: entry.root_transform,
+        }).collect()
+    }
+
+    pub fn materials(&self) -> Vec<MaterialInfo> {
+        self.section(self.manifest.materials).to_vec()
+    }
+
+    pub fn texture_paths(&self) -> &[PathBuf] {
+        &self.manifest.texture_paths
+    }
+
+    pub fn camera(&self) -> Option<Camera> {
+        self.manifest.camera
+    }
+
+    pub fn dependencies(&self) -> Vec<PathBuf> {
+        self.manifest.dependencies.iter().map(|(path, _)| path.clone()).collect()
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join("bolt_scene_cache");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("triangle.gltf");
+        fs::write(&source, "{}").unwrap();
+        let buffer = dir.join("triangle.bin");
+        fs::write(&buffer, [0u8; 36]).unwrap();
+
+        let vertices = [Vec3::ZERO, Vec3::X, Vec3::Y]
+            .iter()
+            .map(|p| ModelVertex { pos: p.extend(1.0), ..Default::default() })
+            .collect::<Vec<_>>();
+        let sections = vec![PrimitiveSection {
+            index: 0,
+            vertices: BufferPart { offset: 0, element_count: 3 },
+            indices: Some(BufferPart { offset: 0, element_count: 3 }),
+            material_index: Some(0),
+            bounds: Bounds::default(),
+        }];
+        let mesh = Mesh::new("triangle".to_string(), vertices, vec![0, 1, 2], Mat4::IDENTITY, sections);
+        let skin = Skin {
+            name: "skin".to_string(),
+            transforms: vec![Mat4::IDENTITY],
+            global_bone_transforms: vec![Mat4::IDENTITY],
+            inverse_bind_matrices: vec![Mat4::from_translation(Vec3::X)],
+            joints: vec![SkinJoint { joint_id: 0, vertex_id: 2, weight: 1.0 }],
+            joint_id_map: [("root".to_string(), 0)].into_iter().collect(),
+            joint_bones: Vec::new(),
+        };
+        let material = MaterialInfo { roughness_factor: 0.5, ..Default::default() };
+
+        let cache_path = SceneCache::path_for(&source);
+        let dependencies = [buffer.clone()];
+        SceneCacheWriter::default()
+            .meshes(std::slice::from_ref(&mesh))
+            .skins(std::slice::from_ref(&skin))
+            .materials(&[material])
+            .dependencies(&dependencies)
+            .write(&cache_path, &source)
+            .unwrap();
+        // some file systems only store whole seconds
+        let set_modified = |path: &Path, seconds| {
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(seconds)).unwrap();
+        };
+        set_modified(&cache_path, 10);
+
+        let cache = SceneCache::open_for(&cache_path, &source).unwrap();
+        let meshes = cache.meshes();
+        assert_eq!(meshes[0].indices, mesh.indices);
+        assert_eq!(meshes[0].vertices[1].pos, mesh.vertices[1].pos);
+        assert_eq!(meshes[0].no_halfedges(), mesh.no_halfedges());
+        let face = meshes[0].connectivity_info.face_iterator().next().unwrap();
+        assert_eq!(meshes[0].face_normal(face), mesh.face_normal(face));
+        assert_eq!(cache.skins()[0].joints[0].vertex_id, 2);
+        assert_eq!(cache.skins()[0].inverse_bind_matrices[0], skin.inverse_bind_matrices[0]);
+        assert_eq!(cache.materials()[0].roughness_factor, 0.5);
+        assert_eq!(cache.dependencies(), dependencies);
+        drop(cache);
+
+        // a dependency that changed or is newer than the cache makes it stale
+        fs::write(&buffer, [0u8; 48]).unwrap();
+        assert!(SceneCache::open_for(&cache_path, &source).is_err());
+        fs::write(&buffer, [1u8; 36]).unwrap();
+        assert!(SceneCache::open_for(&cache_path, &source).is_ok());
+        set_modified(&buffer, 20);
+        assert!(SceneCache::open_for(&cache_path, &source).is_err());
+
+        // corrupt a byte in the last section
+        let mut bytes = fs::read(&cache_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&cache_path, bytes).unwrap();
+        assert!(SceneCache::open(&cache_path).is_err());
+    }
+}
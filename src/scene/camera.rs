@@ -1,6 +1,10 @@
 use glam::*;
-use winit::event::{WindowEvent, VirtualKeyCode, ElementState};
+use serde::{Serialize, Deserialize};
+use winit::event::{WindowEvent, ElementState, MouseScrollDelta};
 
+use super::{Bounds, CameraAction, Frustum, InputBindings, PhysicalCamera, ThinLens, Trigger, TriggerState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CameraMode {
     Examine,
     Fly,
@@ -15,7 +19,7 @@ enum Actions {
     Pan,
     LookAround,
 }
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CameraInput {
     pub lmb: bool,
     pub mmb: bool,
@@ -38,7 +42,48 @@ impl CameraInput {
     }
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    Perspective,
+    /// `ymag` is half the height of the view volume, the width follows the window aspect.
+    Orthographic { ymag: f32 },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective
+    }
+}
+
+/// Walk mode state: the eye stays `eye_height` above the ground plane, heights are measured along `up`.
+#[derive(Debug, Clone, Copy)]
+pub struct Walk {
+    pub eye_height: f32,
+    pub ground: f32,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    velocity: f32,
+}
+
+impl Default for Walk {
+    fn default() -> Self {
+        Self {
+            eye_height: 1.7,
+            ground: 0.0,
+            gravity: 9.81,
+            jump_speed: 4.0,
+            velocity: 0.0,
+        }
+    }
+}
+
+impl Walk {
+    pub fn is_grounded(&self) -> bool {
+        is_zero(self.velocity)
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Camera {
     input: CameraInput,
     position: Vec3,
@@ -54,6 +99,13 @@ pub struct Camera {
     mouse_pos: Vec2,
     window_size: Vec2,
     speed: f32,
+    #[serde(default)]
+    projection: Projection,
+    #[serde(default)]
+    physical: Option<PhysicalCamera>,
+    /// Center and radius of the framed bounds, the clip planes follow it as the camera moves.
+    #[serde(default)]
+    clip_sphere: Option<(Vec3, f32)>,
 }
 
 fn is_zero(value: f32) -> bool {
@@ -77,6 +129,9 @@ impl Camera {
             mouse_pos: Vec2::ZERO,
             window_size,
             speed: 30.0,
+            projection: Projection::Perspective,
+            physical: None,
+            clip_sphere: None,
         };
         camera.update_persp();
         camera
@@ -103,6 +158,9 @@ impl Camera {
             mouse_pos: Vec2::ZERO,
             window_size: vec2(1920.0, 1080.0),
             speed: 30.0,
+            projection: Projection::Perspective,
+            physical: None,
+            clip_sphere: None,
         };
         camera
     }
@@ -111,12 +169,31 @@ impl Camera {
 impl Camera {
     fn update_view(&mut self) {
         self.view_matrix = Mat4::look_at_rh(self.position, self.center, self.up);
+        if let Some((center, radius)) = self.clip_sphere {
+            // a little slack so surfaces touching the sphere aren't clipped
+            let distance = self.position.distance(center);
+            self.z_near = (distance - radius * 1.01).max((distance + radius) * 1e-4);
+            self.z_far = distance + radius * 1.01;
+            self.update_persp();
+        }
     }
 
     fn update_persp(&mut self) {
         let aspect = self.window_size.x / self.window_size.y;
-        self.persp_matrix =
-            Mat4::perspective_rh(self.vfov.to_radians(), aspect, self.z_near, self.z_far);
+        self.persp_matrix = match self.projection {
+            Projection::Perspective => {
+                Mat4::perspective_rh(self.vfov.to_radians(), aspect, self.z_near, self.z_far)
+            }
+            Projection::Orthographic { ymag } => {
+                let xmag = ymag * aspect;
+                Mat4::orthographic_rh(-xmag, xmag, -ymag, ymag, self.z_near, self.z_far)
+            }
+        };
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.update_persp();
     }
 
     pub fn look_at(&mut self, eye: Vec3, center: Vec3, up: Vec3) {
@@ -126,6 +203,45 @@ impl Camera {
         self.update_view();
     }
 
+    /// Fixed clip planes, replacing the ones that follow framed bounds.
+    pub fn set_clip_planes(&mut self, z_near: f32, z_far: f32) {
+        self.clip_sphere = None;
+        self.z_near = z_near;
+        self.z_far = z_far;
+        self.update_persp();
+    }
+
+    /// Looks at `bounds` from the current view direction, backing off until the bounding sphere
+    /// fits the view. The clip planes stay around the sphere as the camera moves.
+    pub fn frame_bounds(&mut self, bounds: &Bounds) {
+        if bounds.is_empty() {
+            return;
+        }
+        let (center, radius) = (bounds.sphere.center, bounds.sphere.radius.max(1e-3));
+        let direction = (self.position - self.center).try_normalize().unwrap_or(Vec3::Z);
+        let aspect = self.window_size.x / self.window_size.y;
+        let distance = match self.projection {
+            Projection::Perspective => {
+                let half_vfov = 0.5 * self.vfov.to_radians();
+                let half_hfov = (half_vfov.tan() * aspect).atan();
+                radius / half_vfov.min(half_hfov).sin()
+            }
+            Projection::Orthographic { .. } => {
+                self.projection = Projection::Orthographic { ymag: radius * aspect.recip().max(1.0) };
+                2.0 * radius
+            }
+        };
+        self.center = center;
+        self.position = center + direction * distance;
+        self.clip_sphere = Some((center, radius));
+        self.update_view();
+    }
+
+    /// Frustum of the current view in world space.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.persp_matrix * self.view_matrix))
+    }
+
     pub fn set_window_size(&mut self, window_size: Vec2) {
         self.window_size = window_size;
         self.update_persp();
@@ -137,12 +253,34 @@ impl Camera {
 
     pub fn set_vfov(&mut self, vfov: f32) {
         self.vfov = vfov;
+        self.sync_focal_length();
         self.update_persp();
     }
 
+    /// Turns on the physical model, the field of view follows its focal length and sensor.
+    pub fn set_physical(&mut self, physical: Option<PhysicalCamera>) {
+        self.physical = physical;
+        if let Some(physical) = physical {
+            self.vfov = physical.vfov();
+            self.update_persp();
+        }
+    }
+
+    fn sync_focal_length(&mut self) {
+        if let Some(physical) = &mut self.physical {
+            physical.set_vfov(self.vfov);
+        }
+    }
+
+    /// Zooms orthographic cameras instead, one unit of `delta` scales the view by 10%.
     pub fn change_vfov(&mut self, delta: f32) {
+        if let Projection::Orthographic { ymag } = self.projection {
+            self.set_projection(Projection::Orthographic { ymag: ymag * 1.1f32.powf(delta) });
+            return;
+        }
         self.vfov += delta;
         self.vfov = self.vfov.max(self.min_vfov).min(self.max_vfov);
+        self.sync_focal_length();
         self.update_persp();
     }
 
@@ -166,6 +304,11 @@ impl Camera {
         } else {
             Vec3::ZERO
         };
+        self.move_local(move_direction, speed)
+    }
+
+    /// Moves eye and center along `move_direction` in view space: x to the left, y up, z forward.
+    pub fn move_local(&mut self, move_direction: Vec3, speed: f32) -> bool {
         if !is_zero(move_direction.x) || !is_zero(move_direction.y) || !is_zero(move_direction.z) {
             let z = (self.center - self.position).normalize();
             let x = self.up.cross(z).normalize();
@@ -180,7 +323,6 @@ impl Camera {
     }
 
     pub fn mouse_move(&mut self, x: f32, y: f32, input: &CameraInput) -> bool {
-        let mut moved = false;
         let mut action = Actions::None;
 
             // if ((input.ctrl) && (input.shift)) || input.alt {
@@ -208,6 +350,24 @@ impl Camera {
             }
         }
 
+        self.drag(action, x, y)
+    }
+
+    /// Applies the drag from the last mouse position to `(x, y)` as `action`.
+    pub fn mouse_drag(&mut self, action: CameraAction, x: f32, y: f32) -> bool {
+        let action = match action {
+            CameraAction::Orbit => Actions::Orbit,
+            CameraAction::Pan => Actions::Pan,
+            CameraAction::Dolly => Actions::Dolly,
+            CameraAction::Look => Actions::LookAround,
+            CameraAction::Rotate => return self.trackball_move(x, y),
+            _ => Actions::None,
+        };
+        self.drag(action, x, y)
+    }
+
+    fn drag(&mut self, action: Actions, x: f32, y: f32) -> bool {
+        let mut moved = false;
         let dx = (x - self.mouse_pos.x) / self.window_size.x;
         let dy = (y - self.mouse_pos.y) / self.window_size.y;
         match action {
@@ -237,7 +397,42 @@ impl Camera {
         moved
     }
 
+    /// Window position to normalized device coordinates, y pointing up.
+    fn to_ndc(&self, pos: Vec2) -> Vec2 {
+        vec2(2.0 * pos.x / self.window_size.x - 1.0, 1.0 - 2.0 * pos.y / self.window_size.y)
+    }
+
+    /// Quaternion trackball: the drag from the last mouse position to `(x, y)` rolls a virtual
+    /// ball around the center. The up vector rotates along, so the camera can tumble freely.
+    pub fn trackball_move(&mut self, x: f32, y: f32) -> bool {
+        let from = self.to_ndc(self.mouse_pos);
+        let to = self.to_ndc(vec2(x, y));
+        self.mouse_pos = vec2(x, y);
+        self.trackball(from, to)
+    }
+
+    pub fn trackball(&mut self, from: Vec2, to: Vec2) -> bool {
+        let (from, to) = (project_to_sphere(from), project_to_sphere(to));
+        if from.abs_diff_eq(to, 1e-6) {
+            return false;
+        }
+        let z = (self.position - self.center).normalize();
+        let x = self.up.cross(z).normalize();
+        let y = z.cross(x);
+        let basis = Quat::from_mat3(&Mat3::from_cols(x, y, z));
+        // the ball turns with the drag, the camera turns the other way around it
+        let rotation = (basis * Quat::from_rotation_arc(from, to) * basis.inverse()).inverse();
+        self.position = self.center + rotation * (self.position - self.center);
+        self.up = rotation * y;
+        self.update_view();
+        true
+    }
+
     pub fn mouse_wheel(&mut self, value: i32) {
+        if let Projection::Orthographic { .. } = self.projection {
+            self.change_vfov(value as f32);
+            return;
+        }
         let fval = value as f32;
         let dx = fval * fval.abs() / self.window_size.x;
         self.dolly(0.0, -dx * self.speed);
@@ -340,6 +535,67 @@ impl Camera {
         self.position += x + y;
     }
 
+    /// Turns the view by the drag from the last mouse position to `(x, y)`.
+    pub fn look_move(&mut self, x: f32, y: f32) -> bool {
+        let dx = (x - self.mouse_pos.x) / self.window_size.x;
+        let dy = (y - self.mouse_pos.y) / self.window_size.y;
+        self.mouse_pos = vec2(x, y);
+        if is_zero(dx) && is_zero(dy) {
+            return false;
+        }
+        self.turn(dx, -dy);
+        self.update_view();
+        true
+    }
+
+    /// Turns the view direction around the eye, pitch stops short of looking straight along `up`.
+    fn turn(&mut self, dx: f32, dy: f32) {
+        let up = self.up.normalize();
+        let forward = self.center - self.position;
+        let distance = forward.length();
+        let mut direction = Quat::from_axis_angle(up, -dx * std::f32::consts::TAU) * forward.normalize();
+        let right = direction.cross(up).normalize();
+        let pitched = Quat::from_axis_angle(right, -dy * std::f32::consts::PI) * direction;
+        if pitched.dot(up).abs() < 0.99 {
+            direction = pitched;
+        }
+        self.center = self.position + direction * distance;
+    }
+
+    /// Walk mode step: `movement` moves on the ground plane, x to the right and y forward, `jump`
+    /// leaves the ground and gravity pulls the eye back down to `walk.eye_height` above it.
+    pub fn walk(&mut self, movement: Vec2, jump: bool, walk: &mut Walk, speed: f32, dt: f32) -> bool {
+        let up = self.up.normalize();
+        let forward = self.center - self.position;
+        let forward = (forward - forward.dot(up) * up).normalize_or_zero();
+        let right = forward.cross(up);
+        let direction = movement.x * right + movement.y * forward;
+        let mut offset = direction.normalize_or_zero() * speed * dt;
+
+        let floor = walk.ground + walk.eye_height;
+        let height = self.position.dot(up);
+        if jump && walk.is_grounded() && height <= floor + 1e-4 {
+            walk.velocity = walk.jump_speed;
+        }
+        if walk.velocity != 0.0 || height > floor + 1e-4 {
+            walk.velocity -= walk.gravity * dt;
+        }
+        let mut new_height = height + walk.velocity * dt;
+        if new_height <= floor {
+            new_height = floor;
+            walk.velocity = 0.0;
+        }
+        offset += (new_height - height) * up;
+
+        if offset == Vec3::ZERO {
+            return false;
+        }
+        self.position += offset;
+        self.center += offset;
+        self.update_view();
+        true
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
         self.view_matrix
     }
@@ -347,13 +603,71 @@ impl Camera {
     pub fn perspective_matrix(&self) -> Mat4 {
         self.persp_matrix
     }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.up
+    }
+
+    pub fn vfov(&self) -> f32 {
+        self.vfov
+    }
+
+    pub fn z_near(&self) -> f32 {
+        self.z_near
+    }
+
+    pub fn z_far(&self) -> f32 {
+        self.z_far
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn physical(&self) -> Option<&PhysicalCamera> {
+        self.physical.as_ref()
+    }
+
+    /// Thin lens of the physical model, a pinhole with unit exposure without one.
+    /// Orthographic cameras have parallel rays that a lens can't focus, they stay a pinhole.
+    pub fn lens(&self) -> ThinLens {
+        let mut lens = self.physical.map(|physical| physical.lens()).unwrap_or_default();
+        if let Projection::Orthographic { .. } = self.projection {
+            lens.aperture_radius = 0.0;
+        }
+        lens
+    }
+
+    pub fn window_size(&self) -> Vec2 {
+        self.window_size
+    }
+}
+
+/// Bell's virtual trackball, a sphere blended into a hyperbolic sheet away from the center.
+fn project_to_sphere(p: Vec2) -> Vec3 {
+    let d = p.length_squared();
+    let z = if d <= 0.5 { (1.0 - d).sqrt() } else { 0.5 / d.sqrt() };
+    vec3(p.x, p.y, z).normalize()
 }
 
 pub struct CameraManip {
+    /// Raw modifiers and mouse buttons, for callers of `Camera::mouse_move`. The w/a/s/d/q/e
+    /// fields follow the bound movement actions, for callers of `Camera::key_move`.
     pub input: CameraInput,
     pub camera: Camera,
     pub mode: CameraMode,
     pub speed: f32,
+    pub walk: Walk,
+    pub bindings: InputBindings,
+    pub triggers: TriggerState,
 }
 
 impl Default for CameraManip {
@@ -363,159 +677,260 @@ impl Default for CameraManip {
             camera: Camera::default(),
             mode: CameraMode::Spherical,
             speed: 1.0,
+            walk: Walk::default(),
+            bindings: InputBindings::default(),
+            triggers: TriggerState::default(),
         }
     }
 }
 
+/// Trackpads scroll in pixels, this many make up one wheel notch.
+const PIXELS_PER_LINE: f32 = 20.0;
+
 impl CameraManip {
     pub fn update(&mut self, window_event: &WindowEvent) -> bool {
-        let mut moved = false;
         match window_event {
             WindowEvent::Resized(winit::dpi::PhysicalSize { width, height }) => {
                 self.camera
                     .set_window_size(vec2(*width as f32, *height as f32));
+                false
             }
-            _ => {
-                match self.mode {
-                    CameraMode::Spherical => {
-                        moved = self.update_spherical(window_event);
-                    }
-                    CameraMode::Fly => {
-                        moved = self.update_fly(window_event);
-                    }
-                    _ => {}
-                };
-            }
+            _ => match self.mode {
+                CameraMode::Spherical => self.update_spherical(window_event),
+                CameraMode::Fly => self.update_fly(window_event),
+                CameraMode::Examine => self.update_examine(window_event),
+                CameraMode::Trackball => self.update_trackball(window_event),
+                CameraMode::Walk => self.update_walk(window_event),
+            },
         }
-        moved
     }
 
-    pub fn update_spherical(&mut self, window_event: &WindowEvent) -> bool {
-        let mut moved = false;
-        match window_event {
-            WindowEvent::ModifiersChanged(m) => {
-                self.input.alt = m.alt();
-                self.input.ctrl = m.ctrl() || m.logo();
-                self.input.shift = m.shift();
-            }
-            WindowEvent::CursorMoved { position, .. } => {
-                let pos = vec2(position.x as f32, position.y as f32);
-                if self.input.is_mouse_down() {
-                    moved = self.camera.mouse_move(pos.x, pos.y, &self.input);
-                } else {
-                    self.camera.set_mouse_pos(pos.x, pos.y);
-                }
-            }
-            WindowEvent::MouseWheel { delta, .. } => {
-                match delta {
-                    winit::event::MouseScrollDelta::PixelDelta(_) => {
-                        //camera.mouse_wheel(d.x.max(d.y) as i32);
-                    }
-                    winit::event::MouseScrollDelta::LineDelta(_, y) => {
-                        self.camera.mouse_wheel(-(*y) as i32);
-                        moved = true;
-                    }
-                };
-            }
-            WindowEvent::MouseInput { state, button, .. } => {
-                let is_down = match state {
-                    winit::event::ElementState::Pressed => true,
-                    winit::event::ElementState::Released => false,
-                };
-                match button {
-                    winit::event::MouseButton::Left => {
-                        self.input.lmb = is_down;
-                    }
-                    winit::event::MouseButton::Right => {
-                        self.input.rmb = is_down;
-                    }
-                    winit::event::MouseButton::Middle => {
-                        self.input.mmb = is_down;
-                    }
-                    _ => {}
-                }
+    pub fn is_active(&self, action: CameraAction) -> bool {
+        self.bindings.is_active(action, self.mode, &self.triggers)
+    }
+
+    /// Sum of the active movement actions in view space, x to the left, y up, z forward.
+    fn movement(&self) -> Vec3 {
+        let axis = |positive, negative| self.is_active(positive) as i32 as f32 - self.is_active(negative) as i32 as f32;
+        vec3(
+            axis(CameraAction::Left, CameraAction::Right),
+            axis(CameraAction::Up, CameraAction::Down),
+            axis(CameraAction::Forward, CameraAction::Backward),
+        )
+    }
+
+    /// Advances the time based modes, walk moves and falls here rather than on key events.
+    /// Call it once per frame with the frame time in seconds.
+    pub fn step(&mut self, dt: f32) -> bool {
+        match self.mode {
+            CameraMode::Walk => {
+                let movement = self.movement();
+                let jump = self.is_active(CameraAction::Jump);
+                self.camera.walk(vec2(-movement.x, movement.z), jump, &mut self.walk, self.speed, dt)
             }
-            _ => {}
+            _ => false,
         }
-        moved
     }
 
-    pub fn update_fly(&mut self, window_event: &WindowEvent) -> bool {
-        let mut moved = false;
+    /// Keeps the held triggers, modifiers and `input` up to date.
+    fn record_input(&mut self, window_event: &WindowEvent) {
         match window_event {
-            // handle w a s d key inputs
             WindowEvent::ModifiersChanged(m) => {
                 self.input.alt = m.alt();
                 self.input.ctrl = m.ctrl() || m.logo();
                 self.input.shift = m.shift();
+                self.triggers.modifiers = (*m).into();
             }
             WindowEvent::MouseInput { state, button, .. } => {
-                let is_down = match state {
-                    winit::event::ElementState::Pressed => true,
-                    winit::event::ElementState::Released => false,
-                };
+                let is_down = *state == ElementState::Pressed;
                 match button {
-                    winit::event::MouseButton::Left => {
-                        self.input.lmb = is_down;
-                    }
-                    winit::event::MouseButton::Right => {
-                        self.input.rmb = is_down;
-                    }
-                    winit::event::MouseButton::Middle => {
-                        self.input.mmb = is_down;
-                    }
+                    winit::event::MouseButton::Left => self.input.lmb = is_down,
+                    winit::event::MouseButton::Right => self.input.rmb = is_down,
+                    winit::event::MouseButton::Middle => self.input.mmb = is_down,
                     _ => {}
                 }
+                self.triggers.set(Trigger::Mouse(*button), is_down);
             }
             WindowEvent::KeyboardInput { input, .. } => {
-                if let Some(keycode) = input.virtual_keycode {
-                    match keycode {
-                        VirtualKeyCode::W => {
-                            self.input.w = input.state == ElementState::Pressed;
-                        }
-                        VirtualKeyCode::A => {
-                            self.input.a = input.state == ElementState::Pressed;
-                        }
-                        VirtualKeyCode::S => {
-                            self.input.s = input.state == ElementState::Pressed;
-                        }
-                        VirtualKeyCode::D => {
-                            self.input.d = input.state == ElementState::Pressed;
-                        }
-                        VirtualKeyCode::Q => {
-                            self.input.q = input.state == ElementState::Pressed;
-                        }
-                        VirtualKeyCode::E => {
-                            self.input.e = input.state == ElementState::Pressed;
-                        }
-                        _ => {}
-                    }
+                if let Some(key) = input.virtual_keycode {
+                    self.triggers.set(Trigger::Key(key), input.state == ElementState::Pressed);
                 }
-                moved = self.camera.key_move(&self.input, self.speed);
             }
+            WindowEvent::Focused(false) => {
+                // releases get lost while another window has focus
+                self.triggers.down.clear();
+            }
+            _ => {}
+        }
+        self.input.w = self.is_active(CameraAction::Forward);
+        self.input.s = self.is_active(CameraAction::Backward);
+        self.input.a = self.is_active(CameraAction::Left);
+        self.input.d = self.is_active(CameraAction::Right);
+        self.input.q = self.is_active(CameraAction::Up);
+        self.input.e = self.is_active(CameraAction::Down);
+    }
+
+    /// Whole notches scrolled by the event, pixel deltas are accumulated until they make one.
+    fn scroll_lines(&mut self, delta: &MouseScrollDelta) -> f32 {
+        match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            MouseScrollDelta::PixelDelta(p) => {
+                self.triggers.scroll += p.y as f32 / PIXELS_PER_LINE;
+                let lines = self.triggers.scroll.trunc();
+                self.triggers.scroll -= lines;
+                lines
+            }
+        }
+    }
 
+    /// Event handling shared by all modes, `zoom` handles scrolling the bound amount.
+    fn update_mode(&mut self, window_event: &WindowEvent, zoom: fn(&mut Camera, f32)) -> bool {
+        self.record_input(window_event);
+        match window_event {
             WindowEvent::CursorMoved { position, .. } => {
                 let pos = vec2(position.x as f32, position.y as f32);
-                if self.input.is_mouse_down() {
-                    moved = self.camera.mouse_move(pos.x, pos.y, &self.input);
-                } else {
-                    self.camera.set_mouse_pos(pos.x, pos.y);
+                match self.bindings.drag_action(self.mode, &self.triggers) {
+                    // walking turns the head rather than moving around the center
+                    Some(CameraAction::Look) if self.mode == CameraMode::Walk => self.camera.look_move(pos.x, pos.y),
+                    Some(action) => self.camera.mouse_drag(action, pos.x, pos.y),
+                    None => {
+                        self.camera.set_mouse_pos(pos.x, pos.y);
+                        false
+                    }
                 }
             }
             WindowEvent::MouseWheel { delta, .. } => {
-                match delta {
-                    winit::event::MouseScrollDelta::PixelDelta(_) => {
-                        //camera.mouse_wheel(d.x.max(d.y) as i32);
-                    }
-                    winit::event::MouseScrollDelta::LineDelta(_, y) => {
-                        self.camera.change_vfov(*y);
-                        //self.camera.mouse_wheel(*y as i32);
-                        moved = true;
-                    }
-                };
+                let lines = self.scroll_lines(delta);
+                let zooms = self.bindings.binding(self.mode, Trigger::Scroll, &self.triggers.modifiers)
+                    .map_or(false, |b| b.action == CameraAction::Zoom);
+                if !zooms || lines == 0.0 {
+                    return false;
+                }
+                zoom(&mut self.camera, lines);
+                true
             }
-            _ => {}
+            _ => false,
+        }
+    }
+
+    pub fn update_spherical(&mut self, window_event: &WindowEvent) -> bool {
+        self.update_mode(window_event, |camera, lines| camera.mouse_wheel(-lines as i32))
+    }
+
+    pub fn update_fly(&mut self, window_event: &WindowEvent) -> bool {
+        let moved = self.update_mode(window_event, |camera, lines| camera.change_vfov(lines));
+        if let WindowEvent::KeyboardInput { .. } = window_event {
+            let movement = self.movement();
+            return self.camera.move_local(movement, self.speed) || moved;
         }
         moved
     }
+
+    pub fn update_examine(&mut self, window_event: &WindowEvent) -> bool {
+        self.update_mode(window_event, |camera, lines| camera.mouse_wheel(-lines as i32))
+    }
+
+    pub fn update_trackball(&mut self, window_event: &WindowEvent) -> bool {
+        self.update_mode(window_event, |camera, lines| camera.mouse_wheel(-lines as i32))
+    }
+
+    /// Mouse drags look around, movement happens in `step`.
+    pub fn update_walk(&mut self, window_event: &WindowEvent) -> bool {
+        self.update_mode(window_event, |camera, lines| camera.change_vfov(lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new(vec2(800.0, 600.0));
+        camera.look_at(vec3(0.0, 2.0, 5.0), vec3(0.0, 2.0, 0.0), Vec3::Y);
+        camera
+    }
+
+    #[test]
+    fn trackball_keeps_distance_and_reverses() {
+        let mut camera = camera();
+        assert!(camera.trackball(vec2(0.0, 0.0), vec2(0.3, 0.2)));
+        assert!((camera.position().distance(camera.center()) - 5.0).abs() < 1e-4);
+        assert!(camera.up().dot(camera.position() - camera.center()).abs() < 1e-4);
+        // dragging to the right turns the camera to the left of the ball
+        assert!(camera.position().x < 0.0);
+
+        camera.trackball(vec2(0.3, 0.2), vec2(0.0, 0.0));
+        assert!(camera.position().abs_diff_eq(vec3(0.0, 2.0, 5.0), 1e-4));
+        assert!(camera.up().abs_diff_eq(Vec3::Y, 1e-4));
+    }
+
+    #[test]
+    fn walk_falls_to_eye_height() {
+        let mut camera = camera();
+        let mut walk = Walk::default();
+        for _ in 0..100 {
+            camera.walk(Vec2::Y, false, &mut walk, 1.0, 0.02);
+        }
+        assert!((camera.position().y - walk.eye_height).abs() < 1e-5);
+        assert!(walk.is_grounded());
+        assert!((camera.position().z - 3.0).abs() < 1e-4);
+
+        camera.walk(Vec2::ZERO, true, &mut walk, 1.0, 0.02);
+        assert!(camera.position().y > walk.eye_height);
+        for _ in 0..100 {
+            camera.walk(Vec2::ZERO, false, &mut walk, 1.0, 0.02);
+        }
+        assert!((camera.position().y - walk.eye_height).abs() < 1e-5);
+    }
+
+    #[test]
+    fn manip_follows_bindings() {
+        let mut manip = CameraManip { camera: camera(), mode: CameraMode::Walk, ..Default::default() };
+        manip.bindings = InputBindings::from_json(r#"{ "bindings": [{ "action": "Forward", "trigger": "Z" }] }"#).unwrap();
+        manip.triggers.set(Trigger::Key(winit::event::VirtualKeyCode::W), true);
+        assert_eq!(manip.movement(), Vec3::ZERO);
+        manip.triggers.set(Trigger::Key(winit::event::VirtualKeyCode::Z), true);
+        assert_eq!(manip.movement(), Vec3::Z);
+        // `input` follows the bound action rather than the key it's named after
+        manip.record_input(&WindowEvent::Focused(true));
+        assert!(manip.input.w);
+        let before = manip.camera.position();
+        manip.step(0.1);
+        assert!(manip.camera.position().z < before.z);
+    }
+
+    #[test]
+    fn frames_bounds() {
+        let points = [vec3(-3.0, 0.0, 1.0), vec3(4.0, 8.0, -2.0), vec3(1.0, -1.0, 5.0)];
+        let bounds = Bounds::from_points(&points);
+        let mut camera = camera();
+        camera.frame_bounds(&bounds);
+        assert_eq!(camera.center(), bounds.sphere.center);
+        assert!((camera.position() - camera.center()).normalize().abs_diff_eq(Vec3::Z, 1e-5));
+        let frustum = camera.frustum();
+        assert!(points.iter().all(|p| frustum.contains_point(*p)));
+        assert!(frustum.intersects(&bounds));
+        // dollying out keeps the far side of the bounds
+        let position = camera.center() + (camera.position() - camera.center()) * 3.0;
+        camera.look_at(position, camera.center(), camera.up());
+        let far = camera.position().distance(bounds.sphere.center) + bounds.sphere.radius;
+        assert!(camera.z_far() >= far);
+        assert!(points.iter().all(|p| camera.frustum().contains_point(*p)));
+    }
+
+    #[test]
+    fn orthographic_projection() {
+        let mut camera = camera();
+        camera.set_projection(Projection::Orthographic { ymag: 3.0 });
+        let corner = camera.perspective_matrix().project_point3(vec3(4.0, 3.0, -1.0));
+        assert!(corner.truncate().abs_diff_eq(vec2(1.0, 1.0), 1e-5));
+        camera.change_vfov(1.0);
+        match camera.projection() {
+            Projection::Orthographic { ymag } => assert!((ymag - 3.3).abs() < 1e-5),
+            Projection::Perspective => panic!("zooming changed the projection"),
+        }
+        // parallel rays have no depth of field
+        camera.set_physical(Some(PhysicalCamera { f_stop: Some(1.4), ..Default::default() }));
+        assert_eq!(camera.lens().aperture_radius, 0.0);
+    }
 }
@@ -0,0 +1,407 @@
+use std::error::Error;
+use std::path::Path;
+
+use glam::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Camera, CameraManip};
+
+/// Camera state at `time` seconds into a path.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraKey {
+    pub time: f32,
+    pub position: Vec3,
+    pub center: Vec3,
+    pub up: Vec3,
+    pub vfov: f32,
+}
+
+impl CameraKey {
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        CameraKey {
+            time,
+            position: camera.position(),
+            center: camera.center(),
+            up: camera.up(),
+            vfov: camera.vfov(),
+        }
+    }
+
+    /// Moves `camera` to the key, the up vector is renormalized after interpolation.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.look_at(self.position, self.center, self.up.try_normalize().unwrap_or(Vec3::Y));
+        camera.set_vfov(self.vfov);
+    }
+
+    fn weighted(keys: [&CameraKey; 4], weights: [f32; 4]) -> Self {
+        let sum3 = |f: fn(&CameraKey) -> Vec3| keys.iter().zip(weights).map(|(k, w)| f(k) * w).sum::<Vec3>();
+        CameraKey {
+            time: keys.iter().zip(weights).map(|(k, w)| k.time * w).sum(),
+            position: sum3(|k| k.position),
+            center: sum3(|k| k.center),
+            up: sum3(|k| k.up),
+            vfov: keys.iter().zip(weights).map(|(k, w)| k.vfov * w).sum(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PathInterpolation {
+    /// Passes through every key.
+    CatmullRom,
+    /// Uses the keys as the control points of one bezier curve, only the first and last key are hit.
+    Bezier,
+}
+
+/// Timed camera keys and how to interpolate between them. With `constant_speed` the eye moves
+/// along the curve at a steady pace over the whole duration instead of following the key times.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraPath {
+    keys: Vec<CameraKey>,
+    interpolation: PathInterpolation,
+    pub constant_speed: bool,
+    /// `(time, arc length)` samples of the eye, `ARC_LENGTH_SAMPLES` per segment after the one at the first key.
+    #[serde(skip)]
+    arc_lengths: Vec<(f32, f32)>,
+}
+
+const ARC_LENGTH_SAMPLES: usize = 32;
+
+impl CameraPath {
+    pub fn new(interpolation: PathInterpolation) -> Self {
+        CameraPath {
+            keys: Vec::new(),
+            interpolation,
+            constant_speed: false,
+            arc_lengths: Vec::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[CameraKey] {
+        &self.keys
+    }
+
+    pub fn interpolation(&self) -> PathInterpolation {
+        self.interpolation
+    }
+
+    /// Switches the curve type and resamples the path length.
+    pub fn set_interpolation(&mut self, interpolation: PathInterpolation) {
+        self.interpolation = interpolation;
+        self.update_arc_lengths();
+    }
+
+    /// Inserts `key` in time order, a key at the same time is replaced.
+    /// Appending only resamples the last few catmull rom segments, a bezier path is resampled whole.
+    pub fn push(&mut self, key: CameraKey) {
+        let i = self.insert(key);
+        // a catmull rom segment depends on the two keys before and after it
+        self.update_arc_lengths_from(i.saturating_sub(2));
+    }
+
+    /// Inserts `key` without resampling, returns its index.
+    fn insert(&mut self, key: CameraKey) -> usize {
+        match self.keys.binary_search_by(|k| k.time.total_cmp(&key.time)) {
+            Ok(i) => {
+                self.keys[i] = key;
+                i
+            }
+            Err(i) => {
+                self.keys.insert(i, key);
+                i
+            }
+        }
+    }
+
+    pub fn start_time(&self) -> f32 {
+        self.keys.first().map_or(0.0, |k| k.time)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |k| k.time) - self.start_time()
+    }
+
+    /// Length of the eye's path.
+    pub fn length(&self) -> f32 {
+        self.arc_lengths.last().map_or(0.0, |(_, length)| *length)
+    }
+
+    /// Camera at `time`, clamped to the first and last key.
+    pub fn sample(&self, time: f32) -> Option<CameraKey> {
+        let time = if self.constant_speed { self.constant_speed_time(time) } else { time };
+        self.sample_curve(time)
+    }
+
+    pub fn apply(&self, time: f32, camera: &mut Camera) -> bool {
+        match self.sample(time) {
+            Some(key) => {
+                key.apply(camera);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sample_curve(&self, time: f32) -> Option<CameraKey> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if self.keys.len() == 1 || time <= first.time {
+            return Some(CameraKey { time, ..*first });
+        }
+        if time >= last.time {
+            return Some(CameraKey { time, ..*last });
+        }
+        let mut key = match self.interpolation {
+            PathInterpolation::CatmullRom => self.catmull_rom(time),
+            PathInterpolation::Bezier => self.bezier((time - first.time) / self.duration()),
+        };
+        key.time = time;
+        Some(key)
+    }
+
+    fn catmull_rom(&self, time: f32) -> CameraKey {
+        let n = self.keys.len();
+        let i = self.keys.partition_point(|k| k.time <= time).clamp(1, n - 1) - 1;
+        let key = |j: isize| &self.keys[j.clamp(0, n as isize - 1) as usize];
+        let (p0, p1, p2, p3) = (key(i as isize - 1), key(i as isize), key(i as isize + 1), key(i as isize + 2));
+        let t = (time - p1.time) / (p2.time - p1.time);
+        let (t2, t3) = (t * t, t * t * t);
+        CameraKey::weighted([p0, p1, p2, p3], [
+            0.5 * (-t3 + 2.0 * t2 - t),
+            0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+            0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+            0.5 * (t3 - t2),
+        ])
+    }
+
+    /// De Casteljau over all keys at curve parameter `t` in `[0, 1]`.
+    fn bezier(&self, t: f32) -> CameraKey {
+        let mut points = self.keys.clone();
+        for level in (1..points.len()).rev() {
+            for i in 0..level {
+                let (a, b) = (points[i], points[i + 1]);
+                points[i] = CameraKey::weighted([&a, &b, &a, &b], [1.0 - t, t, 0.0, 0.0]);
+            }
+        }
+        points[0]
+    }
+
+    fn update_arc_lengths(&mut self) {
+        self.update_arc_lengths_from(0);
+    }
+
+    /// Resamples the segments from `first` on, the ones before keep their samples.
+    fn update_arc_lengths_from(&mut self, first: usize) {
+        let segments = self.keys.len().saturating_sub(1);
+        // every key shapes the whole bezier curve
+        let first = if self.interpolation == PathInterpolation::Bezier { 0 } else { first.min(segments) };
+        if segments == 0 || first == 0 {
+            self.arc_lengths.clear();
+        }
+        if segments == 0 {
+            return;
+        }
+        if self.arc_lengths.is_empty() {
+            self.arc_lengths.push((self.keys[0].time, 0.0));
+        }
+        self.arc_lengths.truncate(first * ARC_LENGTH_SAMPLES + 1);
+        let mut length = self.arc_lengths.last().unwrap().1;
+        let mut previous = self.sample_curve(self.keys[first].time).unwrap().position;
+        for segment in first..segments {
+            let (start, end) = (self.keys[segment].time, self.keys[segment + 1].time);
+            for i in 1..=ARC_LENGTH_SAMPLES {
+                let time = start + (end - start) * i as f32 / ARC_LENGTH_SAMPLES as f32;
+                let position = self.sample_curve(time).unwrap().position;
+                length += position.distance(previous);
+                previous = position;
+                self.arc_lengths.push((time, length));
+            }
+        }
+    }
+
+    /// Curve time at which the eye has covered the same share of the path length as `time` of the duration.
+    fn constant_speed_time(&self, time: f32) -> f32 {
+        let length = self.length();
+        if length <= f32::EPSILON || self.duration() <= 0.0 {
+            return time;
+        }
+        let target = ((time - self.start_time()) / self.duration()).clamp(0.0, 1.0) * length;
+        let i = self.arc_lengths.partition_point(|(_, l)| *l < target).clamp(1, self.arc_lengths.len() - 1);
+        let ((t0, l0), (t1, l1)) = (self.arc_lengths[i - 1], self.arc_lengths[i]);
+        if l1 - l0 <= f32::EPSILON {
+            return t0;
+        }
+        t0 + (t1 - t0) * (target - l0) / (l1 - l0)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let mut path: CameraPath = serde_json::from_str(json)?;
+        path.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        path.update_arc_lengths();
+        Ok(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_json(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Records a `CameraPath` from an interactive session. Call `step` once per frame, a key is added
+/// at most every `interval` seconds and only when the camera moved since the last one.
+/// Pauses are kept by a key at the last still frame before the camera moves again.
+#[derive(Debug, Clone)]
+pub struct CameraRecorder {
+    /// Keys recorded so far, the path length is only sampled by `finish`.
+    pub path: CameraPath,
+    pub interval: f32,
+    time: f32,
+    last_key: f32,
+    /// Latest frame the camera still was at the pose of the last key.
+    hold: Option<CameraKey>,
+    /// Pose of the latest frame.
+    current: Option<CameraKey>,
+}
+
+fn same_pose(a: &CameraKey, b: &CameraKey) -> bool {
+    a.position.abs_diff_eq(b.position, 1e-6)
+        && a.center.abs_diff_eq(b.center, 1e-6)
+        && a.up.abs_diff_eq(b.up, 1e-6)
+        && a.vfov == b.vfov
+}
+
+impl CameraRecorder {
+    pub fn new(interpolation: PathInterpolation, interval: f32) -> Self {
+        CameraRecorder {
+            path: CameraPath::new(interpolation),
+            interval,
+            time: 0.0,
+            last_key: f32::NEG_INFINITY,
+            hold: None,
+            current: None,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Returns true when a key was added.
+    pub fn step(&mut self, manip: &CameraManip, dt: f32) -> bool {
+        self.time += dt;
+        let key = CameraKey::from_camera(self.time, &manip.camera);
+        self.current = Some(key);
+        let moved = self.path.keys().last().is_none_or(|last| !same_pose(last, &key));
+        if !moved {
+            self.hold = Some(key);
+            return false;
+        }
+        if self.time - self.last_key < self.interval {
+            return false;
+        }
+        if let Some(hold) = self.hold.take() {
+            self.path.insert(hold);
+        }
+        self.path.insert(key);
+        self.last_key = self.time;
+        true
+    }
+
+    /// The recorded path, ending with the pose of the last frame.
+    pub fn finish(mut self) -> CameraPath {
+        if let Some(hold) = self.hold.take() {
+            self.path.insert(hold);
+        }
+        if let Some(current) = self.current {
+            if self.path.keys().last().is_none_or(|last| current.time > last.time) {
+                self.path.insert(current);
+            }
+        }
+        self.path.update_arc_lengths();
+        self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, x: f32) -> CameraKey {
+        CameraKey { time, position: vec3(x, 0.0, 5.0), center: vec3(x, 0.0, 0.0), up: Vec3::Y, vfov: 35.0 + x }
+    }
+
+    #[test]
+    fn interpolates_keys() {
+        let mut path = CameraPath::new(PathInterpolation::CatmullRom);
+        for (time, x) in [(2.0, 1.0), (0.0, 0.0), (3.0, 4.0), (1.0, 0.5)] {
+            path.push(key(time, x));
+        }
+        assert_eq!(path.keys().iter().map(|k| k.time).collect::<Vec<_>>(), vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(path.sample(1.0).unwrap().position.x, 0.5);
+        assert_eq!(path.sample(10.0).unwrap().position.x, 4.0);
+        let between = path.sample(2.5).unwrap();
+        assert!(between.position.x > 1.0 && between.position.x < 4.0);
+        assert!((between.vfov - 35.0 - between.position.x).abs() < 1e-4);
+
+        // at constant speed the eye covers half the path length at half the duration
+        path.constant_speed = true;
+        let half = path.sample(1.5).unwrap().position.x;
+        assert!((half - path.length() / 2.0).abs() < 1e-2);
+
+        // loading resamples everything, pushing only the segments around the new key
+        let loaded = CameraPath::from_json(&path.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, path);
+
+        let mut bezier = CameraPath::new(PathInterpolation::Bezier);
+        for k in path.keys() {
+            bezier.push(*k);
+        }
+        assert_eq!(bezier.sample(0.0).unwrap().position.x, 0.0);
+        assert_eq!(bezier.sample(3.0).unwrap().position.x, 4.0);
+
+        // switching the curve type resamples the length
+        path.set_interpolation(PathInterpolation::Bezier);
+        assert_eq!(path.length(), bezier.length());
+    }
+
+    #[test]
+    fn records_session() {
+        let mut manip = CameraManip { camera: Camera::new(vec2(800.0, 600.0)), ..Default::default() };
+        let mut recorder = CameraRecorder::new(PathInterpolation::CatmullRom, 0.1);
+        let mut recorded = 0;
+        // moves for half a second, holds still for half a second and moves again
+        for frame in 0..90 {
+            let x = match frame {
+                0..=29 => frame as f32,
+                30..=59 => 29.0,
+                _ => frame as f32 - 30.0,
+            };
+            let position = vec3(x, 0.0, 5.0);
+            manip.camera.look_at(position, position - Vec3::Z, Vec3::Y);
+            recorded += recorder.step(&manip, 1.0 / 60.0) as usize;
+        }
+        let path = recorder.finish();
+        assert!(recorded > 4 && recorded <= 12);
+        assert!(path.keys().windows(2).all(|w| w[1].time > w[0].time));
+
+        // the pause ends with a key at the last still frame, right before the camera moves on
+        let hold = path.keys().iter().position(|k| (k.time - 1.0).abs() < 1e-4).unwrap();
+        assert_eq!(path.keys()[hold].position.x, 29.0);
+        assert_eq!(path.keys()[hold + 1].position.x, 30.0);
+
+        // and the path ends at the last frame
+        let last = path.keys().last().unwrap();
+        assert!((last.time - 1.5).abs() < 1e-4);
+        assert_eq!(last.position.x, 59.0);
+
+        // finishing samples the whole path length once
+        assert_eq!(CameraPath::from_json(&path.to_json().unwrap()).unwrap(), path);
+    }
+}
@@ -5,32 +5,64 @@ use serde::{Serialize, Deserialize};
 //use super::mesh::{Vertex, Mesh};
 //use super::format::Convertable;
 
+// Fields follow the DSON spec: anything the spec marks optional is either an
+// `Option` or falls back to the spec default, so older or partial assets still load.
+
 structstruck::strike! {
-    #[strikethrough[derive(Debug, Serialize, Deserialize)]]
+    #[strikethrough[derive(Debug, Default, Serialize, Deserialize)]]
     pub struct AssetInfo {
         pub id: String,
+        #[serde(default)]
         pub r#type: String,
+        #[serde(default)]
         pub contributor: struct {
             author: String,
-            email: String,
-            website: String,
+            email: Option<String>,
+            website: Option<String>,
         },
+        #[serde(default = "default_revision")]
         pub revision: String,
+        #[serde(default)]
         pub modified: String,
     }
 }
 
+pub(super) fn default_revision() -> String {
+    "1.0".to_string()
+}
+
 structstruck::strike! {
-    #[strikethrough[derive(Debug, Serialize, Deserialize)]]
+    #[strikethrough[derive(Debug, Clone, Serialize, Deserialize)]]
     pub struct Region {
-        id: String,
-        label: String,
-        display_hint: String,
-        map: Option<struct {
-            count: u32,
-            values: Vec<u32>,
+        pub id: String,
+        pub label: Option<String>,
+        pub display_hint: Option<String>,
+        pub map: Option<pub struct {
+            pub count: u32,
+            pub values: Vec<u32>,
         }>,
-        children: Option<Vec<Region>>,
+        pub children: Option<Vec<Region>>,
+    }
+}
+
+// A geograft attaches to a target figure with matching `vertex_count` and `poly_count`.
+// `vertex_pairs` welds graft vertices onto target vertices, `hidden_polys` are target
+// polygons covered by the graft.
+structstruck::strike! {
+    #[strikethrough[derive(Debug, Default, Clone, Serialize, Deserialize)]]
+    pub struct Graft {
+        pub vertex_count: u32,
+        pub poly_count: u32,
+        #[serde(default)]
+        pub vertex_pairs: pub struct {
+            pub count: u32,
+            pub values: Vec<(u32, u32)>,
+        },
+        #[serde(default)]
+        pub hidden_polys: pub struct {
+            pub count: u32,
+            pub values: Vec<u32>,
+        },
     }
 }
 
@@ -51,11 +83,13 @@ structstruck::strike! {
     #[strikethrough[derive(Debug, Serialize, Deserialize)]]
     pub struct GeometryLibrary {
         pub id: String,
+        #[serde(default)]
         pub name: String,
         pub id_aliases: Option<Vec<String>>,
+        #[serde(default = "default_geometry_type")]
         pub r#type: String,
-        pub edge_interpolation_mode: String,
-        pub subd_normal_smoothing_mode: String,
+        pub edge_interpolation_mode: Option<String>,
+        pub subd_normal_smoothing_mode: Option<String>,
         pub vertices: struct {
             pub count: u32,
             pub values: Vec<[f32; 3]>,
@@ -72,26 +106,76 @@ structstruck::strike! {
             pub count: u32,
             pub values: Vec<Vec<u32>>,
         },
-        pub default_uv_set: String,
-        pub root_region: Region,
-        pub graft: Option<Value>,
+        pub default_uv_set: Option<String>,
+        pub root_region: Option<Region>,
+        pub graft: Option<Graft>,
+        pub rigidity: Option<Value>,
+        #[serde(default)]
         pub extra: Vec<Value>
     }
 }
 
+fn default_geometry_type() -> String {
+    "polygon_mesh".to_string()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Handle {
     pub id: String,
+    #[serde(default = "default_channel_type")]
     pub r#type: String,
+    #[serde(default)]
     pub name: String,
-    pub label: String,
+    pub label: Option<String>,
     pub auto_follow: Option<bool>,
     pub visible: Option<bool>,
+    #[serde(default)]
     pub value: f32,
+    pub current_value: Option<f32>,
+    #[serde(default)]
     pub min: f32,
+    #[serde(default = "default_channel_max")]
     pub max: f32,
+    #[serde(default)]
+    pub clamped: bool,
     pub display_as_percent: Option<bool>,
-    pub step_size: f32,
+    pub step_size: Option<f32>,
+}
+
+fn default_channel_type() -> String {
+    "float".to_string()
+}
+
+fn default_channel_max() -> f32 {
+    1.0
+}
+
+impl Handle {
+    fn axis(id: &str, value: f32) -> Handle {
+        Handle {
+            id: id.to_string(),
+            r#type: default_channel_type(),
+            name: id.to_string(),
+            label: None,
+            auto_follow: None,
+            visible: None,
+            value,
+            current_value: None,
+            min: 0.0,
+            max: default_channel_max(),
+            clamped: false,
+            display_as_percent: None,
+            step_size: None,
+        }
+    }
+}
+
+fn zero_channels() -> Vec<Handle> {
+    vec![Handle::axis("x", 0.0), Handle::axis("y", 0.0), Handle::axis("z", 0.0)]
+}
+
+fn unit_channels() -> Vec<Handle> {
+    vec![Handle::axis("x", 1.0), Handle::axis("y", 1.0), Handle::axis("z", 1.0)]
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -108,8 +192,9 @@ pub struct Formula {
     operations: Vec<Operation>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum RotationOrder {
+    #[default]
     XYZ,
     XZY,
     YXZ,
@@ -126,16 +211,26 @@ structstruck::strike! {
         pub id_aliases: Option<Vec<String>>,
         pub name_aliases: Option<Vec<String>>,
         pub extended_asset_ids: Option<Vec<String>>,
+        #[serde(default = "default_node_type")]
         pub r#type: String,
-        pub label: String,
+        pub label: Option<String>,
+        pub source: Option<String>,
         pub parent: Option<String>,
+        #[serde(default)]
         pub rotation_order: RotationOrder,
+        #[serde(default = "default_inherits_scale")]
         pub inherits_scale: bool,
+        #[serde(default = "zero_channels")]
         pub center_point: Vec<Handle>,
+        #[serde(default = "zero_channels")]
         pub end_point: Vec<Handle>,
+        #[serde(default = "zero_channels")]
         pub orientation: Vec<Handle>,
+        #[serde(default = "zero_channels")]
         pub rotation: Vec<Handle>,
+        #[serde(default = "zero_channels")]
         pub translation: Vec<Handle>,
+        #[serde(default = "unit_channels")]
         pub scale: Vec<Handle>,
         pub general_scale: Option<Handle>,
         pub formulas: Option<Vec<Formula>>,
@@ -143,24 +238,38 @@ structstruck::strike! {
             r#type: String,
             label: String,
             description: String,
+            icon_small: Option<String>,
             icon_large: String,
             colors: Vec<Vec<f32>>,
-            auto_fit_base: String,
+            auto_fit_base: Option<String>,
             extended_bases: Option<Vec<String>>,
         }>,
+        #[serde(default)]
         pub extra: Vec<Value>,
     }
 }
 
+fn default_node_type() -> String {
+    "node".to_string()
+}
+
+fn default_inherits_scale() -> bool {
+    true
+}
+
 structstruck::strike! {
-    #[strikethrough[derive(Debug, Serialize, Deserialize)]]
+    #[strikethrough[derive(Debug, Default, Serialize, Deserialize)]]
     pub struct Joint {
         pub id: String,
         pub node: String,
+        #[serde(default)]
         pub node_weights: pub struct {
             pub count: i32,
             pub values: Vec<(i32, f32)>,
         },
+        pub local_weights: Option<Value>,
+        pub scale_weights: Option<Value>,
+        pub bulge_weights: Option<Value>,
     }
 }
 
@@ -175,7 +284,9 @@ pub struct Skin {
     pub node: String,
     pub geometry: String,
     pub vertex_count: u32,
+    #[serde(default)]
     pub joints: Vec<Joint>,
+    #[serde(default)]
     pub selection_map: Vec<SymmetryMapping>,
 }
 
@@ -183,18 +294,18 @@ structstruck::strike! {
     #[strikethrough[derive(Debug, Serialize, Deserialize)]]
     pub struct Modifier {
         pub id: String,
+        #[serde(default)]
         pub name: String,
-        pub parent: String,
-        pub skin: Skin,
-        pub extra: Vec<pub struct {
-            r#type: String,
-            auto_normalize_general: bool,
-            auto_normalize_local: bool,
-            auto_normalize_scale: bool,
-            binding_mode: String,
-            general_map_mode: String,
-            scale_mode: String,
-        }>,
+        pub parent: Option<String>,
+        pub presentation: Option<Value>,
+        pub channel: Option<Handle>,
+        pub region: Option<String>,
+        pub group: Option<String>,
+        pub formulas: Option<Vec<Formula>>,
+        pub morph: Option<Value>,
+        pub skin: Option<Skin>,
+        #[serde(default)]
+        pub extra: Vec<Value>,
     }
 }
 
@@ -203,8 +314,11 @@ structstruck::strike! {
 pub struct DSF {
     pub file_version: String,
     pub asset_info: AssetInfo,
+    #[serde(default)]
     pub geometry_library: Vec<GeometryLibrary>,
+    #[serde(default)]
     pub node_library: Vec<Node>,
+    #[serde(default)]
     pub modifier_library: Vec<Modifier>,
 }
 
@@ -4,16 +4,20 @@ use serde::{Serialize, Deserialize};
 // use super::dsf::Node;
 
 structstruck::strike! {
-    #[strikethrough[derive(Debug, Serialize, Deserialize)]]
+    #[strikethrough[derive(Debug, Default, Serialize, Deserialize)]]
     pub struct AssetInfo {
         pub id: String,
+        #[serde(default)]
         pub r#type: String,
+        #[serde(default)]
         contributor: struct {
             author: String,
-            email: String,
-            website: String,
+            email: Option<String>,
+            website: Option<String>,
         },
+        #[serde(default = "super::dsf::default_revision")]
         pub revision: String,
+        #[serde(default)]
         pub modified: String,
     }
 }
@@ -22,11 +26,26 @@ structstruck::strike! {
     #[strikethrough[derive(Debug, Serialize, Deserialize)]]
     pub struct ImageRefs {
         pub id: String,
+        #[serde(default)]
         pub name: String,
-        pub map_gamma: f32,
+        pub map_gamma: Option<f32>,
+        pub map_size: Option<[u32; 2]>,
+        #[serde(default)]
         pub map: Vec<pub struct {
             pub url: String,
-            pub label: String,
+            pub label: Option<String>,
+            pub active: Option<bool>,
+            pub color: Option<[f32; 3]>,
+            pub transparency: Option<f32>,
+            pub invert: Option<bool>,
+            pub rotation: Option<f32>,
+            pub xmirror: Option<bool>,
+            pub ymirror: Option<bool>,
+            pub xscale: Option<f32>,
+            pub yscale: Option<f32>,
+            pub xoffset: Option<f32>,
+            pub yoffset: Option<f32>,
+            pub operation: Option<String>,
         }>,
     }
 }
@@ -34,31 +53,37 @@ structstruck::strike! {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MaterialChannel {
     id: String,
+    #[serde(default)]
     r#type: String,
+    #[serde(default)]
     name: String,
     label: Option<String>,
-    value: [f32; 3],
-    current_value: [f32; 3],
-    min: f32,
-    max: f32,
+    value: Option<[f32; 3]>,
+    current_value: Option<[f32; 3]>,
+    min: Option<f32>,
+    max: Option<f32>,
+    #[serde(default)]
     clamped: bool,
-    step_size: f32,
-    default_image_gamma: f32,
-    mappable: bool, 
+    step_size: Option<f32>,
+    default_image_gamma: Option<f32>,
+    #[serde(default)]
+    mappable: bool,
+    image: Option<String>,
 }
 
 structstruck::strike! {
     #[strikethrough[derive(Debug, Serialize, Deserialize)]]
     pub struct DiffuseMaterial {
         channel: MaterialChannel,
-        group: String,
-        presentation: struct {
+        group: Option<String>,
+        presentation: Option<struct {
             r#type: String,
             label: String,
             description: String,
+            icon_small: Option<String>,
             icon_large: String,
             colors: Vec<[f32; 3]>,
-        }
+        }>
     }
 }
 
@@ -67,7 +92,11 @@ structstruck::strike! {
     #[strikethrough[derive(Debug, Serialize, Deserialize)]]
     pub struct Material {
         pub id: String,
-        pub diffuse: DiffuseMaterial,
+        pub name: Option<String>,
+        pub uv_set: Option<String>,
+        pub r#type: Option<String>,
+        pub diffuse: Option<DiffuseMaterial>,
+        #[serde(default)]
         pub extra: Vec<Value>
     }
 }
@@ -76,26 +105,31 @@ structstruck::strike! {
 pub struct GeoIdentifier {
     pub id: String,
     pub url: String,
+    #[serde(default)]
     pub name: String,
-    pub label: String,
-    pub r#type: String,
+    pub label: Option<String>,
+    pub r#type: Option<String>,
+    #[serde(default)]
     pub current_subdivision_level: u8,
-    pub edge_interpolation_mode: String,
-    pub subd_normal_smoothing_mode: String,
+    pub edge_interpolation_mode: Option<String>,
+    pub subd_normal_smoothing_mode: Option<String>,
+    #[serde(default)]
     pub extra: Vec<Value>,
 }
 
 structstruck::strike! {
     #[strikethrough[derive(Debug, Serialize, Deserialize)]]
     pub struct Preview {
-        pub r#type: String,
+        pub r#type: Option<String>,
         pub oriented_box: Option<pub struct {
             min: [f32; 3],
             max: [f32; 3],
         }>,
+        #[serde(default)]
         pub center_point: [f32; 3],
+        #[serde(default)]
         pub end_point: [f32; 3],
-        pub rotation_order: String,
+        pub rotation_order: Option<String>,
     }
 }
 
@@ -106,10 +140,14 @@ structstruck::strike! {
         pub url: String,
         pub r#type: Option<String>,
         pub parent: Option<String>,
+        pub parent_in_place: Option<String>,
+        pub conform_target: Option<String>,
+        #[serde(default)]
         pub name: String,
-        pub label: String,
+        pub label: Option<String>,
         pub geometries: Option<Vec<GeoIdentifier>>,
-        pub preview: Preview,
+        pub preview: Option<Preview>,
+        #[serde(default)]
         pub extra: Vec<Value>,
     }
 }
@@ -120,18 +158,23 @@ structstruck::strike! {
         pub id: Option<String>,
         pub url: String,
         pub geometry: Option<String>,
+        #[serde(default)]
         pub groups: Vec<String>,
-        pub diffuse: pub struct {
+        pub diffuse: Option<pub struct {
             pub channel: pub struct {
                 pub id: String,
+                #[serde(default)]
                 pub r#type: String,
+                #[serde(default)]
                 pub name: String,
+                #[serde(default)]
                 pub value: [f32; 3],
                 pub current_value: Option<[f32; 3]>,
                 pub image: Option<String>,
             },
-        },
+        }>,
         pub uv_set: Option<String>, // url
+        #[serde(default)]
         pub extra: Vec<Value>,
     }
 }
@@ -139,12 +182,15 @@ structstruck::strike! {
 
 
 structstruck::strike! {
-    #[strikethrough[derive(Debug, Serialize, Deserialize)]]
+    #[strikethrough[derive(Debug, Default, Serialize, Deserialize)]]
     pub struct Scene {
         pub nodes: Option<Vec<SceneNode>>,
+        pub uvs: Option<Vec<Value>>,
         pub materials: Option<Vec<MaterialNode>>,
         pub modifiers: Option<Vec<Value>>,
-        pub exta: Option<Vec<Value>>,
+        pub animations: Option<Vec<Value>>,
+        pub current_camera: Option<String>,
+        pub extra: Option<Vec<Value>>,
     }
 }
 
@@ -155,6 +201,7 @@ structstruck::strike! {
         pub asset_info: AssetInfo,
         pub image_library: Option<Vec<ImageRefs>>,
         pub material_library: Option<Vec<Material>>,
+        #[serde(default)]
         pub scene: Scene,
     }
 }
@@ -4,9 +4,9 @@ use std::{collections::HashMap, error::Error};
 use std::fmt::{Debug, Formatter};
 
 use crate::resource::{BreadthFirstIterator, DepthFirstIterator};
-use crate::resource::skin::{Transform, Bone, RigParser, Rig, RotationOrder};
+use crate::resource::skin::{Transform, Bone, RigParser, Rig, RotationOrder, DualQuat};
 
-use super::{DSF, dsf};
+use super::{DSF, dsf, JointConstraint};
 use super::dsf::Handle;
 use ahash::HashSet;
 use glam::*;
@@ -35,8 +35,9 @@ fn radians_to_degrees(radians: Vec3) -> Vec3 {
 impl<S: Transform, T: Bone<S>> RigParser<RigV1<S, T>, S, T> for DazRigParserV1<RigV1<S, T>, S, T> {
 
     fn parse(file: &DSF) -> Vec<Result<RigV1<S,T>, Box<dyn Error>>> {
-        file.modifier_library.iter().map(|m| {
-            let joint_names = m.skin.joints.iter().map(|j| j.node.strip_prefix("#").unwrap().to_string()).collect::<HashSet<_>>();
+        // only skin binding modifiers describe a rig, morphs and the like are skipped
+        file.modifier_library.iter().filter_map(|m| m.skin.as_ref()).map(|skin| {
+            let joint_names = skin.joints.iter().map(|j| j.node.strip_prefix("#").unwrap().to_string()).collect::<HashSet<_>>();
             
             println!("joint_names: {:?}", joint_names);
             let mut bone_map: HashMap<String, usize> = HashMap::new();
@@ -70,10 +71,10 @@ impl<S: Transform, T: Bone<S>> RigParser<RigV1<S, T>, S, T> for DazRigParserV1<R
                 // print!("scale: {:?};\n orientation: {:?};\n rotation: {:?};\n translation: {:?}\n", scale, orientation, rotation, translation); 
                 
     
-                let bone = T::from(
+                let mut bone = T::from(
                     n.name.clone(),
                     n.id.clone(),
-                    n.label.clone(),
+                    n.label.clone().unwrap_or_else(|| n.name.clone()),
                     i,
                     None,
                     Vec::new(),
@@ -94,6 +95,7 @@ impl<S: Transform, T: Bone<S>> RigParser<RigV1<S, T>, S, T> for DazRigParserV1<R
                         n.rotation_order.into()
                     ), // initialize with bind pose
                 );
+                bone.set_constraint(JointConstraint::from_node(n));
                 // print!("yielded local_transform: {:?}\n", bone.get_local_transform());
     
                 (bone, parent)
@@ -306,6 +308,40 @@ impl<T: Transform, S: Bone<T>> RigV1<T, S> {
         }
         acc
     }
+
+    /// Sets the local transforms so that bone `i` ends up at `globals[i]`, indexed like `bones`.
+    /// `local = global * parent_global^-1`, same as the rig parser. Bones whose local
+    /// doesn't change keep their transform as it is.
+    pub fn set_global_transforms(&mut self, globals: &[Mat4]) {
+        for (i, global) in globals.iter().enumerate().take(self.bones.len()) {
+            let local = match self.bones[i].get_parent().and_then(|p| globals.get(p)) {
+                Some(parent) => *global * parent.inverse(),
+                None => *global,
+            };
+            if !self.bones[i].get_local_transform().get_matrix().abs_diff_eq(local, 1e-6) {
+                self.bones[i].set_local_transform(T::from_mat4(local));
+            }
+        }
+    }
+}
+
+/// Rig for tests, bone `i` is `joints[i]`: name, parent and global rest transform.
+#[cfg(test)]
+pub(crate) fn test_rig(joints: &[(&str, Option<usize>, Mat4)]) -> RigV1<Mat4, BoneV1<Mat4>> {
+    let bones = joints.iter().enumerate().map(|(i, (name, parent, global))| {
+        let center = global.w_axis.truncate();
+        let mut bone = <BoneV1<Mat4> as Bone<Mat4>>::from(name.to_string(), name.to_string(), name.to_string(), i, *parent, Vec::new(), center, center, *global, *global);
+        bone.set_inverse_bind_matrix(global.inverse());
+        bone
+    }).collect();
+    let mut rig = RigV1 {
+        bone_map: joints.iter().enumerate().map(|(i, (name, ..))| (name.to_string(), i)).collect(),
+        bones,
+        root_bone: joints.iter().position(|(_, parent, _)| parent.is_none()).unwrap_or(0),
+        root_transform: Mat4::IDENTITY,
+    };
+    rig.set_global_transforms(&joints.iter().map(|(.., global)| *global).collect::<Vec<_>>());
+    rig
 }
 
 // FROM DSF
@@ -329,6 +365,8 @@ pub struct BoneV1<T: Transform> {
     pub local_transform: T,
     pub global_transform: T,
     pub inverse_bind_matrix: T,
+    /// Limits, auto follow and scale inheritance from the DSF node.
+    pub constraint: Option<JointConstraint>,
 }
 
 impl<T: Transform + Mul> Bone<T> for BoneV1<T> {
@@ -356,6 +394,7 @@ impl<T: Transform + Mul> Bone<T> for BoneV1<T> {
             local_transform,
             global_transform,
             inverse_bind_matrix: local_transform.get_inverse(),
+            constraint: None,
         }
     }
     fn get_name(&self) -> &str {
@@ -406,86 +445,219 @@ impl<T: Transform + Mul> Bone<T> for BoneV1<T> {
     fn add_child(&mut self, child: String) {
         self.children.push(child);
     }
+
+    fn get_constraint(&self) -> Option<&JointConstraint> {
+        self.constraint.as_ref()
+    }
+
+    fn set_constraint(&mut self, constraint: JointConstraint) {
+        self.constraint = Some(constraint);
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Decomposed transform, `T * R * S` like `Mat4::from_scale_rotation_translation`.
+/// Products and inverses are exact while the scale is uniform, which keeps rigs free of the
+/// drift repeated `Mat4` products pick up. A non uniform scale followed by a rotation shears,
+/// which `T * R * S` can't hold; those go through `Mat4` and lose the shear.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TransformV1 {
-    pub rotation_order: RotationOrder,
-    pub orientation: Vec3,
-    pub rotation: Vec3,
-    pub translation: Vec3,
     pub scale: Vec3,
+    pub rotation: Quat,
+    pub translation: Vec3,
+    /// Order `get_rotation` reports euler angles in.
+    pub rotation_order: RotationOrder,
+}
+
+impl TransformV1 {
+    pub fn new(scale: Vec3, rotation: Quat, translation: Vec3) -> Self {
+        TransformV1 { scale, rotation, translation, rotation_order: RotationOrder::XYZ }
+    }
+
+    pub fn transform_point3(&self, point: Vec3) -> Vec3 {
+        self.rotation * (self.scale * point) + self.translation
+    }
+
+    fn has_uniform_scale(&self) -> bool {
+        self.scale.abs_diff_eq(Vec3::splat(self.scale.x), 1e-6 * self.scale.x.abs().max(1.0))
+    }
+
+    fn from_mat4_with_order(mat: Mat4, rotation_order: RotationOrder) -> Self {
+        TransformV1 { rotation_order, ..<Self as Transform>::from_mat4(mat) }
+    }
+
+    pub fn inverse(&self) -> Self {
+        if !self.has_uniform_scale() {
+            return Self::from_mat4_with_order(self.get_matrix().inverse(), self.rotation_order);
+        }
+        let rotation = self.rotation.inverse();
+        let scale = self.scale.recip();
+        TransformV1 {
+            scale,
+            rotation,
+            // translation of (T * R * S)^-1 = S^-1 * R^-1 * T^-1
+            translation: scale * (rotation * -self.translation),
+            rotation_order: self.rotation_order,
+        }
+    }
+
+    /// Interpolates translation and scale linearly and the rotation along the shortest arc.
+    pub fn slerp(&self, other: &Self, t: f32) -> Self {
+        TransformV1 {
+            scale: self.scale.lerp(other.scale, t),
+            rotation: self.rotation.slerp(other.rotation, t).normalize(),
+            translation: self.translation.lerp(other.translation, t),
+            rotation_order: self.rotation_order,
+        }
+    }
 }
 
 impl Mul for TransformV1 {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self {
-        Self::from_mat4(self.get_matrix().mul_mat4(&rhs.get_matrix()))
+        if !self.has_uniform_scale() {
+            return Self::from_mat4_with_order(self.get_matrix() * rhs.get_matrix(), self.rotation_order);
+        }
+        TransformV1 {
+            scale: self.scale * rhs.scale,
+            rotation: (self.rotation * rhs.rotation).normalize(),
+            translation: self.transform_point3(rhs.translation),
+            rotation_order: self.rotation_order,
+        }
     }
 }
 
+/// Component wise sum, for weighted blends of transforms. Rotations are summed in the
+/// hemisphere of `self` and normalized.
 impl Add for TransformV1 {
     type Output = Self;
     fn add(self, rhs: Self) -> Self {
-        Self::from_mat4(self.get_matrix().add_mat4(&rhs.get_matrix()))
+        let rhs_rotation = if self.rotation.dot(rhs.rotation) < 0.0 { -rhs.rotation } else { rhs.rotation };
+        TransformV1 {
+            scale: self.scale + rhs.scale,
+            rotation: (self.rotation + rhs_rotation).normalize(),
+            translation: self.translation + rhs.translation,
+            rotation_order: self.rotation_order,
+        }
     }
 }
 
 impl Transform for TransformV1 {
     fn from_mat4(mat: Mat4) -> Self {
         let (scale, rotation, translation) = mat.to_scale_rotation_translation();
-        let w = rotation.w;
-        let r_ = rotation.xyz();
-        let phi = 2.0 * w.acos();
-        let orientation = r_ / (phi / 2.0).sin();
-        let rotation = orientation * phi;
-        TransformV1 {
-            rotation_order: RotationOrder::XYZ,
-            orientation,
-            rotation,
-            translation,
-            scale,
-        }
+        TransformV1::new(scale, rotation.normalize(), translation)
     }
+    /// Same as the `Mat4` implementation. A non uniform scale in a rotated orientation frame
+    /// doesn't fit scale, rotation and translation, so it goes through the matrix.
     fn from_scale_rotation_translation(scale: Vec3, orientation:Vec3, rotation: Vec3, translation: Vec3, rotation_order: RotationOrder) -> Self {
+        let uniform = TransformV1::new(scale, Quat::IDENTITY, Vec3::ZERO).has_uniform_scale();
+        if !uniform && orientation != Vec3::ZERO {
+            let matrix = <Mat4 as Transform>::from_scale_rotation_translation(scale, orientation, rotation, translation, rotation_order);
+            return Self::from_mat4_with_order(matrix, rotation_order);
+        }
+        let orientation = RotationOrder::XYZ.to_quat(orientation);
         TransformV1 {
-            rotation_order,
-            orientation,
-            rotation,
-            translation,
             scale,
+            rotation: (orientation * rotation_order.to_quat(rotation) * orientation.inverse()).normalize(),
+            translation,
+            rotation_order,
         }
     }
     fn get_position(&self) -> Vec3 {
         self.translation
     }
     fn get_rotation(&self) -> Vec3 {
-        self.rotation
+        self.rotation_order.to_angles(self.rotation)
     }
     fn get_scale(&self) -> Vec3 {
         self.scale
     }
     fn get_matrix(&self) -> Mat4 {
-        Mat4::from_scale_rotation_translation(self.scale, Quat::from_rotation_arc(self.orientation, self.rotation), self.translation)
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
     }
     fn get_inverse<T: Transform>(&self) -> T {
-        T::from_mat4(self.get_matrix().inverse())
+        T::from_mat4(self.inverse().get_matrix())
     }
     fn get_inverse_transpose(&self) -> Mat4 {
         self.get_matrix().inverse().transpose()
     }
     fn get_d_quat(&self) -> (Quat, Quat) {
-        self.get_matrix().get_d_quat()
+        let dq = DualQuat::from_rotation_translation(self.rotation, self.translation);
+        (dq.real, dq.dual)
     }
 
     fn zero() -> Self {
-        Self {
-            rotation_order: RotationOrder::XYZ,
-            orientation: Vec3::ZERO,
-            rotation: Vec3::ZERO,
-            translation: Vec3::ZERO,
-            scale: Vec3::ONE,
-        }
+        TransformV1::new(Vec3::ONE, Quat::IDENTITY, Vec3::ZERO)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(seed: f32) -> TransformV1 {
+        TransformV1::new(
+            Vec3::splat(1.0 + seed * 0.5),
+            Quat::from_euler(EulerRot::XYZ, seed, 0.3 - seed, 0.7 * seed),
+            Vec3::new(seed, -2.0 * seed, 3.0),
+        )
+    }
+
+    #[test]
+    fn transform_v1_matches_mat4() {
+        let (a, b) = (transform(0.4), transform(1.3));
+        assert!((a * b).get_matrix().abs_diff_eq(a.get_matrix() * b.get_matrix(), 1e-4));
+        assert!(a.inverse().get_matrix().abs_diff_eq(a.get_matrix().inverse(), 1e-4));
+        assert!((a * a.inverse()).get_matrix().abs_diff_eq(Mat4::IDENTITY, 1e-5));
+        assert!(a.get_inverse::<Mat4>().abs_diff_eq(a.get_matrix().inverse(), 1e-4));
+
+        // same as the Mat4 implementation and back
+        let (scale, orientation, rotation, translation) = (Vec3::splat(2.0), Vec3::new(0.1, 0.2, 0.3), Vec3::new(0.5, -0.4, 1.2), Vec3::new(1.0, 2.0, 3.0));
+        for order in [RotationOrder::XYZ, RotationOrder::YZX, RotationOrder::ZYX] {
+            let srt = TransformV1::from_scale_rotation_translation(scale, orientation, rotation, translation, order);
+            let matrix = <Mat4 as Transform>::from_scale_rotation_translation(scale, orientation, rotation, translation, order);
+            assert!(srt.get_matrix().abs_diff_eq(matrix, 1e-4));
+            assert!(TransformV1::from_mat4(matrix).get_matrix().abs_diff_eq(matrix, 1e-4));
+            let plain = TransformV1::from_scale_rotation_translation(scale, Vec3::ZERO, rotation, translation, order);
+            assert!(plain.get_rotation().abs_diff_eq(rotation, 1e-4));
+        }
+
+        let halfway = a.slerp(&b, 0.5);
+        assert!(halfway.rotation.abs_diff_eq(a.rotation.slerp(b.rotation, 0.5), 1e-5));
+        assert!(halfway.translation.abs_diff_eq((a.translation + b.translation) * 0.5, 1e-5));
+        assert_eq!(a.slerp(&b, 0.0), a);
+    }
+
+    #[test]
+    fn transform_v1_non_uniform_scale() {
+        // quarter turns keep the products free of shear, so they are exact in T * R * S form
+        let a = TransformV1::new(Vec3::new(1.0, 2.0, 3.0), Quat::from_rotation_z(std::f32::consts::FRAC_PI_2), Vec3::new(1.0, 0.0, -2.0));
+        let b = TransformV1::new(Vec3::new(2.0, 1.0, 0.5), Quat::from_rotation_x(std::f32::consts::FRAC_PI_2), Vec3::new(0.0, 3.0, 1.0));
+        assert!((a * b).get_matrix().abs_diff_eq(a.get_matrix() * b.get_matrix(), 1e-4));
+        assert!((b * a).get_matrix().abs_diff_eq(b.get_matrix() * a.get_matrix(), 1e-4));
+        assert!(a.inverse().get_matrix().abs_diff_eq(a.get_matrix().inverse(), 1e-4));
+        assert!((a * a.inverse()).get_matrix().abs_diff_eq(Mat4::IDENTITY, 1e-4));
+        assert_eq!((a * b).rotation_order, a.rotation_order);
+
+        // scale acts in the orientation frame, quarter turns again keep it free of shear
+        let (scale, orientation, rotation, translation) = (Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2), Vec3::new(std::f32::consts::FRAC_PI_2, 0.0, 0.0), Vec3::new(1.0, 2.0, 3.0));
+        let srt = TransformV1::from_scale_rotation_translation(scale, orientation, rotation, translation, RotationOrder::XYZ);
+        let matrix = <Mat4 as Transform>::from_scale_rotation_translation(scale, orientation, rotation, translation, RotationOrder::XYZ);
+        assert!(srt.get_matrix().abs_diff_eq(matrix, 1e-4));
+        assert_eq!(srt.rotation_order, RotationOrder::XYZ);
+    }
+
+    #[test]
+    fn transform_v1_drives_rig() {
+        let globals = [transform(0.0), transform(0.5), transform(0.9)];
+        let bones = globals.iter().enumerate().map(|(i, global)| {
+            let parent = i.checked_sub(1);
+            let local = parent.map_or(*global, |p| *global * globals[p].inverse());
+            <BoneV1<TransformV1> as Bone<TransformV1>>::from(i.to_string(), i.to_string(), i.to_string(), i, parent, Vec::new(), global.translation, global.translation, local, *global)
+        }).collect::<Vec<_>>();
+        let rig = RigV1 { bone_map: HashMap::new(), bones, root_bone: 0, root_transform: TransformV1::identity() };
+        let mat4 = test_rig(&rig.bones.iter().map(|b| (b.name.as_str(), b.parent, b.global_transform.get_matrix())).collect::<Vec<_>>());
+        for i in 0..3 {
+            assert!(rig.local_to_global(i).get_matrix().abs_diff_eq(mat4.local_to_global(i), 1e-4));
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::resource::mesh::{FaceSelection, SelectionKind};
+use super::dsf::{AssetInfo, GeometryLibrary, Joint, Region, Skin, DSF};
+
+#[derive(Debug)]
+pub enum GraftError {
+    NotAGraft(String),
+    NoTarget(String),
+    InvalidIndex(String),
+}
+
+impl fmt::Display for GraftError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for GraftError {}
+
+/// Where the parts of a graft ended up after merging it into its target.
+#[derive(Debug, Clone)]
+pub struct GraftMap {
+    /// Merged vertex index of every graft vertex. Welded vertices point at their target vertex.
+    pub vertices: Vec<u32>,
+    /// Merged polygon index of every graft polygon.
+    pub polys: Vec<u32>,
+}
+
+// Urls to nodes and geometries are either local (`#hip`) or point into another file
+// (`/data/.../Genesis8Female.dsf#hip`). Grafts reference their target by the latter.
+fn fragment(url: &str) -> &str {
+    url.rsplit('#').next().unwrap_or(url)
+}
+
+fn merge_names(target: &mut Vec<String>, names: &[String]) -> Vec<u32> {
+    names.iter().map(|name| {
+        match target.iter().position(|t| t == name) {
+            Some(i) => i as u32,
+            None => {
+                target.push(name.clone());
+                (target.len() - 1) as u32
+            }
+        }
+    }).collect()
+}
+
+fn remap_region(region: &mut Region, remap: &dyn Fn(u32) -> Option<u32>) {
+    if let Some(map) = &mut region.map {
+        map.values = map.values.iter().filter_map(|p| remap(*p)).collect();
+        map.count = map.values.len() as u32;
+    }
+    for child in region.children.iter_mut().flatten() {
+        remap_region(child, remap);
+    }
+}
+
+// Collects the region and all its descendants, parents containing their children's polygons.
+fn region_polys(region: &Region, out: &mut Vec<(String, Vec<u32>)>) -> Vec<u32> {
+    let mut polys = region.map.as_ref().map(|m| m.values.clone()).unwrap_or_default();
+    for child in region.children.iter().flatten() {
+        polys.extend(region_polys(child, out));
+    }
+    out.push((region.id.clone(), polys.clone()));
+    polys
+}
+
+// Checks every index of the graft up front so a bad graft leaves the target untouched.
+fn validate_graft(geometry: &GeometryLibrary, target_vertices: u32) -> Result<(), GraftError> {
+    let graft = geometry.graft.as_ref().unwrap();
+    if let Some(pair) = graft.vertex_pairs.values.iter().find(|(v, t)| *t >= target_vertices || *v as usize >= geometry.vertices.values.len()) {
+        return Err(GraftError::InvalidIndex(format!("{}: vertex pair {:?}", geometry.id, pair)));
+    }
+    let vertex_count = geometry.vertices.values.len() as u32;
+    let invalid = geometry.polylist.values.iter().find(|poly| {
+        poly.len() < 5
+            || poly[0] as usize >= geometry.polygon_groups.values.len()
+            || poly[1] as usize >= geometry.polygon_material_groups.values.len()
+            || poly[2..].iter().any(|v| *v >= vertex_count)
+    });
+    match invalid {
+        Some(poly) => Err(GraftError::InvalidIndex(format!("{}: polygon {:?}", geometry.id, poly))),
+        None => Ok(()),
+    }
+}
+
+impl GeometryLibrary {
+    /// Splits the polylist into triangles.
+    /// Returns the index buffer and, per triangle, the polygon it was cut from.
+    pub fn triangulate(&self) -> (Vec<u32>, Vec<u32>) {
+        let mut indices = Vec::new();
+        let mut triangle_polys = Vec::new();
+        // polylist entries are [polygon group, material group, v0, v1, v2, (v3)]
+        for (p, poly) in self.polylist.values.iter().enumerate() {
+            let corners = poly.get(2..).unwrap_or(&[]);
+            for i in 1..corners.len().saturating_sub(1) {
+                indices.extend([corners[0], corners[i], corners[i + 1]]);
+                triangle_polys.push(p as u32);
+            }
+        }
+        (indices, triangle_polys)
+    }
+
+    /// Regions, polygon groups and surfaces as selections over the triangles from [`GeometryLibrary::triangulate`].
+    pub fn face_selections(&self, triangle_polys: &[u32]) -> Vec<FaceSelection> {
+        let mut poly_triangles = vec![Vec::new(); self.polylist.values.len()];
+        for (t, p) in triangle_polys.iter().enumerate() {
+            poly_triangles[*p as usize].push(t as u32);
+        }
+        let faces_of = |polys: &mut dyn Iterator<Item = u32>| {
+            polys.filter_map(|p| poly_triangles.get(p as usize)).flatten().copied().collect::<Vec<_>>()
+        };
+
+        let mut selections = Vec::new();
+        for (kind, slot, names) in [
+            (SelectionKind::PolygonGroup, 0, &self.polygon_groups.values),
+            (SelectionKind::MaterialGroup, 1, &self.polygon_material_groups.values),
+        ] {
+            for (g, name) in names.iter().enumerate() {
+                let mut polys = self.polylist.values.iter().enumerate()
+                    .filter(|(_, poly)| poly.get(slot) == Some(&(g as u32)))
+                    .map(|(p, _)| p as u32);
+                selections.push(FaceSelection { name: name.clone(), kind, faces: faces_of(&mut polys) });
+            }
+        }
+
+        let mut regions = Vec::new();
+        if let Some(root) = &self.root_region {
+            region_polys(root, &mut regions);
+        }
+        for (name, polys) in regions.into_iter().rev() {
+            let mut faces = faces_of(&mut polys.into_iter());
+            if faces.is_empty() {
+                continue;
+            }
+            faces.sort_unstable();
+            faces.dedup();
+            selections.push(FaceSelection { name, kind: SelectionKind::Region, faces });
+        }
+        selections
+    }
+
+    /// Whether `graft` was authored against this geometry.
+    pub fn accepts_graft(&self, graft: &GeometryLibrary) -> bool {
+        graft.graft.as_ref().is_some_and(|g| {
+            g.vertex_count as usize == self.vertices.values.len() && g.poly_count as usize == self.polylist.values.len()
+        })
+    }
+
+    /// Merges geografts into this geometry: polygons hidden by any graft are removed,
+    /// graft vertices listed in `vertex_pairs` are welded onto their target vertex and
+    /// everything else is appended. Grafts must all target the unmodified geometry,
+    /// which is why they are merged in one go.
+    pub fn merge_grafts(&mut self, grafts: &[&GeometryLibrary]) -> Result<Vec<GraftMap>, GraftError> {
+        for graft in grafts {
+            if graft.graft.is_none() {
+                return Err(GraftError::NotAGraft(graft.id.clone()));
+            }
+            if !self.accepts_graft(graft) {
+                return Err(GraftError::NoTarget(graft.id.clone()));
+            }
+            validate_graft(graft, self.vertices.values.len() as u32)?;
+        }
+
+        // drop hidden polygons and renumber the rest
+        let hidden = grafts.iter()
+            .flat_map(|g| g.graft.as_ref().unwrap().hidden_polys.values.iter().copied())
+            .collect::<HashSet<u32>>();
+        let mut poly_remap = Vec::with_capacity(self.polylist.values.len());
+        let mut polys = Vec::with_capacity(self.polylist.values.len());
+        for (p, poly) in std::mem::take(&mut self.polylist.values).into_iter().enumerate() {
+            if hidden.contains(&(p as u32)) {
+                poly_remap.push(None);
+            } else {
+                poly_remap.push(Some(polys.len() as u32));
+                polys.push(poly);
+            }
+        }
+        if let Some(root) = &mut self.root_region {
+            remap_region(root, &|p| poly_remap.get(p as usize).copied().flatten());
+        }
+
+        let mut maps = Vec::with_capacity(grafts.len());
+        for graft_geometry in grafts {
+            let graft = graft_geometry.graft.as_ref().unwrap();
+            let pairs = graft.vertex_pairs.values.iter().copied().collect::<HashMap<u32, u32>>();
+
+            let vertices = graft_geometry.vertices.values.iter().enumerate().map(|(v, position)| {
+                match pairs.get(&(v as u32)) {
+                    Some(target) => *target,
+                    None => {
+                        self.vertices.values.push(*position);
+                        (self.vertices.values.len() - 1) as u32
+                    }
+                }
+            }).collect::<Vec<u32>>();
+            let groups = merge_names(&mut self.polygon_groups.values, &graft_geometry.polygon_groups.values);
+            let materials = merge_names(&mut self.polygon_material_groups.values, &graft_geometry.polygon_material_groups.values);
+
+            let first_poly = polys.len() as u32;
+            polys.extend(graft_geometry.polylist.values.iter().map(|poly| {
+                let mut merged = vec![groups[poly[0] as usize], materials[poly[1] as usize]];
+                merged.extend(poly[2..].iter().map(|v| vertices[*v as usize]));
+                merged
+            }));
+
+            if let Some(graft_root) = &graft_geometry.root_region {
+                let mut region = graft_root.clone();
+                remap_region(&mut region, &|p| Some(p + first_poly));
+                match &mut self.root_region {
+                    Some(root) => root.children.get_or_insert_with(Vec::new).push(region),
+                    None => self.root_region = Some(region),
+                }
+            }
+
+            maps.push(GraftMap {
+                vertices,
+                polys: (first_poly..polys.len() as u32).collect(),
+            });
+        }
+
+        self.polylist.values = polys;
+        self.polylist.count = self.polylist.values.len() as u32;
+        self.vertices.count = self.vertices.values.len() as u32;
+        self.polygon_groups.count = self.polygon_groups.values.len() as u32;
+        self.polygon_material_groups.count = self.polygon_material_groups.values.len() as u32;
+        Ok(maps)
+    }
+}
+
+impl Skin {
+    // Adds the weights of a graft skin. Welded vertices keep the target's weights.
+    fn merge_graft(&mut self, graft: &Skin, map: &GraftMap, welded: &HashSet<u32>, vertex_count: u32) {
+        for joint in &graft.joints {
+            let weights = joint.node_weights.values.iter()
+                .filter(|(v, _)| !welded.contains(&(*v as u32)))
+                .filter_map(|(v, w)| map.vertices.get(*v as usize).map(|m| (*m as i32, *w)));
+            let node = fragment(&joint.node);
+            match self.joints.iter_mut().find(|j| fragment(&j.node) == node) {
+                Some(target) => target.node_weights.values.extend(weights),
+                None => {
+                    let mut merged = Joint { id: joint.id.clone(), node: format!("#{}", node), ..Default::default() };
+                    merged.node_weights.values.extend(weights);
+                    self.joints.push(merged);
+                }
+            }
+        }
+        for joint in &mut self.joints {
+            joint.node_weights.count = joint.node_weights.values.len() as i32;
+        }
+        self.vertex_count = vertex_count;
+    }
+}
+
+impl DSF {
+    /// Merges geograft figures (e.g. from separate DSF files) into the matching geometry of this figure.
+    /// Skin weights of the grafts are carried over and bones only the graft has are added to the node library.
+    pub fn merge_grafts(&mut self, grafts: Vec<DSF>) -> Result<(), GraftError> {
+        let mut by_target: HashMap<usize, Vec<(&DSF, &GeometryLibrary)>> = HashMap::new();
+        for dsf in &grafts {
+            for geometry in dsf.geometry_library.iter().filter(|g| g.graft.is_some()) {
+                let target = self.geometry_library.iter().position(|t| t.accepts_graft(geometry))
+                    .ok_or_else(|| GraftError::NoTarget(geometry.id.clone()))?;
+                by_target.entry(target).or_default().push((dsf, geometry));
+            }
+        }
+
+        for (target, parts) in by_target {
+            let geometries = parts.iter().map(|(_, g)| *g).collect::<Vec<_>>();
+            let maps = self.geometry_library[target].merge_grafts(&geometries)?;
+            let target_id = self.geometry_library[target].id.clone();
+            let vertex_count = self.geometry_library[target].vertices.count;
+
+            let Some(target_skin) = self.modifier_library.iter_mut()
+                .filter_map(|m| m.skin.as_mut())
+                .find(|s| fragment(&s.geometry) == target_id) else { continue };
+            for ((dsf, geometry), map) in parts.iter().zip(maps.iter()) {
+                let graft_skin = dsf.modifier_library.iter()
+                    .filter_map(|m| m.skin.as_ref())
+                    .find(|s| fragment(&s.geometry) == geometry.id);
+                if let Some(graft_skin) = graft_skin {
+                    let welded = geometry.graft.as_ref().unwrap().vertex_pairs.values.iter().map(|(v, _)| *v).collect();
+                    target_skin.merge_graft(graft_skin, map, &welded, vertex_count);
+                }
+            }
+        }
+
+        let known = self.node_library.iter().map(|n| n.id.clone()).collect::<HashSet<_>>();
+        for dsf in grafts {
+            for mut node in dsf.node_library.into_iter().filter(|n| n.r#type == "bone" && !known.contains(&n.id)) {
+                // parents of graft bones usually live in the target figure's file
+                node.parent = node.parent.map(|p| format!("#{}", fragment(&p)));
+                self.node_library.push(node);
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges geografts that live in this file's geometry library into their targets.
+    /// Grafts without a target in this file are left alone.
+    pub fn merge_own_grafts(&mut self) -> Result<(), GraftError> {
+        let (grafts, geometries): (Vec<_>, Vec<_>) = std::mem::take(&mut self.geometry_library)
+            .into_iter()
+            .partition(|g| g.graft.is_some());
+        let (grafts, unmatched): (Vec<_>, Vec<_>) = grafts.into_iter()
+            .partition(|graft| geometries.iter().any(|g| g.accepts_graft(graft)));
+        self.geometry_library = geometries;
+        self.geometry_library.extend(unmatched);
+        if grafts.is_empty() {
+            return Ok(());
+        }
+
+        let graft_ids = grafts.iter().map(|g| g.id.clone()).collect::<HashSet<_>>();
+        let (graft_skins, modifiers): (Vec<_>, Vec<_>) = std::mem::take(&mut self.modifier_library)
+            .into_iter()
+            .partition(|m| m.skin.as_ref().is_some_and(|s| graft_ids.contains(fragment(&s.geometry))));
+        self.modifier_library = modifiers;
+        self.merge_grafts(vec![DSF {
+            file_version: self.file_version.clone(),
+            asset_info: AssetInfo::default(),
+            geometry_library: grafts,
+            node_library: Vec::new(),
+            modifier_library: graft_skins,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn graft_replaces_hidden_polys() {
+        // two quads side by side, the graft swaps out the right one
+        let mut target: GeometryLibrary = serde_json::from_value(json!({
+            "id": "body",
+            "vertices": { "count": 6, "values": [[0,0,0],[1,0,0],[2,0,0],[0,1,0],[1,1,0],[2,1,0]] },
+            "polygon_groups": { "count": 2, "values": ["left", "right"] },
+            "polygon_material_groups": { "count": 1, "values": ["skin"] },
+            "polylist": { "count": 2, "values": [[0,0,0,1,4,3],[1,0,1,2,5,4]] },
+            "root_region": { "id": "Root", "children": [
+                { "id": "Left", "map": { "count": 1, "values": [0] } },
+                { "id": "Right", "map": { "count": 1, "values": [1] } }
+            ] }
+        })).unwrap();
+        let graft: GeometryLibrary = serde_json::from_value(json!({
+            "id": "patch",
+            "vertices": { "count": 4, "values": [[1,0,0],[2,0,0],[2,1,0],[1.5,0.5,0.5]] },
+            "polygon_groups": { "count": 1, "values": ["patch"] },
+            "polygon_material_groups": { "count": 1, "values": ["skin"] },
+            "polylist": { "count": 2, "values": [[0,0,0,1,3],[0,0,1,2,3]] },
+            "root_region": { "id": "Patch", "map": { "count": 2, "values": [0, 1] } },
+            "graft": {
+                "vertex_count": 6,
+                "poly_count": 2,
+                "vertex_pairs": { "count": 3, "values": [[0,1],[1,2],[2,5]] },
+                "hidden_polys": { "count": 1, "values": [1] }
+            }
+        })).unwrap();
+
+        let maps = target.merge_grafts(&[&graft]).unwrap();
+        assert_eq!(maps[0].vertices, vec![1, 2, 5, 6]);
+        assert_eq!(target.vertices.values.len(), 7);
+        assert_eq!(target.polylist.values, vec![vec![0, 0, 0, 1, 4, 3], vec![2, 0, 1, 2, 6], vec![2, 0, 2, 5, 6]]);
+
+        let (indices, triangle_polys) = target.triangulate();
+        assert_eq!(indices.len(), 12);
+        let selections = target.face_selections(&triangle_polys);
+        let find = |name: &str| selections.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(find("left").faces, vec![0, 1]);
+        assert!(find("right").faces.is_empty());
+        assert_eq!(find("patch").faces, vec![2, 3]);
+        assert_eq!(find("Patch").kind, SelectionKind::Region);
+        assert!(selections.iter().all(|s| s.name != "Right"));
+        assert_eq!(find("Root").faces, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn import_merges_grafts() {
+        let path = std::env::temp_dir().join("bolt_graft_import.dsf");
+        std::fs::write(&path, json!({
+            "file_version": "0.6.0.0",
+            "asset_info": { "id": "/bolt_graft_import.dsf" },
+            "geometry_library": [
+                {
+                    "id": "body",
+                    "vertices": { "count": 6, "values": [[0,0,0],[1,0,0],[2,0,0],[0,1,0],[1,1,0],[2,1,0]] },
+                    "polygon_groups": { "count": 2, "values": ["left", "right"] },
+                    "polygon_material_groups": { "count": 1, "values": ["skin"] },
+                    "polylist": { "count": 2, "values": [[0,0,0,1,4,3],[1,0,1,2,5,4]] }
+                },
+                {
+                    "id": "patch",
+                    "vertices": { "count": 4, "values": [[1,0,0],[2,0,0],[2,1,0],[1.5,0.5,0.5]] },
+                    "polygon_groups": { "count": 1, "values": ["patch"] },
+                    "polygon_material_groups": { "count": 1, "values": ["skin"] },
+                    "polylist": { "count": 2, "values": [[0,0,0,1,3],[0,0,1,2,3]] },
+                    "graft": {
+                        "vertex_count": 6,
+                        "poly_count": 2,
+                        "vertex_pairs": { "count": 3, "values": [[0,1],[1,2],[2,5]] },
+                        "hidden_polys": { "count": 1, "values": [1] }
+                    }
+                }
+            ],
+            "modifier_library": [
+                { "id": "body_skin", "skin": { "node": "#body", "geometry": "#body", "vertex_count": 6, "joints": [
+                    { "id": "hip", "node": "#hip", "node_weights": { "count": 6, "values": [[0,1],[1,1],[2,1],[3,1],[4,1],[5,1]] } }
+                ] } },
+                { "id": "patch_skin", "skin": { "node": "#patch", "geometry": "#patch", "vertex_count": 4, "joints": [
+                    { "id": "hip", "node": "#hip", "node_weights": { "count": 4, "values": [[0,1],[1,1],[2,1],[3,1]] } }
+                ] } }
+            ]
+        }).to_string()).unwrap();
+
+        let dsf = crate::scene::daz::import(&path).unwrap();
+        assert_eq!(dsf.geometry_library.len(), 1);
+        assert_eq!(dsf.geometry_library[0].vertices.values.len(), 7);
+        assert_eq!(dsf.geometry_library[0].polylist.values.len(), 3);
+        let skins = dsf.modifier_library.iter().filter_map(|m| m.skin.as_ref()).collect::<Vec<_>>();
+        assert_eq!(skins.len(), 1);
+        assert_eq!(skins[0].vertex_count, 7);
+        assert_eq!(skins[0].joints[0].node_weights.values.last(), Some(&(6, 1.0)));
+    }
+}
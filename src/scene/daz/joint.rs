@@ -0,0 +1,383 @@
+use glam::{Mat4, Quat, Vec3};
+
+use crate::resource::skin::{rotation_matrix, Bone, RotationOrder, Transform};
+use super::dsf::{self, Handle, DSF};
+use super::format::RigV1;
+
+/// One animatable channel of a DSF node. Values are stored as DAZ Studio shows them,
+/// degrees for rotations and factors for scales.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointChannel {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub clamped: bool,
+    /// Follows the channel of the same name on the figure this one is fitted to.
+    pub auto_follow: bool,
+}
+
+impl JointChannel {
+    pub fn free(value: f32) -> Self {
+        JointChannel { value, min: f32::NEG_INFINITY, max: f32::INFINITY, clamped: false, auto_follow: false }
+    }
+
+    pub fn from_handle(handle: &Handle) -> Self {
+        JointChannel {
+            value: handle.current_value.unwrap_or(handle.value),
+            min: handle.min,
+            max: handle.max,
+            clamped: handle.clamped,
+            auto_follow: handle.auto_follow.unwrap_or(false),
+        }
+    }
+
+    /// Stores `value`, limited to `min..=max` if the channel is clamped, and returns what was stored.
+    pub fn set(&mut self, value: f32) -> f32 {
+        self.value = if self.clamped { value.clamp(self.min, self.max) } else { value };
+        self.value
+    }
+}
+
+fn channels(handles: &[Handle], default: f32) -> [JointChannel; 3] {
+    ["x", "y", "z"].map(|axis| handles.iter().find(|h| h.id == axis).map_or(JointChannel::free(default), JointChannel::from_handle))
+}
+
+fn values(channels: &[JointChannel; 3]) -> Vec3 {
+    Vec3::new(channels[0].value, channels[1].value, channels[2].value)
+}
+
+fn set_values(channels: &mut [JointChannel; 3], values: Vec3) -> Vec3 {
+    Vec3::new(channels[0].set(values.x), channels[1].set(values.y), channels[2].set(values.z))
+}
+
+/// Pose channels of a bone with the limits from its DSF node.
+/// Setters clamp, so the stored pose always respects the limits.
+#[derive(Debug, Clone)]
+pub struct JointConstraint {
+    pub name: String,
+    pub rotation_order: RotationOrder,
+    pub center_point: Vec3,
+    /// Euler XYZ in degrees, the frame the rotation and scale channels act in.
+    pub orientation: Vec3,
+    pub translation: [JointChannel; 3],
+    pub rotation: [JointChannel; 3],
+    pub scale: [JointChannel; 3],
+    pub general_scale: JointChannel,
+    pub inherits_scale: bool,
+}
+
+impl JointConstraint {
+    pub fn from_node(node: &dsf::Node) -> Self {
+        JointConstraint {
+            name: node.name.clone(),
+            rotation_order: node.rotation_order.into(),
+            center_point: values(&channels(&node.center_point, 0.0)),
+            orientation: values(&channels(&node.orientation, 0.0)),
+            translation: channels(&node.translation, 0.0),
+            rotation: channels(&node.rotation, 0.0),
+            scale: channels(&node.scale, 1.0),
+            general_scale: node.general_scale.as_ref().map_or(JointChannel::free(1.0), JointChannel::from_handle),
+            inherits_scale: node.inherits_scale,
+        }
+    }
+
+    /// An unconstrained joint at `center_point`, for bones without a node.
+    pub fn free(name: &str, center_point: Vec3) -> Self {
+        JointConstraint {
+            name: name.to_string(),
+            rotation_order: RotationOrder::XYZ,
+            center_point,
+            orientation: Vec3::ZERO,
+            translation: [JointChannel::free(0.0); 3],
+            rotation: [JointChannel::free(0.0); 3],
+            scale: [JointChannel::free(1.0); 3],
+            general_scale: JointChannel::free(1.0),
+            inherits_scale: true,
+        }
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        values(&self.translation)
+    }
+
+    /// Degrees per axis.
+    pub fn rotation(&self) -> Vec3 {
+        values(&self.rotation)
+    }
+
+    /// Per axis scale including the general scale.
+    pub fn scale(&self) -> Vec3 {
+        values(&self.scale) * self.general_scale.value
+    }
+
+    pub fn set_translation(&mut self, translation: Vec3) -> Vec3 {
+        set_values(&mut self.translation, translation)
+    }
+
+    pub fn set_rotation(&mut self, degrees: Vec3) -> Vec3 {
+        set_values(&mut self.rotation, degrees)
+    }
+
+    pub fn set_scale(&mut self, scale: Vec3) -> Vec3 {
+        set_values(&mut self.scale, scale)
+    }
+
+    pub fn set_general_scale(&mut self, scale: f32) -> f32 {
+        self.general_scale.set(scale)
+    }
+
+    fn radians(degrees: Vec3) -> Vec3 {
+        Vec3::new(degrees.x.to_radians(), degrees.y.to_radians(), degrees.z.to_radians())
+    }
+
+    /// Limits a rotation relative to the rest pose, given in the joint's parent space,
+    /// to the clamped rotation channels. Used by the IK solvers.
+    pub fn clamp_rotation(&self, rotation: Quat) -> Quat {
+        let orientation = RotationOrder::XYZ.to_quat(Self::radians(self.orientation));
+        let mut angles = self.rotation_order.to_angles(orientation.inverse() * rotation * orientation);
+        for (angle, channel) in angles.as_mut().iter_mut().zip(&self.rotation) {
+            if channel.clamped {
+                *angle = angle.clamp(channel.min.to_radians(), channel.max.to_radians());
+            }
+        }
+        orientation * self.rotation_order.to_quat(angles) * orientation.inverse()
+    }
+
+    /// Change from the rest pose, rotating and scaling around the center point:
+    /// `T(translation) * T(center) * O * R * S * O^-1 * T(-center)`.
+    pub fn transform(&self) -> Mat4 {
+        Mat4::from_translation(self.translation() + self.center_point)
+            * <Mat4 as Transform>::from_scale_rotation_translation(
+                self.scale(),
+                Self::radians(self.orientation),
+                Self::radians(self.rotation()),
+                Vec3::ZERO,
+                self.rotation_order,
+            )
+            * Mat4::from_translation(-self.center_point)
+    }
+
+    /// Undoes this joint's own scale around `pivot`, for children that don't inherit scale.
+    fn scale_compensation(&self, pivot: Vec3) -> Mat4 {
+        let orientation = rotation_matrix(RotationOrder::XYZ, Self::radians(self.orientation));
+        let scale = self.scale();
+        if scale.cmpeq(Vec3::ZERO).any() {
+            return Mat4::IDENTITY;
+        }
+        Mat4::from_translation(pivot) * orientation * Mat4::from_scale(scale.recip()) * orientation.inverse() * Mat4::from_translation(-pivot)
+    }
+}
+
+/// Constraints of all bones of a rig, indexed like `RigV1::bones`.
+#[derive(Debug, Clone)]
+pub struct JointConstraints {
+    pub joints: Vec<JointConstraint>,
+    pub parents: Vec<Option<usize>>,
+}
+
+impl JointConstraints {
+    /// Takes the constraints the rig parser stored on each bone, bones without one are left free.
+    pub fn from_rig<S: Transform, T: Bone<S>>(rig: &RigV1<S, T>) -> Self {
+        let joints = rig.bones.iter().map(|bone| {
+            bone.get_constraint().cloned().unwrap_or_else(|| Self::free(bone))
+        }).collect();
+        JointConstraints { joints, parents: rig.bones.iter().map(|bone| bone.get_parent()).collect() }
+    }
+
+    /// Like `from_rig`, falling back to the node named like the bone for bones without a constraint.
+    pub fn from_dsf<S: Transform, T: Bone<S>>(file: &DSF, rig: &RigV1<S, T>) -> Self {
+        let joints = rig.bones.iter().map(|bone| {
+            bone.get_constraint().cloned()
+                .or_else(|| file.node_library.iter().find(|node| node.name == bone.get_name()).map(JointConstraint::from_node))
+                .unwrap_or_else(|| Self::free(bone))
+        }).collect();
+        JointConstraints { joints, parents: rig.bones.iter().map(|bone| bone.get_parent()).collect() }
+    }
+
+    fn free<S: Transform, T: Bone<S>>(bone: &T) -> JointConstraint {
+        JointConstraint::free(bone.get_name(), bone.global_transform().get_matrix().w_axis.truncate())
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut JointConstraint> {
+        self.joints.iter_mut().find(|joint| joint.name == name)
+    }
+
+    /// Copies the auto follow channels from the joints of the same name in `source`,
+    /// e.g. a conforming outfit following its figure.
+    pub fn follow(&mut self, source: &JointConstraints) {
+        for joint in &mut self.joints {
+            let Some(from) = source.joints.iter().find(|j| j.name == joint.name) else {
+                continue;
+            };
+            let pairs = joint.translation.iter_mut().zip(from.translation.iter())
+                .chain(joint.rotation.iter_mut().zip(from.rotation.iter()))
+                .chain(joint.scale.iter_mut().zip(from.scale.iter()))
+                .chain(std::iter::once((&mut joint.general_scale, &from.general_scale)));
+            for (channel, followed) in pairs {
+                if channel.auto_follow {
+                    channel.set(followed.value);
+                }
+            }
+        }
+    }
+
+    /// World space change from the rest pose of every joint, a vertex bound only to joint `i`
+    /// ends up at `transforms[i] * rest`. Joints that don't inherit scale keep their size
+    /// when their parent is scaled but still follow it.
+    pub fn world_transforms(&self) -> Vec<Mat4> {
+        let mut transforms: Vec<Option<Mat4>> = vec![None; self.joints.len()];
+        for i in 0..self.joints.len() {
+            self.resolve(i, &mut transforms);
+        }
+        transforms.into_iter().map(|t| t.unwrap_or(Mat4::IDENTITY)).collect()
+    }
+
+    fn resolve(&self, i: usize, transforms: &mut Vec<Option<Mat4>>) -> Mat4 {
+        if let Some(transform) = transforms[i] {
+            return transform;
+        }
+        let joint = &self.joints[i];
+        let parent = match self.parents[i] {
+            Some(p) if p != i => {
+                let parent = self.resolve(p, transforms);
+                if joint.inherits_scale {
+                    parent
+                } else {
+                    parent * self.joints[p].scale_compensation(joint.center_point)
+                }
+            }
+            _ => Mat4::IDENTITY,
+        };
+        let transform = parent * joint.transform();
+        transforms[i] = Some(transform);
+        transform
+    }
+
+    /// Writes the current pose into the local transforms of `rig`, which must be the rig the constraints were made for.
+    pub fn pose<S: Transform, T: Bone<S>>(&self, rig: &mut RigV1<S, T>) {
+        let globals = self.world_transforms().iter().zip(rig.bones.iter())
+            .map(|(world, bone)| *world * bone.global_transform().get_matrix())
+            .collect::<Vec<_>>();
+        rig.set_global_transforms(&globals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4Swizzles;
+    use crate::resource::skin::RigParser;
+    use crate::scene::daz::format::{BoneV1, DazRigParserV1};
+
+    fn node(json: &str) -> dsf::Node {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn clamps_on_set() {
+        let mut joint = JointConstraint::from_node(&node(r#"{
+            "id": "lForearmBend", "name": "lForearmBend", "type": "bone", "rotation_order": "XZY",
+            "rotation": [
+                { "id": "x", "value": 0, "min": -10, "max": 45, "clamped": true },
+                { "id": "y", "value": 0, "min": -5, "max": 5 },
+                { "id": "z", "value": 0, "min": -135, "max": 20, "clamped": true, "auto_follow": true }
+            ],
+            "general_scale": { "id": "general_scale", "value": 1, "min": 0.5, "max": 2, "clamped": true }
+        }"#));
+        assert_eq!(joint.set_rotation(Vec3::new(90.0, 30.0, -150.0)), Vec3::new(45.0, 30.0, -135.0));
+        assert_eq!(joint.rotation(), Vec3::new(45.0, 30.0, -135.0));
+        assert_eq!(joint.set_general_scale(4.0), 2.0);
+        assert_eq!(joint.scale(), Vec3::splat(2.0));
+        assert!(joint.rotation[2].auto_follow);
+        assert!(!joint.rotation[1].clamped);
+    }
+
+    #[test]
+    fn matches_reference_matrices() {
+        // Rx * Ry * Rz and friends for x = 0.3, y = -0.7, z = 1.1, worked out by hand
+        let angles = Vec3::new(0.3, -0.7, 1.1);
+        let references = [
+            (RotationOrder::XYZ, [0.346929, 0.765048, 0.542533, -0.681633, 0.603004, -0.414442, -0.644218, -0.226026, 0.730682]),
+            (RotationOrder::YZX, [0.346929, 0.891207, 0.292215, -0.841568, 0.433337, -0.322462, -0.414008, -0.134047, 0.900349]),
+            (RotationOrder::ZXY, [0.516597, 0.595278, 0.615445, -0.851403, 0.433337, 0.295520, -0.090778, -0.676656, 0.730682]),
+        ];
+        for (order, columns) in references {
+            let reference = Mat4::from_mat3(glam::Mat3::from_cols_array(&columns));
+            assert!(rotation_matrix(order, angles).abs_diff_eq(reference, 1e-5), "{:?}", order);
+            assert!(Mat4::from_quat(order.to_quat(angles)).abs_diff_eq(reference, 1e-5), "{:?}", order);
+            assert!(order.to_angles(order.to_quat(angles)).abs_diff_eq(angles, 1e-4));
+        }
+        assert_eq!(<Mat4 as Transform>::from_scale_rotation_translation(Vec3::ONE, Vec3::ZERO, Vec3::ZERO, Vec3::Y, RotationOrder::YZX), Mat4::from_translation(Vec3::Y));
+
+        // YZX: y first around the parent, then z, then x. 90 degrees around x then y, pivoting around y = 50
+        let mut joint = JointConstraint::free("shin", Vec3::new(0.0, 50.0, 0.0));
+        joint.rotation_order = RotationOrder::YZX;
+        joint.set_rotation(Vec3::new(90.0, 90.0, 0.0));
+        let reference = Mat4::from_cols(
+            glam::Vec4::new(0.0, 0.0, -1.0, 0.0),
+            glam::Vec4::new(1.0, 0.0, 0.0, 0.0),
+            glam::Vec4::new(0.0, -1.0, 0.0, 0.0),
+            glam::Vec4::new(-50.0, 50.0, 0.0, 1.0),
+        );
+        assert!(joint.transform().abs_diff_eq(reference, 1e-4));
+        // the joint pivots around its center point
+        assert!(joint.transform().transform_point3(Vec3::new(0.0, 50.0, 0.0)).abs_diff_eq(Vec3::new(0.0, 50.0, 0.0), 1e-5));
+        assert!(joint.transform().transform_point3(Vec3::new(0.0, 50.0, 1.0)).abs_diff_eq(Vec3::new(0.0, 49.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn parser_keeps_constraints() {
+        let xyz = |x: f32, y: f32, z: f32| serde_json::json!([{ "id": "x", "value": x }, { "id": "y", "value": y }, { "id": "z", "value": z }]);
+        let bone = |name: &str, center: serde_json::Value| serde_json::json!({
+            "id": name, "name": name, "type": "bone", "rotation_order": "YZX",
+            "center_point": center, "end_point": xyz(0.0, 0.0, 0.0), "orientation": xyz(0.0, 0.0, 0.0),
+            "rotation": xyz(0.0, 0.0, 0.0), "translation": xyz(0.0, 0.0, 0.0), "scale": xyz(1.0, 1.0, 1.0),
+        });
+        let mut thigh = bone("lThigh", xyz(10.0, 95.0, 0.0));
+        thigh["parent"] = "#hip".into();
+        thigh["inherits_scale"] = false.into();
+        thigh["rotation"][0] = serde_json::json!({ "id": "x", "value": 0, "min": -115, "max": 35, "clamped": true, "auto_follow": true });
+        thigh["general_scale"] = serde_json::json!({ "id": "general_scale", "value": 1.2 });
+        let dsf: DSF = serde_json::from_value(serde_json::json!({
+            "file_version": "0.6.0.0",
+            "asset_info": { "id": "/rig.dsf" },
+            "node_library": [bone("hip", xyz(0.0, 100.0, 0.0)), thigh],
+            "modifier_library": [{ "id": "skin", "name": "skin", "skin": {
+                "node": "#hip", "geometry": "#geo", "vertex_count": 1,
+                "joints": [{ "id": "hip", "node": "#hip" }, { "id": "lThigh", "node": "#lThigh" }]
+            } }]
+        })).unwrap();
+        let rig = DazRigParserV1::<RigV1<Mat4, BoneV1<Mat4>>, Mat4, BoneV1<Mat4>>::parse(&dsf).remove(0).unwrap();
+        let constraints = JointConstraints::from_rig(&rig);
+        let thigh = &constraints.joints[constraints.index("lThigh").unwrap()];
+        assert!(!thigh.inherits_scale);
+        assert!(thigh.rotation[0].clamped && thigh.rotation[0].auto_follow);
+        assert_eq!((thigh.rotation[0].min, thigh.rotation[0].max), (-115.0, 35.0));
+        assert_eq!(thigh.general_scale.value, 1.2);
+        assert_eq!(thigh.center_point, Vec3::new(10.0, 95.0, 0.0));
+        assert!(constraints.joints[constraints.index("hip").unwrap()].inherits_scale);
+    }
+
+    #[test]
+    fn scale_inheritance() {
+        let mut thigh = JointConstraint::free("thigh", Vec3::new(0.0, 100.0, 0.0));
+        thigh.set_scale(Vec3::new(1.0, 2.0, 1.0));
+        let mut shin = JointConstraint::free("shin", Vec3::new(0.0, 50.0, 0.0));
+        shin.inherits_scale = false;
+        let mut foot = JointConstraint::free("foot", Vec3::new(0.0, 10.0, 0.0));
+        foot.inherits_scale = true;
+        let constraints = JointConstraints { joints: vec![thigh, shin, foot], parents: vec![None, Some(0), Some(1)] };
+
+        let world = constraints.world_transforms();
+        // the thigh stretches downwards, the shin moves with it but keeps its length
+        let knee = world[1].transform_point3(Vec3::new(0.0, 50.0, 0.0));
+        assert!(knee.abs_diff_eq(Vec3::new(0.0, 0.0, 0.0), 1e-4));
+        let ankle = world[1].transform_point3(Vec3::new(0.0, 10.0, 0.0));
+        assert!(ankle.abs_diff_eq(Vec3::new(0.0, -40.0, 0.0), 1e-4));
+        assert!(world[2].w_axis.xyz().abs_diff_eq(world[1].w_axis.xyz(), 1e-4));
+    }
+}
@@ -1,12 +1,18 @@
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use serde_json;
+use serde_json::value::RawValue;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 
-use super::dsf::DSF;
-use super::duf::DUF;
+use super::dsf::{self, DSF};
+use super::duf::{self, Scene, DUF};
 
 #[derive(Debug)]
 pub enum DazComponentFile {
@@ -14,20 +20,253 @@ pub enum DazComponentFile {
     Duf(DUF),
 }
 
-pub fn read_from_dsf(path: &Path) -> Result<DSF, Box<dyn Error>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let data = serde_json::from_reader(reader)?;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-    Ok(data)
+#[derive(Debug)]
+pub enum DazLoadError {
+    Io { file: PathBuf, source: std::io::Error },
+    Json { file: PathBuf, json_path: String, source: serde_json::Error },
 }
 
-pub fn read_from_duf(path: &Path) -> Result<DUF, Box<dyn Error>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let data = serde_json::from_reader(reader)?;
+impl fmt::Display for DazLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DazLoadError::Io { file, source } => write!(f, "failed to read {:?}: {}", file, source),
+            DazLoadError::Json { file, json_path, source } => {
+                write!(f, "failed to parse {:?} at `{}`: {}", file, json_path, source)
+            }
+        }
+    }
+}
 
-    Ok(data)
+impl Error for DazLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DazLoadError::Io { source, .. } => Some(source),
+            DazLoadError::Json { source, .. } => Some(source),
+        }
+    }
+}
+
+// DAZ Studio writes most of its content gzip compressed, so sniff the magic bytes
+// instead of trusting the extension.
+fn open_daz_file(path: &Path) -> Result<Box<dyn Read>, DazLoadError> {
+    let io_error = |source| DazLoadError::Io { file: path.to_path_buf(), source };
+    let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+    let is_gzip = reader.fill_buf().map_err(io_error)?.starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        Ok(Box::new(BufReader::new(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+pub(crate) fn read_daz_json<T: DeserializeOwned>(path: &Path) -> Result<T, DazLoadError> {
+    let reader = open_daz_file(path)?;
+    let deserializer = &mut serde_json::Deserializer::from_reader(reader);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| DazLoadError::Json {
+        file: path.to_path_buf(),
+        json_path: e.path().to_string(),
+        source: e.into_inner(),
+    })
+}
+
+/// Something in a DAZ file that the loader tolerated instead of failing on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DazWarning {
+    /// A key the schema does not know about. The value was ignored.
+    UnknownKey { json_path: String },
+    /// A library entry that could not be read, e.g. because a required key was missing.
+    /// It was left out of the result.
+    Skipped { json_path: String, reason: String },
+}
+
+impl fmt::Display for DazWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DazWarning::UnknownKey { json_path } => write!(f, "ignored unknown key `{}`", json_path),
+            DazWarning::Skipped { json_path, reason } => write!(f, "skipped `{}`: {}", json_path, reason),
+        }
+    }
+}
+
+// Renders a serde_ignored path the same way serde_path_to_error does (`nodes[1].id`).
+fn ignored_path(path: &serde_ignored::Path, out: &mut String) {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => {}
+        Path::Seq { parent, index } => {
+            ignored_path(parent, out);
+            out.push_str(&format!("[{}]", index));
+        }
+        Path::Map { parent, key } => {
+            ignored_path(parent, out);
+            if !out.is_empty() {
+                out.push('.');
+            }
+            out.push_str(key);
+        }
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => {
+            ignored_path(parent, out)
+        }
+    }
+}
+
+fn join_path(base: &str, inner: &str) -> String {
+    match (base.is_empty(), inner.is_empty() || inner == ".") {
+        (true, _) => inner.to_string(),
+        (false, true) => base.to_string(),
+        (false, false) if inner.starts_with('[') => format!("{}{}", base, inner),
+        (false, false) => format!("{}.{}", base, inner),
+    }
+}
+
+// Deserializes `json` straight from the text, recording every key the schema skipped over.
+fn parse_str<'de, T: Deserialize<'de>>(json: &'de str, base: &str, warnings: &mut Vec<DazWarning>) -> Result<T, (String, serde_json::Error)> {
+    let mut unknown = Vec::new();
+    let mut record = |path: serde_ignored::Path| {
+        let mut inner = String::new();
+        ignored_path(&path, &mut inner);
+        unknown.push(DazWarning::UnknownKey { json_path: join_path(base, &inner) });
+    };
+    let deserializer = &mut serde_json::Deserializer::from_str(json);
+    let result = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(deserializer, &mut record));
+    warnings.extend(unknown);
+    result.map_err(|e| (join_path(base, &e.path().to_string()), e.into_inner()))
+}
+
+// Reads a library array entry by entry so one malformed entry doesn't take the whole file with it.
+// The entries are kept as raw text until then, the file is never parsed into a `Value` tree.
+fn parse_library<T: DeserializeOwned>(library: Option<&RawValue>, path: &str, warnings: &mut Vec<DazWarning>) -> Option<Vec<T>> {
+    let library = library?;
+    match serde_json::from_str::<Option<Vec<&RawValue>>>(library.get()) {
+        Ok(entries) => entries.map(|entries| entries.iter().enumerate().filter_map(|(i, entry)| {
+            match parse_str(entry.get(), &format!("{}[{}]", path, i), warnings) {
+                Ok(parsed) => Some(parsed),
+                Err((json_path, source)) => {
+                    warnings.push(DazWarning::Skipped { json_path, reason: source.to_string() });
+                    None
+                }
+            }
+        }).collect()),
+        Err(_) => {
+            warnings.push(DazWarning::Skipped { json_path: path.to_string(), reason: "expected an array".to_string() });
+            None
+        }
+    }
+}
+
+fn read_daz_text(path: &Path) -> Result<String, DazLoadError> {
+    let mut text = String::new();
+    open_daz_file(path)?
+        .read_to_string(&mut text)
+        .map_err(|source| DazLoadError::Io { file: path.to_path_buf(), source })?;
+    Ok(text)
+}
+
+fn parse_root<'de, T: Deserialize<'de>>(path: &Path, text: &'de str, warnings: &mut Vec<DazWarning>) -> Result<T, DazLoadError> {
+    parse_str(text, "", warnings).map_err(|(json_path, source)| DazLoadError::Json {
+        file: path.to_path_buf(),
+        json_path,
+        source,
+    })
+}
+
+// `DSF` with its libraries left as raw text.
+#[derive(Deserialize)]
+struct LenientDsf<'a> {
+    file_version: String,
+    asset_info: dsf::AssetInfo,
+    #[serde(borrow)]
+    geometry_library: Option<&'a RawValue>,
+    #[serde(borrow)]
+    node_library: Option<&'a RawValue>,
+    #[serde(borrow)]
+    modifier_library: Option<&'a RawValue>,
+}
+
+// `DUF` with its libraries and scene entries left as raw text.
+#[derive(Deserialize)]
+struct LenientDuf<'a> {
+    file_version: String,
+    asset_info: duf::AssetInfo,
+    #[serde(borrow)]
+    image_library: Option<&'a RawValue>,
+    #[serde(borrow)]
+    material_library: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    scene: LenientScene<'a>,
+}
+
+#[derive(Default, Deserialize)]
+struct LenientScene<'a> {
+    #[serde(borrow)]
+    nodes: Option<&'a RawValue>,
+    uvs: Option<Vec<Value>>,
+    #[serde(borrow)]
+    materials: Option<&'a RawValue>,
+    modifiers: Option<Vec<Value>>,
+    animations: Option<Vec<Value>>,
+    current_camera: Option<String>,
+    extra: Option<Vec<Value>>,
+}
+
+/// Reads a DSF file, skipping library entries that don't match the schema.
+/// Only a broken file header (`file_version`, `asset_info`) is an error.
+pub fn read_dsf_lenient(path: &Path) -> Result<(DSF, Vec<DazWarning>), DazLoadError> {
+    let text = read_daz_text(path)?;
+    let mut warnings = Vec::new();
+    let root: LenientDsf = parse_root(path, &text, &mut warnings)?;
+    let dsf = DSF {
+        file_version: root.file_version,
+        asset_info: root.asset_info,
+        geometry_library: parse_library(root.geometry_library, "geometry_library", &mut warnings).unwrap_or_default(),
+        node_library: parse_library(root.node_library, "node_library", &mut warnings).unwrap_or_default(),
+        modifier_library: parse_library(root.modifier_library, "modifier_library", &mut warnings).unwrap_or_default(),
+    };
+    Ok((dsf, warnings))
+}
+
+/// Reads a DUF file, skipping library and scene entries that don't match the schema.
+pub fn read_duf_lenient(path: &Path) -> Result<(DUF, Vec<DazWarning>), DazLoadError> {
+    let text = read_daz_text(path)?;
+    let mut warnings = Vec::new();
+    let root: LenientDuf = parse_root(path, &text, &mut warnings)?;
+    let scene = root.scene;
+    let duf = DUF {
+        file_version: root.file_version,
+        asset_info: root.asset_info,
+        image_library: parse_library(root.image_library, "image_library", &mut warnings),
+        material_library: parse_library(root.material_library, "material_library", &mut warnings),
+        scene: Scene {
+            nodes: parse_library(scene.nodes, "scene.nodes", &mut warnings),
+            uvs: scene.uvs,
+            materials: parse_library(scene.materials, "scene.materials", &mut warnings),
+            modifiers: scene.modifiers,
+            animations: scene.animations,
+            current_camera: scene.current_camera,
+            extra: scene.extra,
+        },
+    };
+    Ok((duf, warnings))
+}
+
+fn report_warnings(path: &Path, warnings: &[DazWarning]) {
+    for warning in warnings {
+        log::warn!("{:?}: {}", path, warning);
+    }
+}
+
+pub fn read_from_dsf(path: &Path) -> Result<DSF, Box<dyn Error>> {
+    let (dsf, warnings) = read_dsf_lenient(path)?;
+    report_warnings(path, &warnings);
+    Ok(dsf)
+}
+
+pub fn read_from_duf(path: &Path) -> Result<DUF, Box<dyn Error>> {
+    let (duf, warnings) = read_duf_lenient(path)?;
+    report_warnings(path, &warnings);
+    Ok(duf)
 }
 
 fn read_daz_component_file<'a>(path: &Path) -> Result<DazComponentFile, Box<dyn Error>>{
@@ -99,3 +338,67 @@ pub fn build_daz(from: &PathBuf) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[derive(serde::Deserialize)]
+    struct Probe {
+        file_version: String,
+        nodes: Vec<ProbeNode>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ProbeNode {
+        #[allow(dead_code)]
+        id: String,
+    }
+
+    #[test]
+    fn reads_gzip_and_reports_json_path() {
+        let dir = std::env::temp_dir();
+        let json = r#"{ "file_version": "0.6.0.0", "nodes": [{ "id": "hip" }] }"#;
+        let path = dir.join("bolt_gzip_probe.dsf");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(json.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        let probe: Probe = read_daz_json(&path).unwrap();
+        assert_eq!(probe.file_version, "0.6.0.0");
+        assert_eq!(probe.nodes.len(), 1);
+
+        let broken = dir.join("bolt_broken_probe.dsf");
+        std::fs::write(&broken, r#"{ "file_version": "0.6.0.0", "nodes": [{ "id": "hip" }, { "id": 3 }] }"#).unwrap();
+        let err = read_daz_json::<Probe>(&broken).err().unwrap();
+        let message = err.to_string();
+        assert!(message.contains("bolt_broken_probe.dsf"));
+        assert!(message.contains("nodes[1].id"));
+    }
+
+    #[test]
+    fn lenient_dsf_skips_bad_entries() {
+        let path = std::env::temp_dir().join("bolt_lenient_probe.dsf");
+        std::fs::write(&path, r#"{
+            "file_version": "0.6.0.0",
+            "asset_info": { "id": "/probe.dsf" },
+            "uv_set_library": [],
+            "node_library": [
+                { "id": "hip", "name": "hip", "type": "bone", "shoe_size": 42 },
+                { "id": "nameless" }
+            ],
+            "modifier_library": [
+                { "id": "smile", "channel": { "id": "value", "value": 0.5 } }
+            ]
+        }"#).unwrap();
+        let (dsf, warnings) = read_dsf_lenient(&path).unwrap();
+        assert_eq!(dsf.node_library.len(), 1);
+        assert_eq!(dsf.node_library[0].scale[1].value, 1.0);
+        assert_eq!(dsf.modifier_library.len(), 1);
+        assert!(dsf.modifier_library[0].skin.is_none());
+        assert!(warnings.contains(&DazWarning::UnknownKey { json_path: "uv_set_library".to_string() }));
+        assert!(warnings.contains(&DazWarning::UnknownKey { json_path: "node_library[0].shoe_size".to_string() }));
+        assert!(warnings.iter().any(|w| matches!(w, DazWarning::Skipped { json_path, .. } if json_path == "node_library[1]")));
+        assert_eq!(dsf.asset_info.revision, "1.0");
+    }
+}
@@ -1,6 +1,6 @@
 use std::{path::PathBuf, collections::HashSet};
 
-pub use self::load::{read_from_dsf};
+pub use self::load::{read_from_dsf, read_dsf_lenient, DazWarning};
 pub use super::mesh::VulkanMesh;
 pub use self::dsf::DSF;
 //pub use self::format::Convertable;
@@ -9,9 +9,14 @@ pub mod load;
 pub mod dsf;
 pub mod duf;
 pub mod format;
+pub mod geometry;
+pub mod joint;
+pub mod pose;
 pub mod skin;
 
 pub use skin::*;
+pub use joint::*;
+pub use pose::*;
 
 use urlencoding::decode;
 
@@ -29,8 +34,11 @@ pub fn unique_files(ref_files: Vec<String>) -> HashSet<String> {
     unique_files
 }
 
+/// Reads a figure, merging the geografts stored in the same file into the geometry they belong to.
 pub fn import(filepath: &PathBuf) -> Result<DSF, Box<dyn std::error::Error>> {
-    read_from_dsf(filepath)
+    let mut dsf = read_from_dsf(filepath)?;
+    dsf.merge_own_grafts()?;
+    Ok(dsf)
     // let mesh = match res {
     //     Ok(mesh) => mesh,
     //     Err(e) => return Err(e),
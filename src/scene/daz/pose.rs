@@ -0,0 +1,189 @@
+use std::error::Error;
+use std::path::Path;
+
+use glam::{Mat4, Vec3};
+use serde_json::Value;
+
+use crate::resource::skin::{Bone, Pose, Transform};
+use super::duf::DUF;
+use super::format::RigV1;
+use super::joint::{JointConstraint, JointConstraints};
+use super::load::read_from_duf;
+
+/// What a pose preset channel drives, taken from its url.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoseTarget {
+    /// `name://@selection:?translation/x/value`, the selected figure itself.
+    Figure,
+    /// `name://@selection/lShldrBend:?rotation/z/value`
+    Node(String),
+    /// `name://@selection#CTRLSmile:?value/value`, morphs and other modifiers.
+    Modifier(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseChannel {
+    pub target: PoseTarget,
+    /// Property path without the trailing `/value`, e.g. `rotation/z` or `scale/general`.
+    pub property: String,
+    pub value: f32,
+}
+
+impl PoseChannel {
+    pub fn parse(url: &str, value: f32) -> Option<Self> {
+        let decoded = urlencoding::decode(url).ok()?;
+        let rest = decoded.strip_prefix("name://@selection")?;
+        let (target, property) = rest.split_once(":?")?;
+        let target = match target.chars().next() {
+            None => PoseTarget::Figure,
+            Some('/') => PoseTarget::Node(target[1..].to_string()),
+            Some('#') => PoseTarget::Modifier(target[1..].to_string()),
+            _ => return None,
+        };
+        let property = property.strip_suffix("/value").unwrap_or(property).to_string();
+        Some(PoseChannel { target, property, value })
+    }
+
+    /// Sets the channel through the clamping setters, false for properties a joint doesn't have.
+    fn apply(&self, joint: &mut JointConstraint) -> bool {
+        let Some((property, axis)) = self.property.split_once('/') else {
+            return false;
+        };
+        if property == "scale" && axis == "general" {
+            joint.set_general_scale(self.value);
+            return true;
+        }
+        let Some(axis) = ["x", "y", "z"].iter().position(|a| *a == axis) else {
+            return false;
+        };
+        let (mut values, set): (Vec3, fn(&mut JointConstraint, Vec3) -> Vec3) = match property {
+            "translation" => (joint.translation(), JointConstraint::set_translation),
+            "rotation" => (joint.rotation(), JointConstraint::set_rotation),
+            "scale" => (Vec3::new(joint.scale[0].value, joint.scale[1].value, joint.scale[2].value), JointConstraint::set_scale),
+            _ => return false,
+        };
+        values[axis] = self.value;
+        set(joint, values);
+        true
+    }
+}
+
+/// Channel values of a DAZ pose preset, the `scene.animations` of a `.duf` file.
+#[derive(Debug, Clone, Default)]
+pub struct DazPose {
+    pub channels: Vec<PoseChannel>,
+}
+
+/// Value at the first key, keys are `[time, value]` or `[time, value, [interpolation]]`.
+fn first_key(animation: &Value) -> Option<f32> {
+    let keys = animation.get("keys")?.as_array()?;
+    keys.first()?.get(1)?.as_f64().map(|v| v as f32)
+}
+
+impl DazPose {
+    /// Channels with a url that isn't understood are skipped.
+    pub fn from_duf(duf: &DUF) -> Self {
+        let channels = duf.scene.animations.iter().flatten().filter_map(|animation| {
+            let url = animation.get("url")?.as_str()?;
+            PoseChannel::parse(url, first_key(animation)?)
+        }).collect();
+        DazPose { channels }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_duf(&read_from_duf(path)?))
+    }
+
+    /// Sets the node channels on the joints of the same name, returns the channels that weren't applied,
+    /// e.g. morphs or bones the figure doesn't have. Limits of the figure are respected.
+    pub fn apply(&self, constraints: &mut JointConstraints) -> Vec<&PoseChannel> {
+        self.channels.iter().filter(|channel| {
+            let applied = match &channel.target {
+                PoseTarget::Node(name) => constraints.get_mut(name).is_some_and(|joint| channel.apply(joint)),
+                _ => false,
+            };
+            !applied
+        }).collect()
+    }
+
+    /// Transform of the figure node, positioning the whole rig.
+    pub fn figure_transform(&self) -> Mat4 {
+        let mut figure = JointConstraint::free("figure", Vec3::ZERO);
+        for channel in self.channels.iter().filter(|c| c.target == PoseTarget::Figure) {
+            channel.apply(&mut figure);
+        }
+        figure.transform()
+    }
+
+    /// Resets `constraints` to the preset, then poses `rig` and sets its root transform to the figure's.
+    /// Starting from the rest pose makes the result independent of any previous pose.
+    pub fn pose<S: Transform, T: Bone<S>>(&self, constraints: &mut JointConstraints, rig: &mut RigV1<S, T>) -> Pose {
+        for joint in &mut constraints.joints {
+            joint.set_translation(Vec3::ZERO);
+            joint.set_rotation(Vec3::ZERO);
+            joint.set_scale(Vec3::ONE);
+            joint.set_general_scale(1.0);
+        }
+        self.apply(constraints);
+        constraints.pose(rig);
+        rig.root_transform = S::from_mat4(self.figure_transform());
+        Pose::capture(rig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::daz::format::test_rig;
+    use glam::Vec4Swizzles;
+
+    const PRESET: &str = r#"{
+        "file_version": "0.6.0.0",
+        "asset_info": { "id": "/People/Poses/wave.duf", "type": "preset_pose" },
+        "scene": {
+            "animations": [
+                { "url": "name://@selection:?translation/y/value", "keys": [[0, 5.0]] },
+                { "url": "name://@selection/lShldrBend:?rotation/z/value", "keys": [[0, -90.0, ["TCB", 0, 0, 0]]] },
+                { "url": "name://@selection/lShldrBend:?rotation/x/value", "keys": [[0, 200.0]] },
+                { "url": "name://@selection/lForearm%20Bend:?rotation/y/value", "keys": [[0, 10.0]] },
+                { "url": "name://@selection#CTRLSmile:?value/value", "keys": [[0, 1.0]] }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn applies_preset_to_rig() {
+        let duf: DUF = serde_json::from_str(PRESET).unwrap();
+        let preset = DazPose::from_duf(&duf);
+        assert_eq!(preset.channels.len(), 5);
+        assert_eq!(preset.channels[3].target, PoseTarget::Node("lForearm Bend".to_string()));
+        assert_eq!(preset.channels[1].property, "rotation/z");
+
+        // a shoulder at (10, 100, 0) with an arm along +x
+        let mut rig = test_rig(&[("lShldrBend", None, Mat4::from_translation(Vec3::new(10.0, 100.0, 0.0)))]);
+        let mut constraints = JointConstraints {
+            joints: vec![JointConstraint::free("lShldrBend", Vec3::new(10.0, 100.0, 0.0))],
+            parents: vec![None],
+        };
+        constraints.joints[0].rotation[0].clamped = true;
+        constraints.joints[0].rotation[0].min = -30.0;
+        constraints.joints[0].rotation[0].max = 30.0;
+
+        let unapplied = preset.apply(&mut constraints);
+        assert_eq!(unapplied.len(), 3);
+        assert_eq!(constraints.joints[0].rotation(), Vec3::new(30.0, 0.0, -90.0));
+
+        // the same preset always gives the same pose
+        let first = preset.pose(&mut constraints, &mut rig);
+        constraints.joints[0].set_rotation(Vec3::new(-20.0, 45.0, 0.0));
+        assert_eq!(preset.pose(&mut constraints, &mut rig), first);
+        assert_eq!(rig.root_transform.w_axis.xyz(), Vec3::new(0.0, 5.0, 0.0));
+
+        // -90 around z points the arm down, the clamped 30 around x swings it back, the shoulder stays in place
+        let shoulder = rig.local_to_global(0);
+        assert!(shoulder.w_axis.xyz().abs_diff_eq(Vec3::new(10.0, 100.0, 0.0), 1e-4));
+        let hand = shoulder.transform_point3(Vec3::new(30.0, 0.0, 0.0));
+        let (sin, cos) = 30f32.to_radians().sin_cos();
+        assert!(hand.abs_diff_eq(Vec3::new(10.0, 100.0 - 30.0 * cos, -30.0 * sin), 1e-3));
+    }
+}
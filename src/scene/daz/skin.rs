@@ -1,36 +1,20 @@
 
 
 use std::collections::HashMap;
-use glam::{Vec3, Mat4};
+use glam::Mat4;
 
 use crate::resource::skin::Skin;
 use crate::resource::skin::SkinJoint;
 use super::dsf::DSF;
 use super::dsf::Joint as DsfJoint;
 use super::dsf::Node;
+use super::joint::JointConstraint;
 
 impl Node {
 
+    /// Change of the node from its rest pose, see `JointConstraint::transform`.
     pub fn transform_mat4(&self) -> Mat4 {
-        let translation = Vec3::new(self.translation[0].value, self.translation[1].value, self.translation[2].value);
-        let rotation = Vec3::new(self.rotation[0].value, self.rotation[1].value, self.rotation[2].value);
-        let scale = Vec3::new(self.scale[0].value, self.scale[1].value, self.scale[2].value);
-
-        let translation_matrix = Mat4::from_translation(translation);
-        let scale_matrix = Mat4::from_scale(scale);
-        
-        // Create rotation matrices
-        let x_rot = Mat4::from_rotation_x(rotation.x);
-        let y_rot = Mat4::from_rotation_y(rotation.y);
-        let z_rot = Mat4::from_rotation_z(rotation.z);
-
-        // TODO: Multiply rotation matrices in correct order defined by "rotation_order"
-        let rotation_matrix = x_rot * y_rot * z_rot;
-
-        // compose translation, rotation and scaling matrices into transform matrix
-        let transformation_matrix = translation_matrix * rotation_matrix * scale_matrix;
-        
-        transformation_matrix
+        JointConstraint::from_node(self).transform()
     }
 }
 
@@ -44,16 +28,15 @@ impl DSF {
             .collect::<HashMap<String, Node>>();
 
         // println!("node_map: {:?}", node_map);
-        self.modifier_library.iter().map(|m| {
+        self.modifier_library.iter().filter_map(|m| m.skin.as_ref().map(|skin| (m, skin))).map(|(m, skin)| {
             let mut joints = Vec::new();
             let mut transforms = Vec::new();
             let mut bone_transforms = Vec::new();
             let mut inverse_bind_matrices = Vec::new();
-            let skin = &m.skin;
             let joint_id_map: HashMap<String, u32> = skin.joints
                 .iter()
                 .enumerate()
-                .map(|(i, DsfJoint { id, node, node_weights })| {
+                .map(|(i, DsfJoint { id, node, node_weights, .. })| {
                     // The joint transform is a Matrix4x4 which is not provided in DsfJoint
                     // You need to find a way to calculate or fetch it. Here it's represented by a placeholder identity matrix for the sake of the example.
                     let tr = node_map.get(id);
@@ -88,6 +71,7 @@ impl DSF {
                 global_bone_transforms: bone_transforms,
                 joints,
                 joint_id_map,
+                joint_bones: Vec::new(),
                 inverse_bind_matrices,
             }   
         }).collect()
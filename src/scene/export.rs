@@ -0,0 +1,637 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use serde_json::{json, Value};
+
+use crate::resource::skin::{Bone, Rig, Skin};
+use super::daz::format::{BoneV1, RigV1};
+use super::{Bounds, BufferPart, Camera, MaterialInfo, Mesh, PrimitiveSection, Projection, Scene};
+
+// glTF constants we need for serialization
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+const TRIANGLES: u32 = 4;
+const LINEAR: u32 = 9729;
+const LINEAR_MIPMAP_LINEAR: u32 = 9987;
+const REPEAT: u32 = 10497;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GltfFormat {
+    /// JSON document with a sibling `.bin` buffer.
+    Gltf,
+    /// Single binary container.
+    Glb,
+}
+
+impl GltfFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gltf" => Some(GltfFormat::Gltf),
+            "glb" => Some(GltfFormat::Glb),
+            _ => None,
+        }
+    }
+}
+
+/// A glTF json document with its binary buffer, see `GltfExport::build`.
+pub struct GltfDocument {
+    pub json: Value,
+    pub bin: Vec<u8>,
+    /// Textures from outside the output folder: source path and destination relative to the folder.
+    pub texture_copies: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Collects the cpu side of a scene and serializes it to glTF 2.0.
+///
+/// Skins are matched to meshes and rigs by index, the same way `load_daz` pairs them.
+#[derive(Default)]
+pub struct GltfExport<'a> {
+    meshes: &'a [Mesh],
+    materials: &'a [MaterialInfo],
+    texture_paths: &'a [PathBuf],
+    skins: &'a [Skin],
+    rigs: &'a [RigV1<Mat4, BoneV1<Mat4>>],
+    camera: Option<&'a Camera>,
+}
+
+impl<'a> GltfExport<'a> {
+    pub fn from_scene(scene: &'a Scene) -> Self {
+        GltfExport::default()
+            .meshes(&scene.meshes)
+            .materials(&scene.materials)
+            .texture_paths(&scene.texture_paths)
+            .skins(&scene.skins)
+            .rigs(&scene.rigs)
+            .camera(scene.camera.as_ref())
+    }
+    pub fn meshes(mut self, meshes: &'a [Mesh]) -> Self {
+        self.meshes = meshes;
+        self
+    }
+    pub fn materials(mut self, materials: &'a [MaterialInfo]) -> Self {
+        self.materials = materials;
+        self
+    }
+    pub fn texture_paths(mut self, texture_paths: &'a [PathBuf]) -> Self {
+        self.texture_paths = texture_paths;
+        self
+    }
+    pub fn skins(mut self, skins: &'a [Skin]) -> Self {
+        self.skins = skins;
+        self
+    }
+    pub fn rigs(mut self, rigs: &'a [RigV1<Mat4, BoneV1<Mat4>>]) -> Self {
+        self.rigs = rigs;
+        self
+    }
+    pub fn camera(mut self, camera: Option<&'a Camera>) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    /// Writes the scene to `path`. The container format is picked from the file extension.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let format = match GltfFormat::from_path(path) {
+            Some(format) => format,
+            None => return Err(format!("Unsupported export format: {:?}", path).into()),
+        };
+        let base_dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        let GltfDocument { json: mut document, bin, texture_copies } = self.build(&base_dir);
+        for (source, destination) in &texture_copies {
+            fs::copy(source, base_dir.join(destination))?;
+        }
+
+        match format {
+            GltfFormat::Gltf => {
+                let bin_name = format!("{}.bin", path.file_stem().unwrap_or_default().to_string_lossy());
+                if !bin.is_empty() {
+                    document["buffers"][0]["uri"] = json!(bin_name);
+                    fs::write(base_dir.join(&bin_name), &bin)?;
+                }
+                fs::write(path, serde_json::to_vec_pretty(&document)?)?;
+            }
+            GltfFormat::Glb => {
+                let json = serde_json::to_vec(&document)?;
+                let glb = gltf::binary::Glb {
+                    header: gltf::binary::Header {
+                        magic: *b"glTF",
+                        version: 2,
+                        length: 0, // computed by the writer
+                    },
+                    json: Cow::Owned(json),
+                    bin: if bin.is_empty() { None } else { Some(Cow::Owned(bin)) },
+                };
+                glb.to_writer(fs::File::create(path)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the glTF json document and its binary buffer. Texture uris are relative to
+    /// `base_dir`, textures outside of it are to be copied there, see `GltfDocument::texture_copies`.
+    pub fn build(&self, base_dir: &Path) -> GltfDocument {
+        let mut writer = GltfWriter::default();
+
+        let mut scene_nodes = Vec::new();
+        for (mesh_index, mesh) in self.meshes.iter().enumerate() {
+            let skin = self.skins.get(mesh_index);
+            let rig = self.rigs.get(mesh_index);
+            let skin_index = match (skin, rig) {
+                (Some(skin), Some(rig)) => writer.push_skin(skin, rig, &mut scene_nodes),
+                _ => None,
+            };
+            let gltf_mesh = writer.push_mesh(mesh, skin.filter(|_| skin_index.is_some()));
+            let mut node = json!({
+                "name": mesh.name,
+                "mesh": gltf_mesh,
+                "matrix": mesh.transform.to_cols_array(),
+            });
+            if let Some(skin_index) = skin_index {
+                node["skin"] = json!(skin_index);
+            }
+            scene_nodes.push(writer.push_node(node));
+        }
+
+        for material in self.materials {
+            writer.push_material(material);
+        }
+
+        for path in self.texture_paths {
+            writer.push_texture(path, base_dir);
+        }
+
+        if let Some(camera) = self.camera {
+            let camera_index = writer.cameras.len();
+            writer.cameras.push(match camera.projection() {
+                Projection::Perspective => json!({
+                    "type": "perspective",
+                    "perspective": {
+                        "yfov": camera.vfov().to_radians(),
+                        "znear": camera.z_near(),
+                        "zfar": camera.z_far(),
+                    }
+                }),
+                Projection::Orthographic { ymag } => json!({
+                    "type": "orthographic",
+                    "orthographic": {
+                        "xmag": ymag * camera.window_size().x / camera.window_size().y,
+                        "ymag": ymag,
+                        "znear": camera.z_near(),
+                        "zfar": camera.z_far(),
+                    }
+                }),
+            });
+            if let Some(physical) = camera.physical() {
+                writer.cameras[camera_index]["extras"] = json!(physical);
+            }
+            // glTF camera nodes hold the camera to world transform
+            let node = json!({
+                "name": "camera",
+                "camera": camera_index,
+                "matrix": camera.view_matrix().inverse().to_cols_array(),
+            });
+            scene_nodes.push(writer.push_node(node));
+        }
+
+        writer.finish(scene_nodes)
+    }
+}
+
+impl Scene {
+    pub fn export(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        GltfExport::from_scene(self).write(path)
+    }
+}
+
+#[derive(Default)]
+struct GltfWriter {
+    bin: Vec<u8>,
+    accessors: Vec<Value>,
+    buffer_views: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    skins: Vec<Value>,
+    nodes: Vec<Value>,
+    cameras: Vec<Value>,
+    texture_copies: Vec<(PathBuf, PathBuf)>,
+}
+
+impl GltfWriter {
+    fn push_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // all accessors we write are 4 byte aligned
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn push_accessor<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        component_type: u32,
+        accessor_type: &str,
+        target: Option<u32>,
+    ) -> usize {
+        let view = self.push_view(bytemuck::cast_slice(data), target);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": component_type,
+            "count": data.len(),
+            "type": accessor_type,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn push_mesh(&mut self, mesh: &Mesh, skin: Option<&Skin>) -> usize {
+        let whole_mesh = [PrimitiveSection {
+            index: 0,
+            vertices: BufferPart {
+                offset: 0,
+                element_count: mesh.vertices.len(),
+            },
+            indices: if mesh.indices.is_empty() {
+                None
+            } else {
+                Some(BufferPart {
+                    offset: 0,
+                    element_count: mesh.indices.len(),
+                })
+            },
+            material_index: None,
+            bounds: Bounds::default(),
+        }];
+        let sections = if mesh.primitive_sections.is_empty() {
+            &whole_mesh[..]
+        } else {
+            &mesh.primitive_sections[..]
+        };
+        let influences = skin.map(|skin| vertex_influences(skin, mesh.vertices.len()));
+
+        let primitives = sections.iter().map(|section| {
+            let range = section.vertices.offset..section.vertices.offset + section.vertices.element_count;
+            let vertices = &mesh.vertices[range.clone()];
+
+            let positions = vertices.iter().map(|v| v.pos.xyz().to_array()).collect::<Vec<_>>();
+            let (min, max) = positions.iter().fold(
+                (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+                |(min, max), p| (min.min(Vec3::from(*p)), max.max(Vec3::from(*p))),
+            );
+            let normals = vertices.iter().map(|v| {
+                let n = v.normal.xyz();
+                if n.length_squared() > 0.0 { n.normalize().to_array() } else { [0.0, 1.0, 0.0] }
+            }).collect::<Vec<_>>();
+            let uvs = vertices.iter().map(|v| v.uv.xy().to_array()).collect::<Vec<_>>();
+            let colors = vertices.iter().map(|v| v.color.to_array()).collect::<Vec<_>>();
+
+            let position = self.push_accessor(&positions, FLOAT, "VEC3", Some(ARRAY_BUFFER));
+            self.accessors[position]["min"] = json!(min.to_array());
+            self.accessors[position]["max"] = json!(max.to_array());
+            let mut attributes = json!({
+                "POSITION": position,
+                "NORMAL": self.push_accessor(&normals, FLOAT, "VEC3", Some(ARRAY_BUFFER)),
+                "TEXCOORD_0": self.push_accessor(&uvs, FLOAT, "VEC2", Some(ARRAY_BUFFER)),
+                "COLOR_0": self.push_accessor(&colors, FLOAT, "VEC4", Some(ARRAY_BUFFER)),
+            });
+            if let Some(influences) = &influences {
+                let (joints, weights): (Vec<[u16; 4]>, Vec<[f32; 4]>) = influences[range].iter().cloned().unzip();
+                attributes["JOINTS_0"] = json!(self.push_accessor(&joints, UNSIGNED_SHORT, "VEC4", Some(ARRAY_BUFFER)));
+                attributes["WEIGHTS_0"] = json!(self.push_accessor(&weights, FLOAT, "VEC4", Some(ARRAY_BUFFER)));
+            }
+
+            let mut primitive = json!({
+                "attributes": attributes,
+                "mode": TRIANGLES,
+            });
+            if let Some(indices) = section.indices {
+                let indices = &mesh.indices[indices.offset..indices.offset + indices.element_count];
+                primitive["indices"] = json!(self.push_accessor(indices, UNSIGNED_INT, "SCALAR", Some(ELEMENT_ARRAY_BUFFER)));
+            }
+            if let Some(material) = section.material_index {
+                primitive["material"] = json!(material);
+            }
+            primitive
+        }).collect::<Vec<_>>();
+
+        self.meshes.push(json!({
+            "name": mesh.name,
+            "primitives": primitives,
+        }));
+        self.meshes.len() - 1
+    }
+
+    fn push_material(&mut self, material: &MaterialInfo) {
+        let mut pbr = json!({
+            "baseColorFactor": [material.color.x, material.color.y, material.color.z, 1.0],
+            "metallicFactor": material.metallic_factor,
+            "roughnessFactor": material.roughness_factor,
+        });
+        if material.albedo >= 0 {
+            pbr["baseColorTexture"] = json!({ "index": material.albedo });
+        }
+        let mut gltf_material = json!({
+            "pbrMetallicRoughness": pbr,
+            "emissiveFactor": material.emissive_factor.clamp(Vec3::ZERO, Vec3::ONE).to_array(),
+        });
+        if material.normal >= 0 {
+            gltf_material["normalTexture"] = json!({
+                "index": material.normal,
+                "scale": material.normal_factor,
+            });
+        }
+        self.materials.push(gltf_material);
+    }
+
+    /// Textures below `base_dir` are referenced in place, others get copied next to the document
+    /// so the export doesn't depend on absolute paths.
+    fn push_texture(&mut self, path: &Path, base_dir: &Path) {
+        let uri = match path.strip_prefix(base_dir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => {
+                // textures from different folders may share a file name
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let copy = PathBuf::from(format!("{}_{}", self.images.len(), name));
+                self.texture_copies.push((path.to_path_buf(), copy.clone()));
+                copy
+            }
+        };
+        let uri = uri.to_string_lossy().replace('\\', "/");
+        self.images.push(json!({ "uri": urlencoding::encode(&uri).replace("%2F", "/") }));
+        self.textures.push(json!({
+            "sampler": 0,
+            "source": self.images.len() - 1,
+        }));
+    }
+
+    /// Writes the rig as a node hierarchy and returns the index of the glTF skin.
+    /// Returns `None` if a skin joint can't be found in the rig.
+    fn push_skin(
+        &mut self,
+        skin: &Skin,
+        rig: &RigV1<Mat4, BoneV1<Mat4>>,
+        scene_nodes: &mut Vec<usize>,
+    ) -> Option<usize> {
+        let mut joint_names = skin.joint_id_map.iter().collect::<Vec<_>>();
+        joint_names.sort_by_key(|(_, index)| **index);
+        let joint_bones = joint_names
+            .iter()
+            .map(|(name, _)| {
+                rig.get_bone_by_id(name)
+                    .or_else(|| rig.get_bone(name))
+                    .map(|bone| bone.get_index())
+            })
+            .collect::<Option<Vec<usize>>>();
+        let joint_bones = match joint_bones {
+            Some(joint_bones) => joint_bones,
+            None => {
+                log::warn!("skipping skin {}: joints don't match rig", skin.name);
+                return None;
+            }
+        };
+
+        // glTF composes parent * local, so we re-derive local transforms from the rig's globals
+        let globals = (0..rig.bones.len()).map(|i| rig.local_to_global(i)).collect::<Vec<_>>();
+        let first_node = self.nodes.len();
+        for (i, bone) in rig.bones.iter().enumerate() {
+            let local = match bone.get_parent() {
+                Some(parent) => globals[parent].inverse() * globals[i],
+                None => globals[i],
+            };
+            let children = rig
+                .bones
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| child.get_parent() == Some(i))
+                .map(|(c, _)| first_node + c)
+                .collect::<Vec<_>>();
+            let mut node = json!({
+                "name": bone.get_name(),
+                "matrix": local.to_cols_array(),
+            });
+            if !children.is_empty() {
+                node["children"] = json!(children);
+            }
+            self.push_node(node);
+        }
+        scene_nodes.extend(
+            rig.bones
+                .iter()
+                .filter(|bone| bone.get_parent().is_none())
+                .map(|bone| first_node + bone.get_index()),
+        );
+
+        let inverse_bind_matrices = joint_bones
+            .iter()
+            .enumerate()
+            .map(|(joint, bone)| {
+                skin.inverse_bind_matrices
+                    .get(joint)
+                    .cloned()
+                    .unwrap_or_else(|| globals[*bone].inverse())
+                    .to_cols_array()
+            })
+            .collect::<Vec<_>>();
+        let inverse_bind_matrices = self.push_accessor(&inverse_bind_matrices, FLOAT, "MAT4", None);
+
+        self.skins.push(json!({
+            "name": skin.name,
+            "inverseBindMatrices": inverse_bind_matrices,
+            "joints": joint_bones.iter().map(|bone| first_node + bone).collect::<Vec<_>>(),
+        }));
+        Some(self.skins.len() - 1)
+    }
+
+    fn finish(self, scene_nodes: Vec<usize>) -> GltfDocument {
+        let mut bin = self.bin;
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": "bolt" },
+            "scene": 0,
+            "scenes": [{ "nodes": scene_nodes }],
+            "nodes": self.nodes,
+        });
+        let mut set = |key: &str, values: Vec<Value>| {
+            if !values.is_empty() {
+                document[key] = Value::Array(values);
+            }
+        };
+        set("accessors", self.accessors);
+        set("bufferViews", self.buffer_views);
+        set("meshes", self.meshes);
+        set("materials", self.materials);
+        set("skins", self.skins);
+        set("cameras", self.cameras);
+        if !self.textures.is_empty() {
+            set("images", self.images);
+            set("textures", self.textures);
+            set("samplers", vec![json!({
+                "magFilter": LINEAR,
+                "minFilter": LINEAR_MIPMAP_LINEAR,
+                "wrapS": REPEAT,
+                "wrapT": REPEAT,
+            })]);
+        }
+        if !bin.is_empty() {
+            document["buffers"] = json!([{ "byteLength": bin.len() }]);
+        }
+        GltfDocument { json: document, bin, texture_copies: self.texture_copies }
+    }
+}
+
+/// Gathers the four strongest joint influences per vertex and renormalizes them.
+fn vertex_influences(skin: &Skin, vertex_count: usize) -> Vec<([u16; 4], [f32; 4])> {
+    let mut per_vertex: HashMap<u32, Vec<(u32, f32)>> = HashMap::new();
+    for joint in &skin.joints {
+        per_vertex.entry(joint.vertex_id).or_default().push((joint.joint_id, joint.weight));
+    }
+    (0..vertex_count as u32).map(|vertex_id| {
+        let mut joints = [0u16; 4];
+        let mut weights = [0f32; 4];
+        if let Some(influences) = per_vertex.get_mut(&vertex_id) {
+            influences.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let total: f32 = influences.iter().take(4).map(|(_, w)| w).sum();
+            for (i, (joint, weight)) in influences.iter().take(4).enumerate() {
+                joints[i] = *joint as u16;
+                weights[i] = if total > 0.0 { weight / total } else { 0.0 };
+            }
+        }
+        (joints, weights)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::skin::SkinJoint;
+    use crate::scene::daz::format::test_rig;
+    use crate::scene::{camera_from_gltf, meshes_from_gltf, ModelVertex, PhysicalCamera};
+
+    fn quad() -> Mesh {
+        let vertices = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+            .iter()
+            .map(|[x, y]| ModelVertex {
+                pos: glam::vec4(*x, *y, 0.0, 1.0),
+                normal: glam::vec4(0.0, 0.0, 1.0, 0.0),
+                uv: glam::vec4(*x, *y, 0.0, 0.0),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let indices = vec![0, 1, 2, 0, 2, 3];
+        let sections = vec![PrimitiveSection {
+            index: 0,
+            vertices: BufferPart { offset: 0, element_count: vertices.len() },
+            indices: Some(BufferPart { offset: 0, element_count: indices.len() }),
+            material_index: Some(0),
+            bounds: Bounds::default(),
+        }];
+        Mesh::new("quad".to_string(), vertices, indices, Mat4::IDENTITY, sections)
+    }
+
+    #[test]
+    fn round_trip_glb() {
+        let mesh = quad();
+        let material = MaterialInfo {
+            metallic_factor: 0.25,
+            roughness_factor: 0.75,
+            ..Default::default()
+        };
+        let mut camera = Camera::new(glam::vec2(1280.0, 720.0));
+        camera.look_at(glam::vec3(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        camera.set_physical(Some(PhysicalCamera { focal_length: 85.0, f_stop: Some(1.4), ..Default::default() }));
+
+        let rig = test_rig(&[("root", None, Mat4::IDENTITY), ("tip", Some(0), Mat4::from_translation(Vec3::Y))]);
+        let skin = Skin {
+            name: "quad_skin".to_string(),
+            transforms: Vec::new(),
+            global_bone_transforms: Vec::new(),
+            inverse_bind_matrices: Vec::new(),
+            joints: (0..4).map(|v| SkinJoint { joint_id: v / 2, vertex_id: v, weight: 0.5 }).collect(),
+            joint_id_map: [("root".to_string(), 0), ("tip".to_string(), 1)].into_iter().collect(),
+            joint_bones: Vec::new(),
+        };
+
+        let meshes = [mesh];
+        let path = std::env::temp_dir().join("bolt_round_trip.glb");
+        GltfExport::default()
+            .meshes(&meshes)
+            .materials(&[material])
+            .skins(std::slice::from_ref(&skin))
+            .rigs(std::slice::from_ref(&rig))
+            .camera(Some(&camera))
+            .write(&path)
+            .unwrap();
+
+        let (document, buffers, _) = gltf::import(&path).unwrap();
+        let imported = meshes_from_gltf(&document, &buffers);
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].indices, meshes[0].indices);
+        for (a, b) in imported[0].vertices.iter().zip(meshes[0].vertices.iter()) {
+            assert_eq!(a.pos, b.pos);
+            assert_eq!(a.uv, b.uv);
+        }
+        assert_eq!(imported[0].primitive_sections[0].material_index, Some(0));
+
+        let imported_material: MaterialInfo = document.materials().next().unwrap().into();
+        assert_eq!(imported_material.metallic_factor, 0.25);
+        assert_eq!(imported_material.roughness_factor, 0.75);
+
+        let imported_camera = camera_from_gltf(&document).unwrap();
+        assert!(imported_camera.position().distance(glam::vec3(0.0, 0.0, 5.0)) < 1e-4);
+        assert!((imported_camera.vfov() - camera.vfov()).abs() < 1e-3);
+        assert_eq!(imported_camera.physical(), camera.physical());
+
+        let gltf_skin = document.skins().next().unwrap();
+        assert_eq!(gltf_skin.joints().map(|j| j.name().unwrap().to_string()).collect::<Vec<_>>(), vec!["root", "tip"]);
+        let primitive = document.meshes().next().unwrap().primitives().next().unwrap();
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let weights = reader.read_weights(0).unwrap().into_f32().collect::<Vec<_>>();
+        assert_eq!(weights[0], [1.0, 0.0, 0.0, 0.0]);
+        let joints = reader.read_joints(0).unwrap().into_u16().collect::<Vec<_>>();
+        assert_eq!(joints[3][0], 1);
+    }
+
+    #[test]
+    fn copies_outside_textures() {
+        let dir = std::env::temp_dir().join("bolt_export_textures");
+        let textures = dir.join("textures");
+        let out = dir.join("out");
+        fs::create_dir_all(&textures).unwrap();
+        fs::create_dir_all(out.join("maps")).unwrap();
+        fs::write(textures.join("skin.png"), b"png").unwrap();
+        fs::write(out.join("maps/eyes.png"), b"png").unwrap();
+
+        let texture_paths = [textures.join("skin.png"), out.join("maps/eyes.png")];
+        let path = out.join("scene.gltf");
+        GltfExport::default().texture_paths(&texture_paths).write(&path).unwrap();
+
+        let document: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(document["images"][0]["uri"], "0_skin.png");
+        assert_eq!(document["images"][1]["uri"], "maps/eyes.png");
+        assert_eq!(fs::read(out.join("0_skin.png")).unwrap(), b"png");
+    }
+}
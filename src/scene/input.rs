@@ -0,0 +1,362 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ModifiersState, MouseButton, VirtualKeyCode};
+
+use super::CameraMode;
+
+/// What the camera modes respond to, independent of the keys and buttons that trigger it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CameraAction {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+    Jump,
+    /// Drags, active while their trigger is held.
+    Orbit,
+    Pan,
+    Dolly,
+    Look,
+    Rotate,
+    /// Scrolling.
+    Zoom,
+}
+
+impl CameraAction {
+    pub fn is_drag(&self) -> bool {
+        matches!(self, CameraAction::Orbit | CameraAction::Pan | CameraAction::Dolly | CameraAction::Look | CameraAction::Rotate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Scroll,
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    UnknownTrigger(String),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for InputError {}
+
+macro_rules! key_name {
+    ($key:ident) => { stringify!($key) };
+    ($key:ident $name:literal) => { $name };
+}
+
+/// Every `VirtualKeyCode` with its name, the match in `key_name` fails to compile when winit
+/// adds a key that isn't listed.
+macro_rules! key_names {
+    ($($key:ident $(= $name:literal)?),* $(,)?) => {
+        fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(key_name!($key $($name)?) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+
+        fn key_name(key: VirtualKeyCode) -> &'static str {
+            match key {
+                $(VirtualKeyCode::$key => key_name!($key $($name)?),)*
+            }
+        }
+
+        #[cfg(test)]
+        const ALL_KEYS: &[VirtualKeyCode] = &[$(VirtualKeyCode::$key),*];
+    };
+}
+
+key_names!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    F13, F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadComma,
+    NumpadEnter, NumpadEquals, Numlock,
+    Up, Down, Left, Right, PageUp, PageDown, Home, End, Insert, Delete,
+    Space, Return, Escape, Tab, Back, Compose, Caret, Capital,
+    // "Scroll" is the mouse wheel
+    Snapshot, Scroll = "ScrollLock", Pause, Sysrq,
+    LShift, RShift, LControl, RControl, LAlt, RAlt, LWin, RWin, Apps,
+    Comma, Period, Semicolon, Colon, Minus, Equals, Plus, Asterisk, Slash, Backslash,
+    Apostrophe, Grave, At, Underline, LBracket, RBracket,
+    AbntC1, AbntC2, Ax, Convert, NoConvert, Kana, Kanji, Yen, OEM102, Unlabeled,
+    Calculator, Mail, MediaSelect, MediaStop, Mute, MyComputer, NavigateForward,
+    NavigateBackward, NextTrack, PlayPause, PrevTrack, Power, Sleep, Stop, Wake,
+    VolumeDown, VolumeUp, WebBack, WebFavorites, WebForward, WebHome, WebRefresh, WebSearch,
+    WebStop, Copy, Paste, Cut,
+);
+
+/// Written as the `VirtualKeyCode` name (`ScrollLock` for `Scroll`), `MouseLeft`/`MouseRight`/
+/// `MouseMiddle`/`MouseN` or `Scroll`.
+impl TryFrom<String> for Trigger {
+    type Error = InputError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let trigger = match name.as_str() {
+            "Scroll" => Some(Trigger::Scroll),
+            "MouseLeft" => Some(Trigger::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Trigger::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Trigger::Mouse(MouseButton::Middle)),
+            other => match other.strip_prefix("Mouse").and_then(|n| n.parse().ok()) {
+                Some(n) => Some(Trigger::Mouse(MouseButton::Other(n))),
+                None => key_from_name(other).map(Trigger::Key),
+            },
+        };
+        trigger.ok_or(InputError::UnknownTrigger(name))
+    }
+}
+
+impl From<Trigger> for String {
+    fn from(trigger: Trigger) -> Self {
+        match trigger {
+            Trigger::Scroll => "Scroll".to_string(),
+            Trigger::Mouse(MouseButton::Left) => "MouseLeft".to_string(),
+            Trigger::Mouse(MouseButton::Right) => "MouseRight".to_string(),
+            Trigger::Mouse(MouseButton::Middle) => "MouseMiddle".to_string(),
+            Trigger::Mouse(MouseButton::Other(n)) => format!("Mouse{}", n),
+            Trigger::Key(key) => key_name(key).to_string(),
+        }
+    }
+}
+
+impl Serialize for Trigger {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Trigger {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Trigger::try_from(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Modifiers a binding needs, the command key counts as ctrl.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, ctrl: false, alt: false };
+    pub const SHIFT: Modifiers = Modifiers { shift: true, ctrl: false, alt: false };
+
+    fn count(&self) -> usize {
+        self.shift as usize + self.ctrl as usize + self.alt as usize
+    }
+
+    /// Every modifier of `self` is held in `held`.
+    fn held_in(&self, held: &Modifiers) -> bool {
+        (!self.shift || held.shift) && (!self.ctrl || held.ctrl) && (!self.alt || held.alt)
+    }
+}
+
+impl From<ModifiersState> for Modifiers {
+    fn from(m: ModifiersState) -> Self {
+        Modifiers { shift: m.shift(), ctrl: m.ctrl() || m.logo(), alt: m.alt() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub action: CameraAction,
+    pub trigger: Trigger,
+    #[serde(default, flatten)]
+    pub modifiers: Modifiers,
+    /// Modes the binding is used in, all of them when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modes: Vec<CameraMode>,
+}
+
+impl Binding {
+    pub fn new(action: CameraAction, trigger: Trigger) -> Self {
+        Binding { action, trigger, modifiers: Modifiers::NONE, modes: Vec::new() }
+    }
+
+    pub fn modifiers(mut self, modifiers: Modifiers) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    pub fn modes(mut self, modes: &[CameraMode]) -> Self {
+        self.modes = modes.to_vec();
+        self
+    }
+
+    fn applies(&self, mode: CameraMode) -> bool {
+        self.modes.is_empty() || self.modes.contains(&mode)
+    }
+}
+
+/// Held triggers and modifiers, fed from window events.
+#[derive(Debug, Clone, Default)]
+pub struct TriggerState {
+    pub down: HashSet<Trigger>,
+    pub modifiers: Modifiers,
+    /// Fraction of a scroll notch left over from pixel deltas.
+    pub scroll: f32,
+}
+
+impl TriggerState {
+    pub fn set(&mut self, trigger: Trigger, is_down: bool) {
+        if is_down {
+            self.down.insert(trigger);
+        } else {
+            self.down.remove(&trigger);
+        }
+    }
+}
+
+/// Maps triggers to camera actions. When several bindings of a trigger match the held
+/// modifiers, the one needing the most modifiers wins, so `Shift+MouseMiddle` can orbit while
+/// `MouseMiddle` alone pans.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputBindings {
+    pub bindings: Vec<Binding>,
+}
+
+impl Default for InputBindings {
+    /// WASD and QE to move, the mouse buttons like the modes had them before bindings.
+    fn default() -> Self {
+        use CameraAction::*;
+        use CameraMode::*;
+        let key = |action, key| Binding::new(action, Trigger::Key(key));
+        let mouse = |action, button| Binding::new(action, Trigger::Mouse(button));
+        let (left, middle, right) = (MouseButton::Left, MouseButton::Middle, MouseButton::Right);
+        InputBindings {
+            bindings: vec![
+                key(Forward, VirtualKeyCode::W),
+                key(Backward, VirtualKeyCode::S),
+                key(Left, VirtualKeyCode::A),
+                key(Right, VirtualKeyCode::D),
+                key(Up, VirtualKeyCode::Q),
+                key(Down, VirtualKeyCode::E),
+                key(Jump, VirtualKeyCode::Space),
+                mouse(Pan, middle).modes(&[Spherical, Fly, Examine, Trackball]),
+                mouse(Orbit, middle).modifiers(Modifiers::SHIFT).modes(&[Spherical, Fly]),
+                mouse(Look, right).modes(&[Spherical, Fly]),
+                mouse(Dolly, right).modifiers(Modifiers::SHIFT).modes(&[Spherical, Fly]),
+                mouse(Orbit, left).modes(&[Examine]),
+                mouse(Rotate, left).modes(&[Trackball]),
+                mouse(Dolly, right).modes(&[Examine, Trackball]),
+                mouse(Look, left).modes(&[Walk]),
+                mouse(Look, middle).modes(&[Walk]),
+                mouse(Look, right).modes(&[Walk]),
+                Binding::new(Zoom, Trigger::Scroll),
+            ],
+        }
+    }
+}
+
+impl InputBindings {
+    /// The binding `trigger` fires in `mode` with `modifiers` held.
+    pub fn binding(&self, mode: CameraMode, trigger: Trigger, modifiers: &Modifiers) -> Option<&Binding> {
+        self.bindings.iter()
+            .filter(|b| b.trigger == trigger && b.applies(mode) && b.modifiers.held_in(modifiers))
+            // ties go to the binding listed first
+            .fold(None, |best: Option<&Binding>, b| match best {
+                Some(best) if best.modifiers.count() >= b.modifiers.count() => Some(best),
+                _ => Some(b),
+            })
+    }
+
+    pub fn active<'a>(&'a self, mode: CameraMode, state: &'a TriggerState) -> impl Iterator<Item = CameraAction> + 'a {
+        state.down.iter().filter_map(move |t| self.binding(mode, *t, &state.modifiers)).map(|b| b.action)
+    }
+
+    pub fn is_active(&self, action: CameraAction, mode: CameraMode, state: &TriggerState) -> bool {
+        self.active(mode, state).any(|a| a == action)
+    }
+
+    /// The drag of the held trigger bound first, so the result doesn't depend on press order.
+    pub fn drag_action(&self, mode: CameraMode, state: &TriggerState) -> Option<CameraAction> {
+        let active = self.active(mode, state).filter(CameraAction::is_drag).collect::<HashSet<_>>();
+        self.bindings.iter().map(|b| b.action).find(|a| active.contains(a))
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, InputError> {
+        serde_json::from_str(json).map_err(InputError::Json)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, InputError> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(InputError::Io)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_names_round_trip() {
+        let mouse = [MouseButton::Left, MouseButton::Right, MouseButton::Middle, MouseButton::Other(4)];
+        let triggers = ALL_KEYS.iter().map(|key| Trigger::Key(*key))
+            .chain(mouse.into_iter().map(Trigger::Mouse))
+            .chain([Trigger::Scroll]);
+        for trigger in triggers {
+            assert_eq!(Trigger::try_from(String::from(trigger)).unwrap(), trigger);
+        }
+        assert_eq!(ALL_KEYS.iter().collect::<HashSet<_>>().len(), ALL_KEYS.len());
+        assert!(Trigger::try_from("NotAKey".to_string()).is_err());
+    }
+
+    #[test]
+    fn resolves_and_loads_bindings() {
+        let bindings = InputBindings::default();
+        let mut state = TriggerState::default();
+        state.set(Trigger::Mouse(MouseButton::Middle), true);
+        assert_eq!(bindings.drag_action(CameraMode::Spherical, &state), Some(CameraAction::Pan));
+        state.modifiers = Modifiers::SHIFT;
+        assert_eq!(bindings.drag_action(CameraMode::Spherical, &state), Some(CameraAction::Orbit));
+        assert_eq!(bindings.drag_action(CameraMode::Examine, &state), Some(CameraAction::Pan));
+        assert_eq!(bindings.drag_action(CameraMode::Walk, &state), Some(CameraAction::Look));
+
+        let loaded = InputBindings::from_json(&bindings.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, bindings);
+
+        // an AZERTY layout with a trackpad, pan on shift + left button
+        let azerty = InputBindings::from_json(r#"{ "bindings": [
+            { "action": "Forward", "trigger": "Z" },
+            { "action": "Left", "trigger": "Q" },
+            { "action": "Pan", "trigger": "MouseLeft", "shift": true, "modes": ["Examine"] },
+            { "action": "Orbit", "trigger": "MouseLeft", "modes": ["Examine"] },
+            { "action": "Zoom", "trigger": "Scroll", "ctrl": true }
+        ] }"#).unwrap();
+        let mut state = TriggerState::default();
+        state.set(Trigger::Key(VirtualKeyCode::Z), true);
+        state.set(Trigger::Mouse(MouseButton::Left), true);
+        assert!(azerty.is_active(CameraAction::Forward, CameraMode::Fly, &state));
+        assert_eq!(azerty.drag_action(CameraMode::Examine, &state), Some(CameraAction::Orbit));
+        state.modifiers.shift = true;
+        assert_eq!(azerty.drag_action(CameraMode::Examine, &state), Some(CameraAction::Pan));
+        assert!(azerty.binding(CameraMode::Fly, Trigger::Scroll, &Modifiers::NONE).is_none());
+
+        assert!(matches!(InputBindings::from_json(r#"{ "bindings": [{ "action": "Pan", "trigger": "Mouse Left" }] }"#), Err(InputError::Json(_))));
+    }
+}
@@ -1,5 +1,15 @@
 mod camera;
 pub use camera::*;
+mod camera_path;
+pub use camera_path::*;
+mod input;
+pub use input::*;
+mod physical_camera;
+pub use physical_camera::*;
+mod export;
+pub use export::*;
+mod cache;
+pub use cache::*;
 use glam::Mat4;
 use rayon::prelude::*;
 
@@ -10,6 +20,7 @@ use crate::resource::{mesh, material, skin::{Skin, VulkanSkin, Rig, RigParser},
 pub use mesh::*;
 pub use material::*;
 pub mod daz;
+pub mod bvh;
 
 use crate::{Buffer, BufferInfo, Context, Texture2d};
 
@@ -17,7 +28,7 @@ use gltf::{
     buffer::Buffer as GltfBuffer,
     mesh::{Reader, Semantic}, Texture,
 };
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use self::daz::format::{RigV1, BoneV1, DazRigParserV1};
@@ -32,6 +43,40 @@ pub struct Scene {
     pub material_buffer: Buffer,
     pub camera: Option<Camera>,
     pub textures: Vec<Texture2d>,
+    pub texture_paths: Vec<PathBuf>,
+    /// Files besides the main one whose contents ended up in the scene, e.g. glTF buffers.
+    pub dependencies: Vec<PathBuf>,
+}
+
+impl Scene {
+    /// World space bounds of all meshes.
+    pub fn bounds(&self) -> Bounds {
+        self.meshes.iter().fold(Bounds::default(), |bounds, mesh| bounds.union(&mesh.bounds()))
+    }
+
+    /// `(mesh, section)` indices of the primitive sections inside `frustum`.
+    pub fn visible_sections(&self, frustum: &Frustum) -> Vec<(usize, usize)> {
+        self.meshes.iter().enumerate().flat_map(|(m, mesh)| {
+            mesh.primitive_sections.iter().enumerate()
+                .filter(|(_, section)| frustum.intersects(&section.bounds.transform(&mesh.transform)))
+                .map(move |(s, _)| (m, s))
+        }).collect()
+    }
+
+    /// The scene's own camera, or one that frames the whole scene.
+    pub fn camera_or_framed(&self, window_size: glam::Vec2) -> Camera {
+        match self.camera {
+            Some(mut camera) => {
+                camera.set_window_size(window_size);
+                camera
+            }
+            None => {
+                let mut camera = Camera::new(window_size);
+                camera.frame_bounds(&self.bounds());
+                camera
+            }
+        }
+    }
 }
 
 fn find_mesh(node: &gltf::Node, transforms: &mut Vec<glam::Mat4>, mesh_index: usize) -> bool {
@@ -67,8 +112,8 @@ fn calc_mesh_global_transform(gltf: &gltf::Document, mesh_index: usize) -> glam:
 }
 
 
-fn load_textures_par(document: &gltf::Document, filepath: &PathBuf, context: Arc<Context>) -> Vec<Texture2d> {
-    let paths = document.images().map(|im| {
+fn gltf_texture_paths(document: &gltf::Document, filepath: &PathBuf) -> Vec<PathBuf> {
+    document.images().map(|im| {
         let source = im.source();
         match source {
             gltf::image::Source::View { view, mime_type } => {
@@ -77,13 +122,27 @@ fn load_textures_par(document: &gltf::Document, filepath: &PathBuf, context: Arc
             }
             gltf::image::Source::Uri { uri, mime_type } => {
                 println!("mime_type: {:?}; uri: {:?}", mime_type, uri);
-                return filepath.as_path().parent().unwrap().join(PathBuf::from(uri).as_path()).as_os_str().to_os_string().into_string().unwrap() // PathBuf::from(uri).as_path();
+                return filepath.as_path().parent().unwrap().join(PathBuf::from(uri).as_path());
             }
         }
     })
-    .collect::<Vec<String>>();
+    .collect::<Vec<PathBuf>>()
+}
+
+/// External buffers, embedded `data:` uris aren't files.
+fn gltf_buffer_paths(document: &gltf::Document, filepath: &Path) -> Vec<PathBuf> {
+    let dir = filepath.parent().unwrap_or(Path::new(""));
+    document.buffers().filter_map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+            let uri = urlencoding::decode(uri).map(|uri| uri.into_owned()).unwrap_or_else(|_| uri.to_string());
+            Some(dir.join(uri))
+        }
+        _ => None,
+    }).collect()
+}
 
-    let mut images = crate::resource::image::load_images_par::<u8>(&paths);
+fn load_textures_par(paths: &[PathBuf], context: Arc<Context>) -> Vec<Texture2d> {
+    let mut images = crate::resource::image::load_images_par::<u8>(paths);
     println!("images loaded");
     images.iter_mut().map(|i| {
         i.set_format(ash::vk::Format::R8G8B8A8_UNORM);
@@ -109,37 +168,8 @@ fn load_textures_lin(document: &gltf::Document, filepath: &PathBuf, context: Arc
 }
 
 
-fn load_glts(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn std::error::Error>> {
+pub(crate) fn meshes_from_gltf(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Mesh> {
     let mut meshes = Vec::<Mesh>::new();
-    let res = gltf::import(filepath);
-    let (gltf, buffers, _) = match res {
-        Ok(s) => s,
-        Err(e) => {
-            return Err(Box::new(e));
-        }
-    };
-    println!("filepath: {:?}", filepath.as_path().parent());
-    
-    let textures = load_textures_par(&gltf, filepath, context.clone());
-    println!("textures loaded");
-    // println!("{:#?}", gltf);
-    let materials = gltf
-        .materials()
-        .map(|m| {
-            let mut mat: MaterialInfo = m.into();
-            // if mat.color == glam::Vec3::ZERO {
-            mat.color = glam::Vec3::splat(1.0);
-            // mat.opacity_factor = 0.0;
-            // }
-            mat
-        })
-        .collect::<Vec<MaterialInfo>>();
-    let material_buffer = Buffer::from_data(
-        context.clone(),
-        BufferInfo::default().usage_storage().gpu_only(),
-        &materials,
-    );
-
     for mesh in gltf.meshes() {
         let mut mesh_indices = Vec::<u32>::new();
         let mut mesh_vertices = Vec::<ModelVertex>::new();
@@ -181,6 +211,7 @@ fn load_glts(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
                 },
                 indices: None,
                 material_index: primitive.material().index(),
+                bounds: Bounds::default(),
             });
             // println!("  Vertices {:?}", (offset, mesh_vertices.len() - offset));
 
@@ -212,33 +243,71 @@ fn load_glts(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
         meshes.push(mesh);
     }
 
-    let mut camera = None;
-    for gltf_camera in gltf.cameras() {
-        match gltf_camera.projection() {
-            gltf::camera::Projection::Orthographic(_) => {}
-            gltf::camera::Projection::Perspective(persp) => {
-                for node in gltf.nodes() {
-                    let found = match node.camera() {
-                        Some(node_camera) => node_camera.index() == gltf_camera.index(),
-                        None => false,
-                    };
-                    if found {
-                        let view_matrix =
-                            glam::Mat4::from_cols_array_2d(&node.transform().matrix());
-                        camera = Some(Camera::from_view(
-                            view_matrix,
-                            persp.yfov(),
-                            persp.znear(),
-                            persp.zfar().unwrap_or(100.0),
-                        ));
-                        break;
-                    }
-                }
-            }
+    meshes
+}
+
+pub(crate) fn camera_from_gltf(gltf: &gltf::Document) -> Option<Camera> {
+    //Support for the first (default) camera only
+    let gltf_camera = gltf.cameras().next()?;
+    let node = gltf.nodes().find(|node| match node.camera() {
+        Some(node_camera) => node_camera.index() == gltf_camera.index(),
+        None => false,
+    })?;
+    // the node holds the camera to world transform
+    let view_matrix = glam::Mat4::from_cols_array_2d(&node.transform().matrix()).inverse();
+    let mut camera = match gltf_camera.projection() {
+        gltf::camera::Projection::Orthographic(ortho) => {
+            let mut camera = Camera::from_view(view_matrix, 35.0, ortho.znear(), ortho.zfar());
+            camera.set_projection(Projection::Orthographic { ymag: ortho.ymag() });
+            camera
         }
-        //Support for the first (default) camera only
-        break;
+        gltf::camera::Projection::Perspective(persp) => Camera::from_view(
+            view_matrix,
+            persp.yfov().to_degrees(),
+            persp.znear(),
+            persp.zfar().unwrap_or(100.0),
+        ),
+    };
+    let physical = PhysicalCamera::from_gltf(&gltf_camera);
+    if physical.is_some() {
+        camera.set_physical(physical);
     }
+    Some(camera)
+}
+
+fn load_glts(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn std::error::Error>> {
+    let res = gltf::import(filepath);
+    let (gltf, buffers, _) = match res {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(Box::new(e));
+        }
+    };
+    println!("filepath: {:?}", filepath.as_path().parent());
+    
+    let texture_paths = gltf_texture_paths(&gltf, filepath);
+    let textures = load_textures_par(&texture_paths, context.clone());
+    println!("textures loaded");
+    // println!("{:#?}", gltf);
+    let materials = gltf
+        .materials()
+        .map(|m| {
+            let mut mat: MaterialInfo = m.into();
+            // if mat.color == glam::Vec3::ZERO {
+            mat.color = glam::Vec3::splat(1.0);
+            // mat.opacity_factor = 0.0;
+            // }
+            mat
+        })
+        .collect::<Vec<MaterialInfo>>();
+    let material_buffer = Buffer::from_data(
+        context.clone(),
+        BufferInfo::default().usage_storage().gpu_only(),
+        &materials,
+    );
+
+    let meshes = meshes_from_gltf(&gltf, &buffers);
+    let camera = camera_from_gltf(&gltf);
 
     let vulkan_meshes: Vec<Box<VulkanMesh>> = meshes.iter().map(|mesh| Box::new(mesh.to_vulkan_mesh(context.clone()))).collect();
 
@@ -252,6 +321,8 @@ fn load_glts(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
         material_buffer,
         camera,
         textures,
+        texture_paths,
+        dependencies: gltf_buffer_paths(&gltf, filepath),
     })
 }
 
@@ -269,26 +340,7 @@ fn load_daz(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
 
         // we need to go through the mesh by indices so we can be sure to only handle triangles
         // and not quads
-        let index_buffer = geo.polylist.values.iter().fold(Vec::new(), |mut acc, quad| {
-            // reorder
-            match quad.len() {
-                5 => {
-                    acc.push(quad[2].clone());
-                    acc.push(quad[3].clone());
-                    acc.push(quad[4].clone());
-                },
-                6 => {
-                    acc.push(quad[2].clone());
-                    acc.push(quad[3].clone());
-                    acc.push(quad[4].clone());
-                    acc.push(quad[2].clone());
-                    acc.push(quad[4].clone());
-                    acc.push(quad[5].clone());
-                }
-                _ => {}
-            };
-            acc
-        });
+        let (index_buffer, triangle_polys) = geo.triangulate();
 
         let vertices = geo.vertices.values.par_iter().map(|v| {
             ModelVertex {
@@ -348,8 +400,10 @@ fn load_daz(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
                 element_count: index_buffer.len() as usize,
             }),
             material_index: Some(0),
+            bounds: Bounds::default(),
         }];
         let mut mesh = Mesh::new(geo.name.clone(), vertices, index_buffer, glam::Mat4::IDENTITY, sections);
+        mesh.face_selections = geo.face_selections(&triangle_polys);
         
         let normals = mesh.vertex_iter()
                 .map(|vertex_id| mesh.vertex_normal(vertex_id))
@@ -390,14 +444,10 @@ fn load_daz(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
     let vulkan_meshes: Vec<Box<VulkanMesh>> = meshes.iter().map(|mesh| Box::new(mesh.to_vulkan_mesh(context.clone()))).collect();
 
     let mut skins: Vec<Skin> = dsf.skins();
-    skins.iter_mut().zip(rigs.iter()).for_each(|(skin, rig)| {
-        skin.inverse_bind_matrices = rig.get_bones().iter().map(|bone| {
-            bone.inverse_bind_matrix
-        }).collect();
-    });
-    let vulkan_skins = skins.iter().map(|skin| VulkanSkin::from_data(context.clone(), format!("vk_{}", skin.name), skin)).collect();
+    skins.iter_mut().zip(rigs.iter()).for_each(|(skin, rig)| skin.bind_to_rig(rig));
+    let vulkan_skins = vulkan_skins(context.clone(), &skins, &meshes);
 
-    Ok(Scene {
+    let mut scene = Scene {
         meshes: meshes,
         vulkan_meshes,
         skins,
@@ -405,12 +455,64 @@ fn load_daz(context: Arc<Context>, filepath: &PathBuf) -> Result<Scene, Box<dyn
         vulkan_skins,
         materials: materials,
         material_buffer: material_buffer,
-        camera: Some(Camera::new(glam::vec2(1280.0, 720.0))),
+        camera: None,
         textures: Vec::new(),
-    })
+        texture_paths: Vec::new(),
+        dependencies: Vec::new(),
+    };
+    let mut camera = Camera::new(glam::vec2(1280.0, 720.0));
+    camera.frame_bounds(&scene.bounds());
+    scene.camera = Some(camera);
+    Ok(scene)
+}
+
+/// Uploads each skin together with the bind pose of the mesh it deforms, `skins[i]` skins `meshes[i]`.
+fn vulkan_skins(context: Arc<Context>, skins: &[Skin], meshes: &[Mesh]) -> Vec<VulkanSkin> {
+    skins.iter().zip(meshes).map(|(skin, mesh)| {
+        VulkanSkin::from_data(context.clone(), format!("vk_{}", skin.name), skin, mesh)
+    }).collect()
+}
+
+fn load_cached(context: Arc<Context>, cache: &SceneCache) -> Scene {
+    let meshes = cache.meshes();
+    let materials = cache.materials();
+    let material_buffer = Buffer::from_data(
+        context.clone(),
+        BufferInfo::default().usage_storage().gpu_only(),
+        &materials,
+    );
+    let vulkan_meshes: Vec<Box<VulkanMesh>> = meshes.iter().map(|mesh| Box::new(mesh.to_vulkan_mesh(context.clone()))).collect();
+    let skins = cache.skins();
+    let vulkan_skins = vulkan_skins(context.clone(), &skins, &meshes);
+    let texture_paths = cache.texture_paths().to_vec();
+    let textures = if texture_paths.is_empty() {
+        Vec::new()
+    } else {
+        load_textures_par(&texture_paths, context.clone())
+    };
+
+    Scene {
+        meshes,
+        vulkan_meshes,
+        skins,
+        vulkan_skins,
+        rigs: cache.rigs(),
+        materials,
+        material_buffer,
+        camera: cache.camera(),
+        textures,
+        texture_paths,
+        dependencies: cache.dependencies(),
+    }
 }
 
+/// Loads a scene, reusing the processed scene cached next to the file (see `SceneCache::path_for`)
+/// while it is up to date.
 pub fn load_scene(context: Arc<Context>, filepath: &PathBuf) -> Scene {
+    load_scene_cached(context, filepath, &SceneCache::path_for(filepath))
+}
+
+fn load_scene_uncached(context: Arc<Context>, filepath: &PathBuf) -> Scene {
     // TODO: make this chain of responsibility
     let scene = match filepath.extension().unwrap().to_str().unwrap() {
         "gltf" => load_glts(context.clone(), filepath),
@@ -421,6 +523,21 @@ pub fn load_scene(context: Arc<Context>, filepath: &PathBuf) -> Scene {
     scene.unwrap()
 }
 
+/// Like `load_scene` with the cache at `cache_path`. The processed scene is reused while it is
+/// newer than the source and its dependencies, and written there otherwise.
+pub fn load_scene_cached(context: Arc<Context>, filepath: &PathBuf, cache_path: &Path) -> Scene {
+    match SceneCache::open_for(cache_path, filepath) {
+        Ok(cache) => return load_cached(context, &cache),
+        Err(e) if matches!(e.downcast_ref::<CacheError>(), Some(CacheError::Stale)) => {}
+        Err(e) => log::warn!("Ignoring scene cache {:?}: {}", cache_path, e),
+    }
+    let scene = load_scene_uncached(context, filepath);
+    if let Err(e) = SceneCacheWriter::from_scene(&scene).write(cache_path, filepath) {
+        log::warn!("Failed to write scene cache {:?}: {}", cache_path, e);
+    }
+    scene
+}
+
 fn read_indices<'a, 's, F>(reader: &Reader<'a, 's, F>) -> Option<Vec<u32>>
 where
     F: Clone + Fn(GltfBuffer<'a>) -> Option<&'s [u8]>,
@@ -0,0 +1,161 @@
+use glam::{vec4, Vec4};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Camera body and lens settings. Lengths of the lens and sensor are in millimeters,
+/// `focus_distance` is in scene units which are taken to be meters.
+/// Without an `f_stop` the lens is a pinhole, exposure is only metered once `f_stop`,
+/// `shutter_time` and `iso` are all known.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicalCamera {
+    pub focal_length: f32,
+    pub sensor_width: f32,
+    pub sensor_height: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f_stop: Option<f32>,
+    pub focus_distance: f32,
+    /// Seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutter_time: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso: Option<f32>,
+}
+
+impl Default for PhysicalCamera {
+    /// A 50mm pinhole on a full frame sensor, focused at 10m, with unit exposure.
+    fn default() -> Self {
+        Self {
+            focal_length: 50.0,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_stop: None,
+            focus_distance: 10.0,
+            shutter_time: None,
+            iso: None,
+        }
+    }
+}
+
+/// What the ray generation shaders need, `aperture_radius` of zero is a pinhole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinLens {
+    pub aperture_radius: f32,
+    pub focus_distance: f32,
+    pub exposure: f32,
+}
+
+impl Default for ThinLens {
+    fn default() -> Self {
+        Self {
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            exposure: 1.0,
+        }
+    }
+}
+
+impl ThinLens {
+    /// Matches `vec4 lens` in the scene uniforms of `pathtrace.rgen` and `spectral.rgen`.
+    pub fn as_vec4(&self) -> Vec4 {
+        vec4(self.aperture_radius, self.focus_distance, self.exposure, 0.0)
+    }
+}
+
+const FIELDS: [&str; 7] = ["focal_length", "sensor_width", "sensor_height", "f_stop", "focus_distance", "shutter_time", "iso"];
+
+impl PhysicalCamera {
+    /// Vertical field of view in degrees.
+    pub fn vfov(&self) -> f32 {
+        (2.0 * (0.5 * self.sensor_height / self.focal_length).atan()).to_degrees()
+    }
+
+    /// Picks the focal length that gives `vfov` degrees on this sensor.
+    pub fn set_vfov(&mut self, vfov: f32) {
+        self.focal_length = 0.5 * self.sensor_height / (0.5 * vfov.to_radians()).tan();
+    }
+
+    /// Lens radius in scene units, zero without an f-stop.
+    pub fn aperture_radius(&self) -> f32 {
+        self.f_stop.map_or(0.0, |f_stop| 0.5 * self.focal_length / f_stop * 1e-3)
+    }
+
+    /// Exposure value at ISO 100, None unless f-stop, shutter time and ISO are set.
+    pub fn ev100(&self) -> Option<f32> {
+        let (f_stop, shutter_time, iso) = (self.f_stop?, self.shutter_time?, self.iso?);
+        Some((f_stop * f_stop / shutter_time * 100.0 / iso).log2())
+    }
+
+    /// Scale from scene radiance to sensor values, using the saturation based sensitivity
+    /// with a 78% reference so that an EV100 of 0 maps 1.2 to 1. One without an EV100.
+    pub fn exposure(&self) -> f32 {
+        self.ev100().map_or(1.0, |ev100| 1.0 / (1.2 * ev100.exp2()))
+    }
+
+    pub fn lens(&self) -> ThinLens {
+        ThinLens {
+            aperture_radius: self.aperture_radius(),
+            focus_distance: self.focus_distance,
+            exposure: self.exposure(),
+        }
+    }
+
+    /// Reads the settings from glTF camera extras, fields that are missing keep their default.
+    /// None when the extras have none of the fields.
+    pub fn from_extras(extras: &Value) -> Option<Self> {
+        let object = extras.as_object()?;
+        if !FIELDS.iter().any(|field| object.contains_key(*field)) {
+            return None;
+        }
+        serde_json::from_value(extras.clone()).ok()
+    }
+
+    /// Settings from the extras of a camera of a loaded glTF document.
+    pub fn from_gltf(camera: &gltf::Camera) -> Option<Self> {
+        let extras = serde_json::from_str::<Value>(camera.extras().as_ref()?.get()).ok()?;
+        Self::from_extras(&extras)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lens_and_exposure() {
+        let mut camera = PhysicalCamera::default();
+        assert!((camera.vfov() - 26.99).abs() < 1e-2);
+        camera.set_vfov(40.0);
+        assert!((camera.vfov() - 40.0).abs() < 1e-4);
+
+        camera = PhysicalCamera { f_stop: Some(1.0), shutter_time: Some(1.0), iso: Some(100.0), focal_length: 50.0, ..Default::default() };
+        assert_eq!(camera.ev100(), Some(0.0));
+        assert!((camera.exposure() - 1.0 / 1.2).abs() < 1e-6);
+        // one stop less light halves the exposure
+        camera.iso = Some(50.0);
+        assert!((camera.exposure() - 0.5 / 1.2).abs() < 1e-6);
+        assert!((camera.lens().aperture_radius - 0.025).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reads_gltf_extras() {
+        let gltf = gltf::Gltf::from_slice(br#"{
+            "asset": { "version": "2.0" },
+            "cameras": [
+                { "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 } },
+                { "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 },
+                  "extras": { "focal_length": 85.0, "f_stop": 1.4, "name": "portrait" } }
+            ]
+        }"#).unwrap();
+        let cameras = gltf.cameras().collect::<Vec<_>>();
+        assert_eq!(PhysicalCamera::from_gltf(&cameras[0]), None);
+        let camera = PhysicalCamera::from_gltf(&cameras[1]).unwrap();
+        assert_eq!(camera.focal_length, 85.0);
+        assert_eq!(camera.f_stop, Some(1.4));
+        assert_eq!(camera.iso, None);
+        // an aperture alone defocuses but leaves the exposure alone
+        assert!(camera.lens().aperture_radius > 0.0);
+        assert_eq!(camera.lens().exposure, 1.0);
+        assert_eq!(PhysicalCamera::from_extras(&serde_json::json!({ "name": "portrait" })), None);
+    }
+}
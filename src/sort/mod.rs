@@ -3,6 +3,7 @@ use std::{path::PathBuf, sync::Arc};
 use crate::{
     Buffer, ComputePipeline, ComputePipelineInfo, Context, DescriptorSet, DescriptorSetLayout,
     DescriptorSetLayoutInfo, PipelineLayout, PipelineLayoutInfo, Resource, DescriptorSetInfo, BufferInfo,
+    Access, PassKind, RenderGraph, TransientImages,
 };
 use ash::vk::{self, CommandBuffer};
 
@@ -74,12 +75,13 @@ impl RadixSortInfo {
 
 
 pub struct RadixSort {
+    context: Arc<Context>,
     pipeline: ComputePipeline,
     info: RadixSortInfo,
     pub desc_sets: [DescriptorSet; 2],
     pub desc_set_layout: DescriptorSetLayout,
     pub pipeline_layout: PipelineLayout,
-    barriers: [vk::MemoryBarrier; 2],
+    transients: TransientImages,
     push_constants: PushConstants,
     push_constants_histograms: PushConstantsHistograms,
     pub m_buffer0: vk::Buffer, // array to sort (owned by caller)
@@ -151,17 +153,6 @@ impl RadixSort {
                 .comp(crate::util::find_asset("glsl/sort/multi_radixsort.comp").unwrap()),
         );
 
-        let barriers = [
-            vk::MemoryBarrier::builder()
-                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
-                .dst_access_mask(vk::AccessFlags::SHADER_READ)
-                .build(),
-            vk::MemoryBarrier::builder()
-                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
-                .dst_access_mask(vk::AccessFlags::SHADER_READ)
-                .build()
-        ];
-
         let buffer_info = BufferInfo::default()
         .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
         .gpu_only()
@@ -209,12 +200,13 @@ impl RadixSort {
         let g_num_blocks_per_workgroup = 32;
 
         Self {
+            context,
             pipeline,
             info,
             desc_sets: [desc_set1, desc_set2],
             desc_set_layout,
             pipeline_layout,
-            barriers,
+            transients: TransientImages::new(),
             push_constants: PushConstants {
                 g_num_elements,
                 g_shift: 0,
@@ -308,88 +300,69 @@ impl RadixSort {
     }
 
 
+    /// Sorts 8 bits per iteration, the barriers between the histogram and scatter passes come from
+    /// the render graph. The sorted keys and orderings are ready for compute shader reads afterwards.
     pub fn pass(&mut self, cmd: CommandBuffer, device: &ash::Device) {
-        (0..4).into_iter().for_each(|i| {
-            self.pass_impl(cmd, device, i);
-        });
-        assert!(self.push_constants.g_shift == 0);
-    }
-
-    fn pass_impl(&mut self, cmd: CommandBuffer, device: &ash::Device, index: i32) {
-        let sets = self.desc_set_for_iteration(index);
-        let descriptor_sets = [sets[0].handle(), sets[1].handle()];
+        let sets: Vec<[vk::DescriptorSet; 2]> = (0..4)
+            .map(|i| self.desc_set_for_iteration(i).map(|set| set.handle()))
+            .collect();
+        let pipelines = [self.pipeline.handles().unwrap()[0], self.pipeline.handles().unwrap()[1]];
+        let layout = self.pipeline_layout.handle();
         let i_size = self.info.global_invocation_size();
-        unsafe {
 
-            // histo pass
-            device.cmd_bind_pipeline(
-                cmd,
-                vk::PipelineBindPoint::COMPUTE,
-                self.pipeline.handles().unwrap()[0],
-            );
-            device.cmd_bind_descriptor_sets(
-                cmd,
-                vk::PipelineBindPoint::COMPUTE,
-                self.pipeline_layout.handle(),
-                0,
-                descriptor_sets.as_slice(),
-                &[],
-            );
-            device.cmd_push_constants(
-                cmd,
-                self.pipeline_layout.handle(),
-                vk::ShaderStageFlags::COMPUTE,
-                0,
-                &bytemuck::bytes_of(&self.push_constants_histograms)
-            );
-            device.cmd_dispatch(cmd, i_size.0, i_size.1, i_size.2);
-            device.cmd_pipeline_barrier(
-                cmd,
-                vk::PipelineStageFlags::COMPUTE_SHADER,
-                vk::PipelineStageFlags::COMPUTE_SHADER,
-                vk::DependencyFlags::empty(),
-                [self.barriers[0]].as_slice(),
-                &[],
-                &[],
-            );
+        let mut graph = RenderGraph::new();
+        let keys = [
+            graph.import_buffer("keys", self.m_buffer0, Some(Access::compute_read())),
+            graph.import_buffer("keys pong", self.m_buffer1.handle(), None),
+        ];
+        let histograms = graph.import_buffer("histograms", self.m_buffer_histograms.handle(), None);
+        let orderings = [
+            graph.import_buffer("orderings", self.m_ord0, Some(Access::compute_read())),
+            graph.import_buffer("orderings pong", self.m_ord1.handle(), None),
+        ];
+        for (i, sets) in sets.into_iter().enumerate() {
+            let (src, dst) = (i % 2, (i + 1) % 2);
+            let shift = 8 * i as u32;
+            let histogram_constants = PushConstantsHistograms { g_shift: shift, ..self.push_constants_histograms };
+            let constants = PushConstants { g_shift: shift, ..self.push_constants };
 
-            // sort pass
-            device.cmd_bind_pipeline(
-                cmd,
-                vk::PipelineBindPoint::COMPUTE,
-                self.pipeline.handles().unwrap()[1],
-            );
-            device.cmd_bind_descriptor_sets(
-                cmd,
-                vk::PipelineBindPoint::COMPUTE,
-                self.pipeline_layout.handle(),
-                0,
-                descriptor_sets.as_slice(),
-                &[],
-            );
-            device.cmd_push_constants(
-                cmd,
-                self.pipeline_layout.handle(),
-                vk::ShaderStageFlags::COMPUTE,
-                0,
-                &bytemuck::bytes_of(&self.push_constants)
-            );
-            device.cmd_dispatch(cmd, i_size.0, i_size.1, i_size.2);
-            device.cmd_pipeline_barrier(
-                cmd,
-                vk::PipelineStageFlags::COMPUTE_SHADER,
-                vk::PipelineStageFlags::COMPUTE_SHADER,
-                vk::DependencyFlags::empty(),
-                [self.barriers[1]].as_slice(),
-                &[],
-                &[],
-            );
+            graph.add_pass(&format!("histograms {}", i), PassKind::Compute)
+                .read(keys[src], Access::compute_read())
+                .read(histograms, Access::compute_read())
+                .write(histograms, Access::compute_write())
+                .record(move |cmd, _| {
+                    cmd_sort_dispatch(device, cmd, pipelines[0], layout, &sets, bytemuck::bytes_of(&histogram_constants), i_size);
+                });
+            // both orderings are bound, which one is read depends on the iteration
+            graph.add_pass(&format!("scatter {}", i), PassKind::Compute)
+                .read(keys[src], Access::compute_read())
+                .read(histograms, Access::compute_read())
+                .write(keys[dst], Access::compute_write())
+                .read(orderings[0], Access::compute_read())
+                .write(orderings[0], Access::compute_write())
+                .read(orderings[1], Access::compute_read())
+                .write(orderings[1], Access::compute_write())
+                .record(move |cmd, _| {
+                    cmd_sort_dispatch(device, cmd, pipelines[1], layout, &sets, bytemuck::bytes_of(&constants), i_size);
+                });
         }
+        graph.execute(&self.context, cmd, &mut self.transients).unwrap();
+    }
+}
 
-        // increment shift on push constants
-        self.push_constants.g_shift += 8;
-        self.push_constants_histograms.g_shift += 8;
-        self.push_constants.g_shift %= 32;
-        self.push_constants_histograms.g_shift %= 32;
+fn cmd_sort_dispatch(
+    device: &ash::Device,
+    cmd: CommandBuffer,
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    sets: &[vk::DescriptorSet],
+    constants: &[u8],
+    i_size: (u32, u32, u32),
+) {
+    unsafe {
+        device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::COMPUTE, layout, 0, sets, &[]);
+        device.cmd_push_constants(cmd, layout, vk::ShaderStageFlags::COMPUTE, 0, constants);
+        device.cmd_dispatch(cmd, i_size.0, i_size.1, i_size.2);
     }
 }